@@ -1,54 +1,680 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 use anyhow::{Result, Context};
 use console::style;
+use filetime::FileTime;
 use humansize::{format_size, DECIMAL};
+use serde::{Serialize, Deserialize};
+use walkdir::WalkDir;
 
-use crate::{FileInfo, DedupAction};
+use crate::{is_nul_padded_group, CancelToken, FileInfo, DedupAction, KeepStrategy};
+use crate::revert::{RevertKind, RevertLog};
+use crate::trash::trash_file;
+use crate::utils::{
+    are_same_file, copy_metadata, device_id, file_id, files_identical, generate_unique_filename, hash_file,
+    identical_ignoring_nul_padding, is_file_in_use, is_persistent_storage, is_reflink_supported, is_safe_path, shared_extents,
+    NameTemplate,
+};
+
+/// Order in which duplicate groups are processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupOrder {
+    /// Whatever order the scan produced
+    #[default]
+    Unordered,
+    /// Groups wasting the most space first
+    SizeDesc,
+}
+
+/// Configuration for actions performed on duplicate files
+#[derive(Debug, Clone, Default)]
+pub struct ActionConfig {
+    /// Resolve a symlinked keeper to its real file before linking duplicates to it
+    pub dereference_keeper: bool,
+    /// Keeper rules applied in turn, each narrowing the candidates; remaining ties go to the first found
+    pub keep: Vec<KeepStrategy>,
+    /// Order in which groups are processed
+    pub order: GroupOrder,
+    /// Stop starting new groups once this many bytes have been freed
+    pub stop_after: Option<u64>,
+    /// Only act on groups whose hash starts with this lowercase hex prefix
+    pub hash_prefix: Option<String>,
+    /// Skip duplicates that another process has open or locked
+    pub skip_in_use: bool,
+    /// Compare each duplicate byte for byte with the keeper before acting on it
+    pub verify: bool,
+    /// After each group, re-hash the keeper and check every processed duplicate
+    /// still leads to the same content
+    pub post_verify: bool,
+    /// Only delete a duplicate after verifying it byte for byte against a keeper
+    /// that is a separate file on persistent storage
+    pub safe: bool,
+    /// Action to try on a duplicate when the main one can't apply to it
+    /// (e.g. a hard link across filesystems)
+    pub fallback: Option<DedupAction>,
+    /// Leave groups of files smaller than this alone in destructive actions,
+    /// since each copy occupies a whole block anyway
+    pub min_block_size: Option<u64>,
+    /// File name template for moved or copied files; `_N` suffixes are used when unset
+    pub move_template: Option<NameTemplate>,
+    /// How many times to retry a transient I/O failure
+    pub retries: u32,
+    /// Carry timestamps, permissions and extended attributes over when a file is copied
+    /// (Copy action and cross-device moves)
+    pub preserve_metadata: bool,
+    /// Append each completed move to this manifest as it happens
+    pub move_manifest: Option<MoveManifest>,
+    /// Record each delete and move here so it can be undone, before it happens where possible
+    pub revert_log: Option<RevertLog>,
+    /// Where `DedupAction::Revert` finds copies of deleted files, under their original absolute paths
+    pub revert_backup_dir: Option<PathBuf>,
+    /// Give the keeper the newest modification time in its group once duplicates are processed
+    pub touch_keeper_newest: bool,
+    /// Move deleted files into this freedesktop.org trash directory instead of unlinking them
+    pub trash_dir: Option<PathBuf>,
+    /// Go ahead with a move or copy even if the target filesystem is too full for all of it
+    pub ignore_free_space: bool,
+    /// Remove source directories left empty by the Move action
+    pub delete_empty_after_move: bool,
+    /// Directories that were scanned; they are never removed and bound upward pruning
+    pub scan_roots: Vec<PathBuf>,
+    /// Write failed operations as JSON lines to this file
+    pub json_errors: Option<PathBuf>,
+    /// Stop before starting new operations once cancelled
+    pub cancel: CancelToken,
+}
+
+/// What a member of a duplicate group is on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemberKind {
+    Regular,
+    /// Symbolic link, with the canonical path it resolves to (`None` when dangling)
+    Symlink(Option<PathBuf>),
+    /// Another name for the same inode as the earlier member at this index
+    HardlinkOf(usize),
+}
+
+/// How the members of a duplicate group are linked to each other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupTopology {
+    pub members: Vec<MemberKind>,
+    /// Canonical path of each member, when it resolves
+    canonical: Vec<Option<PathBuf>>,
+}
+
+impl GroupTopology {
+    /// Index of a symlink member that resolves to member `index`
+    pub fn linked_from(&self, index: usize) -> Option<usize> {
+        let target = self.canonical[index].as_ref()?;
+        if matches!(self.members[index], MemberKind::Symlink(_)) {
+            return None;
+        }
+        self.members.iter().position(|member| {
+            matches!(member, MemberKind::Symlink(Some(resolved)) if resolved == target)
+        })
+    }
+
+    /// Whether two members already lead to the same data, through a hard link or a symlink
+    pub fn same_file(&self, a: usize, b: usize) -> bool {
+        let root = |i: usize| match self.members[i] {
+            MemberKind::HardlinkOf(first) => first,
+            _ => i,
+        };
+        if root(a) == root(b) {
+            return true;
+        }
+        match (&self.canonical[a], &self.canonical[b]) {
+            (Some(x), Some(y)) => x == y,
+            _ => false,
+        }
+    }
+}
+
+/// Classify each member of a group as a regular file, a symlink (and where it
+/// points) or a hard link alias of an earlier member
+pub fn classify_group(files: &[FileInfo]) -> GroupTopology {
+    let mut members = Vec::with_capacity(files.len());
+    let mut canonical = Vec::with_capacity(files.len());
+    let mut ids: Vec<Option<(u64, u64)>> = Vec::with_capacity(files.len());
+
+    for file in files {
+        let is_symlink = fs::symlink_metadata(&file.path)
+            .map(|metadata| metadata.file_type().is_symlink())
+            .unwrap_or(false);
+        let resolved = fs::canonicalize(&file.path).ok();
+
+        if is_symlink {
+            members.push(MemberKind::Symlink(resolved.clone()));
+            ids.push(None);
+        } else {
+            let id = file_id(&file.path);
+            let alias = id.and_then(|id| ids.iter().position(|other| *other == Some(id)));
+            members.push(match alias {
+                Some(first) => MemberKind::HardlinkOf(first),
+                None => MemberKind::Regular,
+            });
+            ids.push(id);
+        }
+        canonical.push(resolved);
+    }
+
+    GroupTopology { members, canonical }
+}
+
+/// One completed move, as recorded in a move manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveRecord {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub size: u64,
+    pub hash: String,
+}
+
+/// Append-only JSON Lines record of completed moves
+///
+/// Every record is flushed and synced before the next move starts, so after a
+/// crash the file lists exactly the moves that happened. Re-running the same
+/// command resumes the work: moved files are gone from the scanned tree, and
+/// new records are appended to the existing manifest.
+#[derive(Debug, Clone)]
+pub struct MoveManifest {
+    path: PathBuf,
+    file: Arc<Mutex<fs::File>>,
+}
+
+impl MoveManifest {
+    /// Open a manifest for appending, creating it if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open move manifest {}", path.display()))?;
+        Ok(Self { path: path.to_path_buf(), file: Arc::new(Mutex::new(file)) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn record(&self, record: &MoveRecord) -> Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.sync_data())
+            .with_context(|| format!("Failed to write move manifest {}", self.path.display()))
+    }
+
+    /// Read back the moves recorded in a manifest, in order
+    pub fn load(path: &Path) -> Result<Vec<MoveRecord>> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read move manifest {}", path.display()))?;
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| serde_json::from_str(line)
+                .with_context(|| format!("Invalid record on line {} of {}", i + 1, path.display())))
+            .collect()
+    }
+}
 
 /// Performs the specified action on duplicate files
 pub fn perform_action(
     duplicates: &[FileInfo],
     action: &DedupAction,
+    config: &ActionConfig,
     dry_run: bool,
 ) -> Result<ActionResult> {
     let mut result = ActionResult::new();
     
-    // Skip the first file (original) and process duplicates
-    for duplicate in duplicates.iter().skip(1) {
-        let action_result = match action {
-            DedupAction::List => {
-                // List action is handled in the main display function
+    let topology = classify_group(duplicates);
+    let padded = is_nul_padded_group(duplicates);
+    let keeper_index = select_keeper(duplicates, &config.keep);
+    let original = &duplicates[keeper_index];
+    let keeper = match action {
+        DedupAction::Hardlink | DedupAction::Symlink | DedupAction::Reflink | DedupAction::DedupInPlace => {
+            resolve_keeper(&original.path, config)?
+        }
+        _ => original.path.clone(),
+    };
+    
+    // Copy only needs one representative per group
+    if let DedupAction::Copy(target_dir) = action {
+        if !config.cancel.is_cancelled() {
+            result.add_operation(copy_file(original, target_dir, config, dry_run)?);
+        }
+        return Ok(result);
+    }
+    
+    // Skip the keeper and process duplicates
+    for (i, duplicate) in duplicates.iter().enumerate() {
+        if i == keeper_index {
+            continue;
+        }
+
+        if config.cancel.is_cancelled() {
+            break;
+        }
+
+        // Removing or replacing a file a symlink in the group points at would leave that link dangling
+        if let Some(link) = topology.linked_from(i) {
+            if matches!(action, DedupAction::List) {
                 continue;
             }
-            DedupAction::Delete => delete_file(&duplicate.path, dry_run)?,
-            DedupAction::Move(target_dir) => move_file(&duplicate.path, target_dir, dry_run)?,
-            DedupAction::Hardlink => create_hardlink(&duplicates[0].path, &duplicate.path, dry_run)?,
-            DedupAction::Symlink => create_symlink(&duplicates[0].path, &duplicate.path, dry_run)?,
-        };
+            let (error_msg, error_kind) = if link == keeper_index {
+                (format!("Keeper {} resolves to this file", original.path.display()), "keeper_alias")
+            } else {
+                (format!("Symlink {} points to this file", duplicates[link].path.display()), "link_target")
+            };
+            eprintln!("🔗 {}: {}, skipping", duplicate.path.display(), error_msg);
+            result.add_operation(FileOperation {
+                path: duplicate.path.clone(),
+                action: action_name(action).to_string(),
+                success: false,
+                error: Some(error_msg),
+                space_saved: 0,
+                attempts: 0,
+                error_kind: Some(error_kind),
+                os_error: None,
+            });
+            continue;
+        }
+
+        // Already a link to the keeper's data: nothing to free, and replacing it gains nothing
+        if topology.same_file(keeper_index, i) {
+            if !matches!(action, DedupAction::List) {
+                println!("⏭️  {}: already linked to keeper {}, skipping", duplicate.path.display(), original.path.display());
+            }
+            continue;
+        }
+        
+        if config.skip_in_use && !matches!(action, DedupAction::List) && is_file_in_use(&duplicate.path) {
+            eprintln!("⏭️  {}: file in use, skipping", duplicate.path.display());
+            result.add_operation(FileOperation {
+                path: duplicate.path.clone(),
+                action: action_name(action).to_string(),
+                success: false,
+                error: Some("File in use by another process".to_string()),
+                space_saved: 0,
+                attempts: 0,
+                error_kind: Some("in_use"),
+                os_error: None,
+            });
+            continue;
+        }
+
+        if config.verify && !matches!(action, DedupAction::List) {
+            let mismatch = match same_content(&original.path, &duplicate.path, padded) {
+                Ok(true) => None,
+                Ok(false) => Some((format!("Content differs from keeper {}", original.path.display()), "content_mismatch", None)),
+                Err(e) => Some((format!("Failed to verify against keeper: {}", e), error_kind_id(e.kind()), e.raw_os_error())),
+            };
+            if let Some((error_msg, error_kind, os_error)) = mismatch {
+                eprintln!("❌ {}: {}", duplicate.path.display(), error_msg);
+                result.add_operation(FileOperation {
+                    path: duplicate.path.clone(),
+                    action: action_name(action).to_string(),
+                    success: false,
+                    error: Some(error_msg),
+                    space_saved: 0,
+                    attempts: 0,
+                    error_kind: Some(error_kind),
+                    os_error,
+                });
+                continue;
+            }
+        }
+
+        if matches!(action, DedupAction::List) {
+            // List action is handled in the main display function
+            continue;
+        }
+
+        let mut action_result = apply_action(action, original, &keeper, duplicate, padded, config, dry_run)?;
+
+        // Only fall back when the primary action can't apply here, not on ordinary failures
+        if let Some(fallback) = &config.fallback {
+            if !action_result.success && matches!(action_result.error_kind, Some("crosses_devices" | "unsupported")) {
+                println!("↪️  {} not possible for {}, falling back to {}",
+                    action_name(action), duplicate.path.display(), action_name(fallback));
+                let keeper = match fallback {
+                    DedupAction::Hardlink | DedupAction::Symlink | DedupAction::Reflink | DedupAction::DedupInPlace => {
+                        resolve_keeper(&original.path, config)?
+                    }
+                    _ => original.path.clone(),
+                };
+                action_result = apply_action(fallback, original, &keeper, duplicate, padded, config, dry_run)?;
+            }
+        }
         
         result.add_operation(action_result);
     }
+
+    if config.touch_keeper_newest && result.operations.iter().any(|op| op.success) {
+        touch_keeper(&keeper, duplicates, dry_run);
+    }
     
     Ok(result)
 }
 
+/// Set the keeper's modification time to the newest among the group's members
+///
+/// Hard links share the keeper's inode, so they get the new time too. Failures are
+/// reported but don't fail the group, since its duplicates are already processed.
+fn touch_keeper(keeper: &Path, files: &[FileInfo], dry_run: bool) {
+    let Some(newest) = files.iter().max_by_key(|file| file.modified) else { return };
+    let current = fs::metadata(keeper).and_then(|metadata| metadata.modified());
+    if current.as_ref().is_ok_and(|current| *current >= newest.modified) {
+        return;
+    }
+
+    if dry_run {
+        println!("🕒 [DRY RUN] Would set mtime of {} to that of {}", keeper.display(), newest.path.display());
+        return;
+    }
+    match filetime::set_file_mtime(keeper, FileTime::from_system_time(newest.modified)) {
+        Ok(()) => println!("🕒 Set mtime of {} to that of {}", keeper.display(), newest.path.display()),
+        Err(e) => eprintln!("❌ Failed to set mtime of {}: {}", keeper.display(), e),
+    }
+}
+
+/// Run one action on a duplicate, applying safe mode to deletions
+fn apply_action(
+    action: &DedupAction,
+    original: &FileInfo,
+    keeper: &Path,
+    duplicate: &FileInfo,
+    padded: bool,
+    config: &ActionConfig,
+    dry_run: bool,
+) -> Result<FileOperation> {
+    if config.safe && matches!(action, DedupAction::Delete) {
+        if let Err(reason) = check_safe_delete(&original.path, &duplicate.path, padded) {
+            eprintln!("🛡️  {}: {}, skipping", duplicate.path.display(), reason);
+            return Ok(FileOperation {
+                path: duplicate.path.clone(),
+                action: action_name(action).to_string(),
+                success: false,
+                error: Some(format!("Safe mode: {}", reason)),
+                space_saved: 0,
+                attempts: 0,
+                error_kind: Some("safe_mode"),
+                os_error: None,
+            });
+        }
+    }
+
+    match action {
+        DedupAction::Delete => delete_file(&duplicate.path, config, dry_run),
+        DedupAction::Move(target_dir) => move_file(&duplicate.path, &duplicate.hash, target_dir, config, dry_run),
+        DedupAction::Hardlink => create_hardlink(keeper, &duplicate.path, config, dry_run),
+        DedupAction::Symlink => create_symlink(keeper, &duplicate.path, config, dry_run),
+        DedupAction::Reflink => create_reflink(keeper, &duplicate.path, config, dry_run),
+        DedupAction::DedupInPlace => dedupe_in_place(keeper, &duplicate.path, config, dry_run),
+        DedupAction::List | DedupAction::Copy(_) | DedupAction::Revert(_) => {
+            unreachable!("list, copy and revert are not per-file actions")
+        }
+    }
+}
+
+/// Remove directories that became empty once `moved` files left them, deepest first
+///
+/// Parents are pruned too as long as they stay strictly inside one of the
+/// scan roots. Returns the directories that were (or in a dry run would be) removed.
+pub fn remove_emptied_dirs(
+    moved: &[PathBuf],
+    scan_roots: &[PathBuf],
+    dry_run: bool,
+) -> Vec<PathBuf> {
+    let mut gone: HashSet<PathBuf> = moved.iter().cloned().collect();
+    let mut pending: BinaryHeap<(usize, PathBuf)> = moved
+        .iter()
+        .filter_map(|path| path.parent())
+        .map(|dir| (dir.components().count(), dir.to_path_buf()))
+        .collect();
+    let mut removed = Vec::new();
+
+    while let Some((_, dir)) = pending.pop() {
+        if gone.contains(&dir) || scan_roots.contains(&dir) || !is_safe_path(&dir) {
+            continue;
+        }
+
+        // In a dry run nothing has moved, so treat moved entries as already gone
+        let empty = match fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .all(|entry| gone.contains(&entry.path())),
+            Err(_) => false,
+        };
+        if !empty {
+            continue;
+        }
+
+        if dry_run {
+            println!("Would remove empty directory: {}", dir.display());
+        } else if let Err(e) = fs::remove_dir(&dir) {
+            eprintln!("❌ {}: Failed to remove empty directory: {}", dir.display(), e);
+            log::error!("remove_dir {} failed: {}", dir.display(), e);
+            continue;
+        } else {
+            println!("🧹 Removed empty directory: {}", dir.display());
+            log::info!("remove_dir {}", dir.display());
+        }
+
+        gone.insert(dir.clone());
+        if let Some(parent) = dir.parent() {
+            if scan_roots.iter().any(|root| parent.starts_with(root)) {
+                pending.push((parent.components().count(), parent.to_path_buf()));
+            }
+        }
+        removed.push(dir);
+    }
+
+    removed
+}
+
+/// Index of the file to keep in a group
+///
+/// Each strategy narrows the candidates in turn; a strategy that matches none of
+/// them leaves them unchanged, and whatever tie remains goes to the first found.
+pub fn select_keeper(files: &[FileInfo], strategies: &[KeepStrategy]) -> usize {
+    let mut candidates: Vec<usize> = (0..files.len()).collect();
+
+    for strategy in strategies {
+        let narrowed = match strategy {
+            KeepStrategy::DirectoryPriority(dirs) => dirs
+                .iter()
+                .map(|dir| {
+                    candidates
+                        .iter()
+                        .copied()
+                        .filter(|&i| is_under(&files[i].path, dir))
+                        .collect::<Vec<_>>()
+                })
+                .find(|matching| !matching.is_empty()),
+            KeepStrategy::PreferExtension(extensions) => extensions
+                .iter()
+                .map(|wanted| {
+                    candidates
+                        .iter()
+                        .copied()
+                        .filter(|&i| has_extension(&files[i].path, wanted))
+                        .collect::<Vec<_>>()
+                })
+                .find(|matching| !matching.is_empty()),
+            KeepStrategy::FirstFound => Some(vec![candidates[0]]),
+            KeepStrategy::NewestModified => Some(lowest_by(&candidates, |i| Reverse(files[i].modified))),
+            KeepStrategy::OldestModified => Some(lowest_by(&candidates, |i| files[i].modified)),
+            KeepStrategy::ShortestPath => Some(lowest_by(&candidates, |i| files[i].path.as_os_str().len())),
+            KeepStrategy::LongestPath => Some(lowest_by(&candidates, |i| Reverse(files[i].path.as_os_str().len()))),
+            KeepStrategy::PreferDirectory(dir) => {
+                let matching: Vec<usize> = candidates.iter().copied().filter(|&i| is_under(&files[i].path, dir)).collect();
+                (!matching.is_empty()).then_some(matching)
+            }
+        };
+        if let Some(narrowed) = narrowed {
+            candidates = narrowed;
+        }
+    }
+
+    candidates[0]
+}
+
+/// Index of the file to keep under a single strategy, ties going to the first found
+pub fn select_canonical(files: &[FileInfo], strategy: &KeepStrategy) -> usize {
+    select_keeper(files, std::slice::from_ref(strategy))
+}
+
+/// The candidates sharing the lowest key, in their original order
+fn lowest_by<K: Ord>(candidates: &[usize], key: impl Fn(usize) -> K) -> Vec<usize> {
+    let Some(lowest) = candidates.iter().map(|&i| key(i)).min() else {
+        return Vec::new();
+    };
+    candidates.iter().copied().filter(|&i| key(i) == lowest).collect()
+}
+
+/// Check whether a path lies inside a directory, also comparing canonical forms
+fn is_under(path: &Path, dir: &Path) -> bool {
+    if path.starts_with(dir) {
+        return true;
+    }
+    match (fs::canonicalize(path), fs::canonicalize(dir)) {
+        (Ok(path), Ok(dir)) => path.starts_with(dir),
+        _ => false,
+    }
+}
+
+fn has_extension(path: &Path, wanted: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.to_string_lossy().eq_ignore_ascii_case(wanted.trim_start_matches('.')))
+}
+
+/// Byte-for-byte comparison used to verify a duplicate against its keeper;
+/// NUL-padded groups only need to match up to the padding
+fn same_content(keeper: &Path, duplicate: &Path, padded: bool) -> io::Result<bool> {
+    if padded {
+        identical_ignoring_nul_padding(keeper, duplicate)
+    } else {
+        files_identical(keeper, duplicate)
+    }
+}
+
+/// Safe-mode checks a duplicate must pass before it is deleted
+fn check_safe_delete(keeper: &Path, duplicate: &Path, padded: bool) -> Result<(), String> {
+    if !is_safe_path(duplicate) {
+        return Err("file is in a protected system directory".to_string());
+    }
+    match are_same_file(keeper, duplicate) {
+        Ok(false) => {}
+        Ok(true) => return Err(format!("keeper {} is the same file", keeper.display())),
+        Err(e) => return Err(format!("could not compare with keeper: {}", e)),
+    }
+    if !is_persistent_storage(keeper) {
+        return Err(format!("keeper {} is on temporary storage", keeper.display()));
+    }
+    match same_content(keeper, duplicate, padded) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("content differs from keeper {}", keeper.display())),
+        Err(e) => Err(format!("could not verify against keeper: {}", e)),
+    }
+}
+
+/// Resolve the keeper to its ultimate target when it is a symlink and dereferencing is enabled
+fn resolve_keeper(keeper: &Path, config: &ActionConfig) -> Result<PathBuf> {
+    if !config.dereference_keeper {
+        return Ok(keeper.to_path_buf());
+    }
+
+    let is_symlink = fs::symlink_metadata(keeper)
+        .with_context(|| format!("Failed to get metadata for {}", keeper.display()))?
+        .file_type()
+        .is_symlink();
+
+    if !is_symlink {
+        return Ok(keeper.to_path_buf());
+    }
+
+    fs::canonicalize(keeper)
+        .with_context(|| format!("Failed to resolve keeper symlink {}", keeper.display()))
+}
+
+/// Stable identifier for an I/O error kind, as recorded in `FileOperation::error_kind`
+///
+/// Besides these, failures that aren't I/O errors use `keeper_alias`, `link_target`, `in_use`,
+/// `content_mismatch`, `safe_mode`, `invalid_path` and `cannot_revert`.
+pub fn error_kind_id(kind: io::ErrorKind) -> &'static str {
+    match kind {
+        io::ErrorKind::NotFound => "not_found",
+        io::ErrorKind::PermissionDenied => "permission_denied",
+        io::ErrorKind::AlreadyExists => "already_exists",
+        io::ErrorKind::WouldBlock => "would_block",
+        io::ErrorKind::InvalidInput => "invalid_input",
+        io::ErrorKind::InvalidData => "invalid_data",
+        io::ErrorKind::TimedOut => "timed_out",
+        io::ErrorKind::Interrupted => "interrupted",
+        io::ErrorKind::Unsupported => "unsupported",
+        io::ErrorKind::UnexpectedEof => "unexpected_eof",
+        io::ErrorKind::OutOfMemory => "out_of_memory",
+        io::ErrorKind::StorageFull => "storage_full",
+        io::ErrorKind::ReadOnlyFilesystem => "read_only_filesystem",
+        io::ErrorKind::CrossesDevices => "crosses_devices",
+        io::ErrorKind::ResourceBusy => "resource_busy",
+        io::ErrorKind::IsADirectory => "is_a_directory",
+        io::ErrorKind::NotADirectory => "not_a_directory",
+        io::ErrorKind::DirectoryNotEmpty => "directory_not_empty",
+        io::ErrorKind::StaleNetworkFileHandle => "stale_network_file_handle",
+        _ => "other",
+    }
+}
+
+/// Short name of an action, as recorded in `FileOperation::action`
+pub fn action_name(action: &DedupAction) -> &'static str {
+    match action {
+        DedupAction::List => "list",
+        DedupAction::Delete => "delete",
+        DedupAction::Move(_) => "move",
+        DedupAction::Hardlink => "hardlink",
+        DedupAction::Symlink => "symlink",
+        DedupAction::Copy(_) => "copy",
+        DedupAction::Reflink => "reflink",
+        DedupAction::DedupInPlace => "dedup-in-place",
+        DedupAction::Revert(_) => "revert",
+    }
+}
+
 /// Result of performing actions on files
 #[derive(Debug, Clone)]
 pub struct ActionResult {
     pub operations: Vec<FileOperation>,
     pub total_space_saved: u64,
     pub total_files_processed: usize,
+    /// Files checked by the post-action integrity pass
+    pub files_verified: usize,
+    /// Paths that failed the post-action integrity pass, with what was wrong
+    pub verify_failures: Vec<(PathBuf, String)>,
 }
 
 /// Represents a single file operation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct FileOperation {
     pub path: PathBuf,
     pub action: String,
     pub success: bool,
     pub error: Option<String>,
     pub space_saved: u64,
+    /// How many times the filesystem call was tried (0 when nothing was attempted)
+    pub attempts: u32,
+    /// Stable identifier for the kind of failure, see `error_kind_id`
+    pub error_kind: Option<&'static str>,
+    /// Raw OS error code behind the failure, if any
+    pub os_error: Option<i32>,
 }
 
 impl ActionResult {
@@ -57,6 +683,8 @@ impl ActionResult {
             operations: Vec::new(),
             total_space_saved: 0,
             total_files_processed: 0,
+            files_verified: 0,
+            verify_failures: Vec::new(),
         }
     }
 
@@ -89,58 +717,155 @@ impl ActionResult {
             for op in &self.operations {
                 if !op.success {
                     if let Some(error) = &op.error {
-                        println!("  {}: {}", op.path.display(), error);
+                        if op.attempts > 1 {
+                            println!("  {}: {} (after {} attempts)", op.path.display(), error, op.attempts);
+                        } else {
+                            println!("  {}: {}", op.path.display(), error);
+                        }
                     }
                 }
             }
         }
+
+        if self.files_verified > 0 {
+            println!();
+            println!("{}", style("🔎 Post-verify").bold());
+            println!("Files verified: {}", self.files_verified);
+            println!("Discrepancies: {}", self.verify_failures.len());
+            for (path, problem) in &self.verify_failures {
+                println!("  {} {}: {}", style("❌").red(), path.display(), problem);
+            }
+        }
+    }
+}
+
+impl Default for ActionResult {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Base delay before the first retry; doubles on each further attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+
+/// Longest wait between two attempts, however many retries are allowed
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Wait before retrying after this many failed attempts
+fn retry_delay(failed_attempts: u32) -> Duration {
+    let factor = 2u32.saturating_pow(failed_attempts.saturating_sub(1));
+    RETRY_BASE_DELAY.saturating_mul(factor).min(RETRY_MAX_DELAY)
+}
+
+/// Check whether an I/O error is likely to succeed if retried
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::ResourceBusy
+            | io::ErrorKind::StaleNetworkFileHandle
+    )
+}
+
+/// Run an I/O operation, retrying transient failures with exponential backoff.
+/// Returns the final outcome and the number of attempts made.
+fn with_retries<T>(retries: u32, mut op: impl FnMut() -> io::Result<T>) -> (io::Result<T>, u32) {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match op() {
+            Err(e) if attempts <= retries && is_transient(&e) => {
+                thread::sleep(retry_delay(attempts));
+            }
+            outcome => return (outcome, attempts),
+        }
     }
 }
 
 /// Delete a file
-fn delete_file(path: &Path, dry_run: bool) -> Result<FileOperation> {
+fn delete_file(path: &Path, config: &ActionConfig, dry_run: bool) -> Result<FileOperation> {
     let file_size = fs::metadata(path)
         .with_context(|| format!("Failed to get metadata for {}", path.display()))?
         .len();
     
+    let action = if config.trash_dir.is_some() { "trash" } else { "delete" };
+
     if dry_run {
-        println!("Would delete: {}", path.display());
+        match &config.trash_dir {
+            Some(trash_dir) => println!("Would move to trash {}: {}", trash_dir.display(), path.display()),
+            None => println!("Would delete: {}", path.display()),
+        }
         return Ok(FileOperation {
             path: path.to_path_buf(),
-            action: "delete".to_string(),
+            action: action.to_string(),
             success: true,
             error: None,
             space_saved: file_size,
+            attempts: 0,
+            error_kind: None,
+            os_error: None,
         });
     }
 
-    match fs::remove_file(path) {
-        Ok(_) => {
-            println!("✅ Deleted: {}", path.display());
+    // A delete that can't be logged stops the run before the file is gone
+    if let (Some(log), None) = (&config.revert_log, &config.trash_dir) {
+        log.record(path, None, RevertKind::Delete, file_size)?;
+    }
+    let (outcome, attempts) = with_retries(config.retries, || match &config.trash_dir {
+        Some(trash_dir) => trash_file(path, trash_dir).map(Some),
+        None => fs::remove_file(path).map(|_| None),
+    });
+    match outcome {
+        Ok(trashed) => {
+            // The trash only names the file once it is in there
+            if let (Some(log), Some(trashed)) = (&config.revert_log, &trashed) {
+                log.record(path, Some(trashed), RevertKind::Trash, file_size)?;
+            }
+            match &config.trash_dir {
+                Some(_) => println!("🗑️  Moved to trash: {}", path.display()),
+                None => println!("✅ Deleted: {}", path.display()),
+            }
             Ok(FileOperation {
                 path: path.to_path_buf(),
-                action: "delete".to_string(),
+                action: action.to_string(),
                 success: true,
                 error: None,
                 space_saved: file_size,
+                attempts,
+                error_kind: None,
+                os_error: None,
             })
         }
         Err(e) => {
-            let error_msg = format!("Failed to delete: {}", e);
+            let error_msg = match config.trash_dir {
+                Some(_) => format!("Failed to move to trash: {}", e),
+                None => format!("Failed to delete: {}", e),
+            };
             eprintln!("❌ {}: {}", path.display(), error_msg);
             Ok(FileOperation {
                 path: path.to_path_buf(),
-                action: "delete".to_string(),
+                action: action.to_string(),
                 success: false,
                 error: Some(error_msg),
                 space_saved: 0,
+                attempts,
+                error_kind: Some(error_kind_id(e.kind())),
+                os_error: e.raw_os_error(),
             })
         }
     }
 }
 
 /// Move a file to a target directory
-fn move_file(source: &Path, target_dir: &Path, dry_run: bool) -> Result<FileOperation> {
+fn move_file(
+    source: &Path,
+    hash: &str,
+    target_dir: &Path,
+    config: &ActionConfig,
+    dry_run: bool,
+) -> Result<FileOperation> {
     let file_size = fs::metadata(source)
         .with_context(|| format!("Failed to get metadata for {}", source.display()))?
         .len();
@@ -152,21 +877,27 @@ fn move_file(source: &Path, target_dir: &Path, dry_run: bool) -> Result<FileOper
     }
     
     // Generate unique filename if file already exists in target
-    let filename = source.file_name().unwrap();
-    let mut target_path = target_dir.join(filename);
-    let mut counter = 1;
-    
-    while target_path.exists() {
-        let stem = source.file_stem().unwrap().to_string_lossy();
-        let ext = source.extension().map(|s| s.to_string_lossy()).unwrap_or_default();
-        let new_filename = if ext.is_empty() {
-            format!("{}_{}", stem, counter)
-        } else {
-            format!("{}_{}.{}", stem, counter, ext)
-        };
-        target_path = target_dir.join(new_filename);
-        counter += 1;
-    }
+    let filename = match source.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => {
+            let error_msg = "Failed to move: path has no file name".to_string();
+            eprintln!("❌ {}: {}", source.display(), error_msg);
+            return Ok(FileOperation {
+                path: source.to_path_buf(),
+                action: "move".to_string(),
+                success: false,
+                error: Some(error_msg),
+                space_saved: 0,
+                attempts: 0,
+                error_kind: Some("invalid_path"),
+                os_error: None,
+            });
+        }
+    };
+    let target_path = match &config.move_template {
+        Some(template) => template.unique_path(target_dir, &filename, hash),
+        None => generate_unique_filename(target_dir, &filename),
+    };
     
     if dry_run {
         println!("Would move: {} -> {}", source.display(), target_path.display());
@@ -176,18 +907,43 @@ fn move_file(source: &Path, target_dir: &Path, dry_run: bool) -> Result<FileOper
             success: true,
             error: None,
             space_saved: file_size,
+            attempts: 0,
+            error_kind: None,
+            os_error: None,
         });
     }
 
-    match fs::rename(source, &target_path) {
+    // A move that can't be logged stops the run before the file leaves
+    if let Some(log) = &config.revert_log {
+        log.record(source, Some(&target_path), RevertKind::Move, file_size)?;
+    }
+    let (outcome, attempts) = with_retries(config.retries, || {
+        fs::rename(source, &target_path).or_else(|e| match e.kind() {
+            io::ErrorKind::CrossesDevices => move_across_devices(source, &target_path, config.preserve_metadata),
+            _ => Err(e),
+        })
+    });
+    match outcome {
         Ok(_) => {
             println!("✅ Moved: {} -> {}", source.display(), target_path.display());
+            // A move that can't be recorded stops the run, so the manifest never falls behind
+            if let Some(manifest) = &config.move_manifest {
+                manifest.record(&MoveRecord {
+                    source: source.to_path_buf(),
+                    target: target_path.clone(),
+                    size: file_size,
+                    hash: hash.to_string(),
+                })?;
+            }
             Ok(FileOperation {
                 path: source.to_path_buf(),
                 action: "move".to_string(),
                 success: true,
                 error: None,
                 space_saved: file_size,
+                attempts,
+                error_kind: None,
+                os_error: None,
             })
         }
         Err(e) => {
@@ -199,16 +955,53 @@ fn move_file(source: &Path, target_dir: &Path, dry_run: bool) -> Result<FileOper
                 success: false,
                 error: Some(error_msg),
                 space_saved: 0,
+                attempts,
+                error_kind: Some(error_kind_id(e.kind())),
+                os_error: e.raw_os_error(),
             })
         }
     }
 }
 
+/// Move a file to another filesystem by copying it and removing the original
+pub(crate) fn move_across_devices(source: &Path, target: &Path, preserve_metadata: bool) -> io::Result<()> {
+    let copied = fs::copy(source, target).and_then(|_| {
+        if preserve_metadata {
+            copy_metadata(source, target)?;
+        }
+        Ok(())
+    });
+    if let Err(e) = copied {
+        let _ = fs::remove_file(target);
+        return Err(e);
+    }
+    fs::remove_file(source)
+}
+
 /// Create a hard link
-fn create_hardlink(original: &Path, duplicate: &Path, dry_run: bool) -> Result<FileOperation> {
+fn create_hardlink(original: &Path, duplicate: &Path, config: &ActionConfig, dry_run: bool) -> Result<FileOperation> {
     let file_size = fs::metadata(duplicate)
         .with_context(|| format!("Failed to get metadata for {}", duplicate.display()))?
         .len();
+
+    // Checked up front, since the duplicate is removed before linking
+    if let (Some(a), Some(b)) = (device_id(original), device_id(duplicate)) {
+        if a != b {
+            let e = io::Error::from(io::ErrorKind::CrossesDevices);
+            let error_msg = format!("Cannot hardlink across filesystems to {}", original.display());
+            eprintln!("❌ {}: {}", duplicate.display(), error_msg);
+            return Ok(FileOperation {
+                path: duplicate.to_path_buf(),
+                action: "hardlink".to_string(),
+                success: false,
+                error: Some(error_msg),
+                space_saved: 0,
+                attempts: 0,
+                error_kind: Some(error_kind_id(e.kind())),
+                os_error: None,
+            });
+        }
+    }
     
     if dry_run {
         println!("Would create hardlink: {} -> {}", duplicate.display(), original.display());
@@ -218,11 +1011,15 @@ fn create_hardlink(original: &Path, duplicate: &Path, dry_run: bool) -> Result<F
             success: true,
             error: None,
             space_saved: file_size,
+            attempts: 0,
+            error_kind: None,
+            os_error: None,
         });
     }
 
     // Remove duplicate file first
-    if let Err(e) = fs::remove_file(duplicate) {
+    let (outcome, remove_attempts) = with_retries(config.retries, || fs::remove_file(duplicate));
+    if let Err(e) = outcome {
         let error_msg = format!("Failed to remove duplicate before hardlinking: {}", e);
         eprintln!("❌ {}: {}", duplicate.display(), error_msg);
         return Ok(FileOperation {
@@ -231,11 +1028,16 @@ fn create_hardlink(original: &Path, duplicate: &Path, dry_run: bool) -> Result<F
             success: false,
             error: Some(error_msg),
             space_saved: 0,
+            attempts: remove_attempts,
+            error_kind: Some(error_kind_id(e.kind())),
+            os_error: e.raw_os_error(),
         });
     }
 
     // Create hard link
-    match fs::hard_link(original, duplicate) {
+    let (outcome, link_attempts) = with_retries(config.retries, || fs::hard_link(original, duplicate));
+    let attempts = remove_attempts.max(link_attempts);
+    match outcome {
         Ok(_) => {
             println!("✅ Created hardlink: {} -> {}", duplicate.display(), original.display());
             Ok(FileOperation {
@@ -244,6 +1046,9 @@ fn create_hardlink(original: &Path, duplicate: &Path, dry_run: bool) -> Result<F
                 success: true,
                 error: None,
                 space_saved: file_size,
+                attempts,
+                error_kind: None,
+                os_error: None,
             })
         }
         Err(e) => {
@@ -255,13 +1060,16 @@ fn create_hardlink(original: &Path, duplicate: &Path, dry_run: bool) -> Result<F
                 success: false,
                 error: Some(error_msg),
                 space_saved: 0,
+                attempts,
+                error_kind: Some(error_kind_id(e.kind())),
+                os_error: e.raw_os_error(),
             })
         }
     }
 }
 
 /// Create a symbolic link
-fn create_symlink(original: &Path, duplicate: &Path, dry_run: bool) -> Result<FileOperation> {
+fn create_symlink(original: &Path, duplicate: &Path, config: &ActionConfig, dry_run: bool) -> Result<FileOperation> {
     let file_size = fs::metadata(duplicate)
         .with_context(|| format!("Failed to get metadata for {}", duplicate.display()))?
         .len();
@@ -274,11 +1082,15 @@ fn create_symlink(original: &Path, duplicate: &Path, dry_run: bool) -> Result<Fi
             success: true,
             error: None,
             space_saved: file_size,
+            attempts: 0,
+            error_kind: None,
+            os_error: None,
         });
     }
 
     // Remove duplicate file first
-    if let Err(e) = fs::remove_file(duplicate) {
+    let (outcome, remove_attempts) = with_retries(config.retries, || fs::remove_file(duplicate));
+    if let Err(e) = outcome {
         let error_msg = format!("Failed to remove duplicate before symlinking: {}", e);
         eprintln!("❌ {}: {}", duplicate.display(), error_msg);
         return Ok(FileOperation {
@@ -287,11 +1099,14 @@ fn create_symlink(original: &Path, duplicate: &Path, dry_run: bool) -> Result<Fi
             success: false,
             error: Some(error_msg),
             space_saved: 0,
+            attempts: remove_attempts,
+            error_kind: Some(error_kind_id(e.kind())),
+            os_error: e.raw_os_error(),
         });
     }
 
     // Create symbolic link
-    let result = {
+    let (result, link_attempts) = with_retries(config.retries, || {
         #[cfg(unix)]
         {
             std::os::unix::fs::symlink(original, duplicate)
@@ -300,7 +1115,8 @@ fn create_symlink(original: &Path, duplicate: &Path, dry_run: bool) -> Result<Fi
         {
             std::os::windows::fs::symlink_file(original, duplicate)
         }
-    };
+    });
+    let attempts = remove_attempts.max(link_attempts);
 
     match result {
         Ok(_) => {
@@ -311,6 +1127,9 @@ fn create_symlink(original: &Path, duplicate: &Path, dry_run: bool) -> Result<Fi
                 success: true,
                 error: None,
                 space_saved: file_size,
+                attempts,
+                error_kind: None,
+                os_error: None,
             })
         }
         Err(e) => {
@@ -322,7 +1141,992 @@ fn create_symlink(original: &Path, duplicate: &Path, dry_run: bool) -> Result<Fi
                 success: false,
                 error: Some(error_msg),
                 space_saved: 0,
+                attempts,
+                error_kind: Some(error_kind_id(e.kind())),
+                os_error: e.raw_os_error(),
             })
         }
     }
-} 
\ No newline at end of file
+}
+
+/// The call that clones a file, as shown by dry runs
+#[cfg(target_os = "linux")]
+const CLONE_CALL: &str = "ioctl FICLONE";
+#[cfg(windows)]
+const CLONE_CALL: &str = "FSCTL_DUPLICATE_EXTENTS_TO_FILE";
+#[cfg(not(any(target_os = "linux", windows)))]
+const CLONE_CALL: &str = "not supported on this platform";
+
+/// Replace a duplicate with a copy-on-write clone of the original
+///
+/// The clone is written next to the duplicate, given the duplicate's
+/// permissions and renamed over it, so the duplicate is left untouched if
+/// cloning fails.
+fn create_reflink(original: &Path, duplicate: &Path, config: &ActionConfig, dry_run: bool) -> Result<FileOperation> {
+    let metadata = fs::metadata(duplicate)
+        .with_context(|| format!("Failed to get metadata for {}", duplicate.display()))?;
+    let file_size = metadata.len();
+
+    if let Some(extents) = shared_extents(duplicate) {
+        if shared_extents(original) == Some(extents) {
+            println!("⏭️  Already shares storage: {} -> {}", duplicate.display(), original.display());
+            return Ok(FileOperation {
+                path: duplicate.to_path_buf(),
+                action: "reflink".to_string(),
+                success: true,
+                error: None,
+                space_saved: 0,
+                attempts: 0,
+                error_kind: None,
+                os_error: None,
+            });
+        }
+    }
+
+    if dry_run {
+        let unsupported = match duplicate.parent().map(is_reflink_supported) {
+            Some(Ok(false)) => ", but this filesystem doesn't support it",
+            _ => "",
+        };
+        println!("Would create reflink: {} -> {} ({}{})", duplicate.display(), original.display(), CLONE_CALL, unsupported);
+        return Ok(FileOperation {
+            path: duplicate.to_path_buf(),
+            action: "reflink".to_string(),
+            success: true,
+            error: None,
+            space_saved: file_size,
+            attempts: 0,
+            error_kind: None,
+            os_error: None,
+        });
+    }
+
+    let mut temp_name = duplicate.as_os_str().to_owned();
+    temp_name.push(".dedup-reflink");
+    let temp_path = PathBuf::from(temp_name);
+
+    let (outcome, clone_attempts) = with_retries(config.retries, || {
+        clone_file(original, &temp_path)?;
+        fs::set_permissions(&temp_path, metadata.permissions())
+    });
+    let (outcome, attempts) = match outcome {
+        Ok(_) => {
+            let (outcome, rename_attempts) = with_retries(config.retries, || fs::rename(&temp_path, duplicate));
+            (outcome.map_err(|e| (format!("Failed to replace duplicate with reflink: {}", e), e)),
+             clone_attempts.max(rename_attempts))
+        }
+        Err(e) => (Err((format!("Failed to create reflink: {}", e), e)), clone_attempts),
+    };
+
+    match outcome {
+        Ok(_) => {
+            println!("✅ Created reflink: {} -> {}", duplicate.display(), original.display());
+            Ok(FileOperation {
+                path: duplicate.to_path_buf(),
+                action: "reflink".to_string(),
+                success: true,
+                error: None,
+                space_saved: file_size,
+                attempts,
+                error_kind: None,
+                os_error: None,
+            })
+        }
+        Err((error_msg, e)) => {
+            let _ = fs::remove_file(&temp_path);
+            eprintln!("❌ {}: {}", duplicate.display(), error_msg);
+            Ok(FileOperation {
+                path: duplicate.to_path_buf(),
+                action: "reflink".to_string(),
+                success: false,
+                error: Some(error_msg),
+                space_saved: 0,
+                attempts,
+                error_kind: Some(error_kind_id(e.kind())),
+                os_error: e.raw_os_error(),
+            })
+        }
+    }
+}
+
+/// Clone `source` into a new file at `target` using ReFS block cloning
+#[cfg(windows)]
+fn clone_file(source: &Path, target: &Path) -> io::Result<()> {
+    let target_file = fs::OpenOptions::new().read(true).write(true).create_new(true).open(target)?;
+    clone_into(source, &target_file)
+}
+
+/// Replace the contents of an open file with block clones of `source` (ReFS only)
+#[cfg(windows)]
+fn clone_into(source: &Path, target_file: &fs::File) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::fs::MetadataExt;
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{GetDiskFreeSpaceW, GetVolumePathNameW};
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+    use windows_sys::Win32::System::Ioctl::{
+        DUPLICATE_EXTENTS_DATA, FSCTL_DUPLICATE_EXTENTS_TO_FILE, FSCTL_SET_SPARSE,
+    };
+
+    const ERROR_INVALID_FUNCTION: i32 = 1;
+    const ERROR_NOT_SUPPORTED: i32 = 50;
+    const FILE_ATTRIBUTE_SPARSE_FILE: u32 = 0x200;
+    // A single request must stay below 4 GiB
+    const MAX_CLONE_CHUNK: u64 = 1 << 31;
+
+    let source_file = fs::File::open(source)?;
+    let metadata = source_file.metadata()?;
+    let len = metadata.len();
+
+    // Cloned regions must be cluster aligned
+    let wide: Vec<u16> = source.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut root = vec![0u16; wide.len().max(261)];
+    let (mut sectors_per_cluster, mut bytes_per_sector, mut free, mut total) = (0u32, 0u32, 0u32, 0u32);
+    let found = unsafe {
+        GetVolumePathNameW(wide.as_ptr(), root.as_mut_ptr(), root.len() as u32) != 0
+            && GetDiskFreeSpaceW(
+                root.as_ptr(),
+                &mut sectors_per_cluster,
+                &mut bytes_per_sector,
+                &mut free,
+                &mut total,
+            ) != 0
+    };
+    if !found {
+        return Err(io::Error::last_os_error());
+    }
+    let cluster_size = (sectors_per_cluster as u64 * bytes_per_sector as u64).max(1);
+    let aligned_len = len.div_ceil(cluster_size) * cluster_size;
+
+    let ioctl = |code: u32, input: *const std::ffi::c_void, input_len: usize| {
+        let mut returned = 0u32;
+        let ok = unsafe {
+            DeviceIoControl(
+                target_file.as_raw_handle() as _,
+                code,
+                input,
+                input_len as u32,
+                std::ptr::null_mut(),
+                0,
+                &mut returned,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok != 0 {
+            return Ok(());
+        }
+        let error = io::Error::last_os_error();
+        match error.raw_os_error() {
+            Some(ERROR_INVALID_FUNCTION | ERROR_NOT_SUPPORTED) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "block cloning is not supported on this volume (requires ReFS)",
+            )),
+            _ => Err(error),
+        }
+    };
+
+    // Sparse sources can only be cloned into sparse targets
+    if metadata.file_attributes() & FILE_ATTRIBUTE_SPARSE_FILE != 0 {
+        ioctl(FSCTL_SET_SPARSE, std::ptr::null(), 0)?;
+    }
+
+    target_file.set_len(aligned_len)?;
+    let mut offset = 0;
+    while offset < aligned_len {
+        let count = (aligned_len - offset).min(MAX_CLONE_CHUNK);
+        let data = DUPLICATE_EXTENTS_DATA {
+            FileHandle: source_file.as_raw_handle() as _,
+            SourceFileOffset: offset as i64,
+            TargetFileOffset: offset as i64,
+            ByteCount: count as i64,
+        };
+        ioctl(
+            FSCTL_DUPLICATE_EXTENTS_TO_FILE,
+            &data as *const DUPLICATE_EXTENTS_DATA as *const _,
+            std::mem::size_of::<DUPLICATE_EXTENTS_DATA>(),
+        )?;
+        offset += count;
+    }
+    target_file.set_len(len)
+}
+
+/// Clone `source` into a new file at `target` with the kernel's FICLONE ioctl
+///
+/// Works on filesystems with shared extents, such as btrfs and XFS, when both
+/// files are on the same one.
+#[cfg(target_os = "linux")]
+fn clone_file(source: &Path, target: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let source_file = fs::File::open(source)?;
+    let target_file = fs::OpenOptions::new().write(true).create_new(true).open(target)?;
+    let ret = unsafe { libc::ioctl(target_file.as_raw_fd(), libc::FICLONE, source_file.as_raw_fd()) };
+    if ret < 0 {
+        let error = io::Error::last_os_error();
+        return Err(match error.raw_os_error() {
+            Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EINVAL) => io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("reflink is not supported here, it needs btrfs or XFS with reflink enabled ({})", error),
+            ),
+            _ => error,
+        });
+    }
+    Ok(())
+}
+
+/// Block cloning is only implemented for Linux and for ReFS on Windows
+#[cfg(not(any(target_os = "linux", windows)))]
+fn clone_file(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflink is only supported on Linux (btrfs, XFS) and on ReFS volumes on Windows",
+    ))
+}
+
+/// Make a duplicate share the original's storage without replacing the duplicate's file
+///
+/// Unlike `create_reflink`, the duplicate keeps its inode, so its permissions,
+/// ownership, timestamps and any hard links to it are untouched.
+fn dedupe_in_place(original: &Path, duplicate: &Path, config: &ActionConfig, dry_run: bool) -> Result<FileOperation> {
+    let file_size = fs::metadata(duplicate)
+        .with_context(|| format!("Failed to get metadata for {}", duplicate.display()))?
+        .len();
+
+    if let Some(extents) = shared_extents(duplicate) {
+        if shared_extents(original) == Some(extents) {
+            println!("⏭️  Already shares storage: {} -> {}", duplicate.display(), original.display());
+            return Ok(FileOperation {
+                path: duplicate.to_path_buf(),
+                action: "dedup-in-place".to_string(),
+                success: true,
+                error: None,
+                space_saved: 0,
+                attempts: 0,
+                error_kind: None,
+                os_error: None,
+            });
+        }
+    }
+
+    if dry_run {
+        println!("Would share storage: {} -> {}", duplicate.display(), original.display());
+        return Ok(FileOperation {
+            path: duplicate.to_path_buf(),
+            action: "dedup-in-place".to_string(),
+            success: true,
+            error: None,
+            space_saved: file_size,
+            attempts: 0,
+            error_kind: None,
+            os_error: None,
+        });
+    }
+
+    let (outcome, attempts) = with_retries(config.retries, || share_extents(original, duplicate));
+    match outcome {
+        Ok(_) => {
+            println!("✅ Shared storage: {} -> {}", duplicate.display(), original.display());
+            Ok(FileOperation {
+                path: duplicate.to_path_buf(),
+                action: "dedup-in-place".to_string(),
+                success: true,
+                error: None,
+                space_saved: file_size,
+                attempts,
+                error_kind: None,
+                os_error: None,
+            })
+        }
+        Err(e) => {
+            let error = format!("Failed to share storage: {}", e);
+            eprintln!("❌ {}: {}", duplicate.display(), error);
+            Ok(FileOperation {
+                path: duplicate.to_path_buf(),
+                action: "dedup-in-place".to_string(),
+                success: false,
+                error: Some(error),
+                space_saved: 0,
+                attempts,
+                error_kind: Some(error_kind_id(e.kind())),
+                os_error: e.raw_os_error(),
+            })
+        }
+    }
+}
+
+/// Point `target`'s blocks at `source`'s with the kernel's FIDEDUPERANGE ioctl
+///
+/// The kernel compares both ranges under lock and refuses if they differ, so
+/// a file changed since the scan is never overwritten.
+#[cfg(target_os = "linux")]
+fn share_extents(source: &Path, target: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // _IOWR(0x94, 54, struct file_dedupe_range)
+    const FIDEDUPERANGE: libc::c_ulong = 0xC018_9436;
+    const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+    // Filesystems may cap a single request, so go in bounded steps
+    const MAX_DEDUPE_CHUNK: u64 = 16 * 1024 * 1024;
+
+    #[repr(C)]
+    struct FileDedupeRange {
+        src_offset: u64,
+        src_length: u64,
+        dest_count: u16,
+        reserved1: u16,
+        reserved2: u32,
+        info: FileDedupeRangeInfo,
+    }
+
+    #[repr(C)]
+    struct FileDedupeRangeInfo {
+        dest_fd: i64,
+        dest_offset: u64,
+        bytes_deduped: u64,
+        status: i32,
+        reserved: u32,
+    }
+
+    let source_file = fs::File::open(source)?;
+    let target_file = fs::OpenOptions::new().write(true).open(target)?;
+    let len = source_file.metadata()?.len();
+    if target_file.metadata()?.len() != len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "file sizes differ"));
+    }
+
+    let mut offset = 0;
+    while offset < len {
+        let mut range = FileDedupeRange {
+            src_offset: offset,
+            src_length: (len - offset).min(MAX_DEDUPE_CHUNK),
+            dest_count: 1,
+            reserved1: 0,
+            reserved2: 0,
+            info: FileDedupeRangeInfo {
+                dest_fd: target_file.as_raw_fd() as i64,
+                dest_offset: offset,
+                bytes_deduped: 0,
+                status: 0,
+                reserved: 0,
+            },
+        };
+        let ret = unsafe { libc::ioctl(source_file.as_raw_fd(), FIDEDUPERANGE as _, &mut range) };
+        if ret < 0 {
+            let error = io::Error::last_os_error();
+            return Err(match error.raw_os_error() {
+                Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EINVAL | libc::EXDEV) => io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("in-place deduplication is not supported here ({})", error),
+                ),
+                _ => error,
+            });
+        }
+        match range.info.status {
+            FILE_DEDUPE_RANGE_DIFFERS => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "file contents differ"));
+            }
+            status if status < 0 => return Err(io::Error::from_raw_os_error(-status)),
+            _ => {}
+        }
+        if range.info.bytes_deduped == 0 {
+            return Err(io::Error::other("the filesystem deduplicated no data"));
+        }
+        offset += range.info.bytes_deduped;
+    }
+    Ok(())
+}
+
+/// Overwrite `target` with block clones of `source`, then restore its timestamps
+///
+/// Block cloning doesn't compare anything, so the contents are checked first
+/// and a file that changed since the scan is refused, as FIDEDUPERANGE does.
+#[cfg(windows)]
+fn share_extents(source: &Path, target: &Path) -> io::Result<()> {
+    if !files_identical(source, target)? {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "file contents differ"));
+    }
+    let target_file = fs::OpenOptions::new().read(true).write(true).open(target)?;
+    let metadata = target_file.metadata()?;
+    let mut times = fs::FileTimes::new();
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    clone_into(source, &target_file)?;
+    target_file.set_times(times)
+}
+
+/// In-place deduplication needs FIDEDUPERANGE (Linux) or ReFS block cloning (Windows)
+#[cfg(not(any(target_os = "linux", windows)))]
+fn share_extents(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "in-place deduplication is only supported on Linux and on ReFS volumes on Windows",
+    ))
+}
+
+/// Find a file under `dir` with the same content as `file`
+fn find_existing_copy(dir: &Path, file: &FileInfo) -> Result<Option<PathBuf>> {
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let same_size = entry.metadata().map(|m| m.len() == file.size).unwrap_or(false);
+        if same_size && hash_file(entry.path(), file.hash_algorithm, file.hash_len())? == file.content_hash() {
+            return Ok(Some(entry.path().to_path_buf()));
+        }
+    }
+    Ok(None)
+}
+
+/// Copy a file into a target directory unless identical content is already there,
+/// verifying the copy's hash afterwards
+fn copy_file(source: &FileInfo, target_dir: &Path, config: &ActionConfig, dry_run: bool) -> Result<FileOperation> {
+    if let Some(existing) = find_existing_copy(target_dir, source)? {
+        println!("⏭️  Already present: {} == {}", source.path.display(), existing.display());
+        return Ok(FileOperation {
+            path: source.path.clone(),
+            action: "copy".to_string(),
+            success: true,
+            error: None,
+            space_saved: 0,
+            attempts: 0,
+            error_kind: None,
+            os_error: None,
+        });
+    }
+
+    let filename = match source.path.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => {
+            let error_msg = "Failed to copy: path has no file name".to_string();
+            eprintln!("❌ {}: {}", source.path.display(), error_msg);
+            return Ok(FileOperation {
+                path: source.path.clone(),
+                action: "copy".to_string(),
+                success: false,
+                error: Some(error_msg),
+                space_saved: 0,
+                attempts: 0,
+                error_kind: Some("invalid_path"),
+                os_error: None,
+            });
+        }
+    };
+    let target_path = match &config.move_template {
+        Some(template) => template.unique_path(target_dir, &filename, &source.hash),
+        None => generate_unique_filename(target_dir, &filename),
+    };
+
+    if dry_run {
+        println!("Would copy: {} -> {}", source.path.display(), target_path.display());
+        return Ok(FileOperation {
+            path: source.path.clone(),
+            action: "copy".to_string(),
+            success: true,
+            error: None,
+            space_saved: 0,
+            attempts: 0,
+            error_kind: None,
+            os_error: None,
+        });
+    }
+
+    fs::create_dir_all(target_dir)
+        .with_context(|| format!("Failed to create target directory {}", target_dir.display()))?;
+
+    let (outcome, attempts) = with_retries(config.retries, || fs::copy(&source.path, &target_path));
+    let outcome = outcome.and_then(|_| match config.preserve_metadata {
+        true => copy_metadata(&source.path, &target_path),
+        false => Ok(()),
+    });
+    let failure = match outcome {
+        Ok(_) => match hash_file(&target_path, source.hash_algorithm, source.hash_len()) {
+            Ok(hash) if hash == source.content_hash() => None,
+            Ok(_) => Some(("Failed to verify copy: content differs from source".to_string(), "content_mismatch", None)),
+            Err(e) => {
+                let io_error = e.downcast_ref::<io::Error>();
+                Some((
+                    format!("Failed to verify copy: {}", e),
+                    io_error.map(|e| error_kind_id(e.kind())).unwrap_or("other"),
+                    io_error.and_then(|e| e.raw_os_error()),
+                ))
+            }
+        },
+        Err(e) => Some((format!("Failed to copy: {}", e), error_kind_id(e.kind()), e.raw_os_error())),
+    };
+
+    match failure {
+        None => {
+            println!("✅ Copied: {} -> {}", source.path.display(), target_path.display());
+            Ok(FileOperation {
+                path: source.path.clone(),
+                action: "copy".to_string(),
+                success: true,
+                error: None,
+                space_saved: 0,
+                attempts,
+                error_kind: None,
+                os_error: None,
+            })
+        }
+        Some((error_msg, error_kind, os_error)) => {
+            // Don't leave a bad copy behind
+            let _ = fs::remove_file(&target_path);
+            eprintln!("❌ {}: {}", source.path.display(), error_msg);
+            Ok(FileOperation {
+                path: source.path.clone(),
+                action: "copy".to_string(),
+                success: false,
+                error: Some(error_msg),
+                space_saved: 0,
+                attempts,
+                error_kind: Some(error_kind),
+                os_error,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashAlgorithm;
+    use crate::utils::DEFAULT_HASH_LEN;
+    use tempfile::tempdir;
+
+    fn move_twice(name: &str) -> PathBuf {
+        let src = tempdir().unwrap();
+        let target = tempdir().unwrap();
+        fs::write(target.path().join(name), b"existing").unwrap();
+        let source = src.path().join(name);
+        fs::write(&source, b"moved").unwrap();
+
+        let op = move_file(&source, "00", target.path(), &ActionConfig::default(), false).unwrap();
+        assert!(op.success);
+        let moved: Vec<PathBuf> = fs::read_dir(target.path())
+            .unwrap()
+            .map(|e| PathBuf::from(e.unwrap().file_name()))
+            .filter(|p| p.as_os_str() != name)
+            .collect();
+        assert_eq!(moved.len(), 1);
+        moved[0].clone()
+    }
+
+    #[test]
+    fn test_move_file_collision_names() {
+        assert_eq!(move_twice(".gitignore"), PathBuf::from(".gitignore_1"));
+        assert_eq!(move_twice("archive.tar.gz"), PathBuf::from("archive.tar_1.gz"));
+        assert_eq!(move_twice("README"), PathBuf::from("README_1"));
+    }
+
+    #[test]
+    fn test_move_manifest_records_each_move() {
+        let src = tempdir().unwrap();
+        let target = tempdir().unwrap();
+        let manifest_path = src.path().join("moves.jsonl");
+        let config = ActionConfig {
+            move_manifest: Some(MoveManifest::open(&manifest_path).unwrap()),
+            ..ActionConfig::default()
+        };
+
+        for name in ["a", "b"] {
+            fs::write(src.path().join(name), b"data").unwrap();
+            assert!(move_file(&src.path().join(name), "ab12", target.path(), &config, false).unwrap().success);
+        }
+
+        let records = MoveManifest::load(&manifest_path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].source, src.path().join("a"));
+        assert_eq!(records[1].target, target.path().join("b"));
+        assert_eq!(records[1].size, 4);
+        assert!(records[1].target.exists());
+    }
+
+    #[test]
+    fn test_copy_skips_content_already_present() {
+        let src = tempdir().unwrap();
+        let library = tempdir().unwrap();
+        let source = src.path().join("song.mp3");
+        fs::write(&source, b"la la la").unwrap();
+        let file = FileInfo {
+            path: source.clone(),
+            size: 8,
+            hash: hash_file(&source, HashAlgorithm::Blake3, DEFAULT_HASH_LEN).unwrap(),
+            modified: std::time::UNIX_EPOCH,
+            detected_type: None,
+            raw_hash: None,
+            source_root: None,
+            hash_algorithm: HashAlgorithm::Blake3,
+            inode: None,
+        };
+        let config = ActionConfig::default();
+
+        let op = copy_file(&file, library.path(), &config, false).unwrap();
+        assert!(op.success);
+        assert!(source.exists());
+        assert_eq!(fs::read(library.path().join("song.mp3")).unwrap(), b"la la la");
+
+        // Second copy finds the content already in the library
+        let op = copy_file(&file, library.path(), &config, false).unwrap();
+        assert!(op.success);
+        assert_eq!(fs::read_dir(library.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fallback_when_action_unsupported() {
+        let dir = tempdir().unwrap();
+        let files: Vec<FileInfo> = ["a", "b"]
+            .iter()
+            .map(|name| {
+                let path = dir.path().join(name);
+                fs::write(&path, b"same").unwrap();
+                FileInfo {
+                    path,
+                    size: 4,
+                    hash: "h".to_string(),
+                    modified: std::time::UNIX_EPOCH,
+                    detected_type: None,
+                    raw_hash: None,
+                    source_root: None,
+                    hash_algorithm: HashAlgorithm::Blake3,
+                    inode: None,
+                }
+            })
+            .collect();
+        let config = ActionConfig {
+            fallback: Some(DedupAction::Hardlink),
+            ..ActionConfig::default()
+        };
+
+        // Skipped where cloning works, since nothing would fall back
+        if is_reflink_supported(dir.path()).unwrap() {
+            return;
+        }
+        let result = perform_action(&files, &DedupAction::Reflink, &config, false).unwrap();
+        assert_eq!(result.operations.len(), 1);
+        assert_eq!(result.operations[0].action, "hardlink");
+        assert!(result.operations[0].success);
+        assert!(are_same_file(&files[0].path, &files[1].path).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_linked_members_are_left_intact() {
+        let dir = tempdir().unwrap();
+        let info = |name: &str| FileInfo {
+            path: dir.path().join(name),
+            size: 4,
+            hash: "h".to_string(),
+            modified: std::time::UNIX_EPOCH,
+            detected_type: None,
+            raw_hash: None,
+            source_root: None,
+            hash_algorithm: HashAlgorithm::Blake3,
+            inode: None,
+        };
+        fs::write(dir.path().join("keep"), b"same").unwrap();
+        fs::write(dir.path().join("target"), b"same").unwrap();
+        fs::hard_link(dir.path().join("keep"), dir.path().join("alias")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("target"), dir.path().join("link")).unwrap();
+        let files = vec![info("keep"), info("target"), info("alias"), info("link")];
+
+        let topology = classify_group(&files);
+        assert_eq!(topology.members[0], MemberKind::Regular);
+        assert_eq!(topology.members[2], MemberKind::HardlinkOf(0));
+        assert!(matches!(topology.members[3], MemberKind::Symlink(Some(_))));
+        assert_eq!(topology.linked_from(1), Some(3));
+        assert!(topology.same_file(0, 2));
+        assert!(topology.same_file(1, 3));
+
+        let result = perform_action(&files, &DedupAction::Delete, &ActionConfig::default(), false).unwrap();
+
+        // Only the symlink itself is removed; its target and the keeper's alias stay
+        assert!(dir.path().join("target").exists());
+        assert!(dir.path().join("alias").exists());
+        assert!(!dir.path().join("link").exists());
+        assert_eq!(result.operations.len(), 2);
+        assert_eq!(result.operations[0].error_kind, Some("link_target"));
+        assert!(result.operations[1].success);
+    }
+
+    #[test]
+    fn test_touch_keeper_newest() {
+        let dir = tempdir().unwrap();
+        let old = std::time::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let new = std::time::UNIX_EPOCH + Duration::from_secs(2_000_000);
+        let info = |name: &str, modified| {
+            let path = dir.path().join(name);
+            fs::write(&path, b"same").unwrap();
+            filetime::set_file_mtime(&path, FileTime::from_system_time(modified)).unwrap();
+            FileInfo {
+                path,
+                size: 4,
+                hash: "h".to_string(),
+                modified,
+                detected_type: None,
+                raw_hash: None,
+                source_root: None,
+                hash_algorithm: HashAlgorithm::Blake3,
+                inode: None,
+            }
+        };
+        let files = vec![info("keep", old), info("newer", new)];
+        let config = ActionConfig { touch_keeper_newest: true, ..ActionConfig::default() };
+        let mtime = || fs::metadata(dir.path().join("keep")).unwrap().modified().unwrap();
+
+        perform_action(&files, &DedupAction::Delete, &config, true).unwrap();
+        assert_eq!(mtime(), old);
+
+        perform_action(&files, &DedupAction::Delete, &config, false).unwrap();
+        assert!(!dir.path().join("newer").exists());
+        assert_eq!(mtime(), new);
+    }
+
+    #[test]
+    fn test_reflink_keeps_duplicate_until_cloned() {
+        let dir = tempdir().unwrap();
+        let original = dir.path().join("original");
+        let duplicate = dir.path().join("duplicate");
+        fs::write(&original, b"same").unwrap();
+        fs::write(&duplicate, b"same").unwrap();
+        let permissions = fs::metadata(&duplicate).unwrap().permissions();
+
+        let op = create_reflink(&original, &duplicate, &ActionConfig::default(), false).unwrap();
+        assert_eq!(fs::read(&duplicate).unwrap(), b"same");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+        if !is_reflink_supported(dir.path()).unwrap() {
+            assert!(!op.success);
+            assert_eq!(op.error_kind, Some("unsupported"));
+            return;
+        }
+        assert!(op.success);
+        assert!(!are_same_file(&original, &duplicate).unwrap());
+        assert_eq!(fs::metadata(&duplicate).unwrap().permissions(), permissions);
+    }
+
+    #[test]
+    fn test_dedupe_in_place_keeps_duplicate_file() {
+        let dir = tempdir().unwrap();
+        let original = dir.path().join("original");
+        let duplicate = dir.path().join("duplicate");
+        let changed = dir.path().join("changed");
+        fs::write(&original, b"same").unwrap();
+        fs::write(&duplicate, b"same").unwrap();
+        fs::write(&changed, b"diff").unwrap();
+
+        // Whether or not the filesystem supports it, the duplicate stays the same file
+        let before = fs::metadata(&duplicate).unwrap();
+        dedupe_in_place(&original, &duplicate, &ActionConfig::default(), false).unwrap();
+        let after = fs::metadata(&duplicate).unwrap();
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::MetadataExt::ino(&before), std::os::unix::fs::MetadataExt::ino(&after));
+        assert_eq!(before.modified().unwrap(), after.modified().unwrap());
+        assert_eq!(fs::read(&duplicate).unwrap(), b"same");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+
+        let op = dedupe_in_place(&original, &changed, &ActionConfig::default(), false).unwrap();
+        assert!(!op.success);
+        assert_eq!(fs::read(&changed).unwrap(), b"diff");
+    }
+
+    #[test]
+    fn test_select_keeper_directory_priority() {
+        let file = |path: &str| FileInfo {
+            path: PathBuf::from(path),
+            size: 1,
+            hash: "h".to_string(),
+            modified: std::time::UNIX_EPOCH,
+            detected_type: None,
+            raw_hash: None,
+            source_root: None,
+            hash_algorithm: HashAlgorithm::Blake3,
+            inode: None,
+        };
+        let files = [
+            file("/downloads/a.jpg"),
+            file("/imported/a.jpg"),
+            file("/curated/x/a.jpg"),
+            file("/imported/b.jpg"),
+        ];
+        let priority = |dirs: &[&str]| vec![KeepStrategy::DirectoryPriority(
+            dirs.iter().map(PathBuf::from).collect()
+        )];
+
+        assert_eq!(select_keeper(&files, &[]), 0);
+        assert_eq!(select_keeper(&files, &priority(&["/curated", "/imported", "/downloads"])), 2);
+        assert_eq!(select_keeper(&files, &priority(&["/elsewhere", "/imported"])), 1);
+        // No member under any priority dir falls back to the first found
+        assert_eq!(select_keeper(&files, &priority(&["/elsewhere"])), 0);
+    }
+
+    #[test]
+    fn test_select_keeper_prefer_extension() {
+        let file = |path: &str| FileInfo {
+            path: PathBuf::from(path),
+            size: 1,
+            hash: "h".to_string(),
+            modified: std::time::UNIX_EPOCH,
+            detected_type: None,
+            raw_hash: None,
+            source_root: None,
+            hash_algorithm: HashAlgorithm::Blake3,
+            inode: None,
+        };
+        let files = [
+            file("/imported/a.jpeg"),
+            file("/imported/a.JPG"),
+            file("/curated/a.jpeg"),
+            file("/curated/a.png"),
+        ];
+        let prefer = |exts: &[&str]| KeepStrategy::PreferExtension(exts.iter().map(|e| e.to_string()).collect());
+
+        assert_eq!(select_keeper(&files, &[prefer(&["jpg", "png"])]), 1);
+        assert_eq!(select_keeper(&files, &[prefer(&["gif", "png"])]), 3);
+        assert_eq!(select_keeper(&files, &[prefer(&["gif"])]), 0);
+        // Directory priority narrows first; the extension then picks among what's left
+        let chain = [
+            KeepStrategy::DirectoryPriority(vec![PathBuf::from("/curated")]),
+            prefer(&["jpg", "jpeg"]),
+        ];
+        assert_eq!(select_keeper(&files, &chain), 2);
+    }
+
+    #[test]
+    fn test_select_canonical_strategies() {
+        let dir = tempdir().unwrap();
+        let make = |relative: &str, age_secs: i64| {
+            let path = dir.path().join(relative);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, b"same").unwrap();
+            filetime::set_file_mtime(&path, FileTime::from_unix_time(1_700_000_000 - age_secs, 0)).unwrap();
+            FileInfo {
+                size: 4,
+                hash: "h".to_string(),
+                modified: fs::metadata(&path).unwrap().modified().unwrap(),
+                detected_type: None,
+                raw_hash: None,
+                source_root: None,
+                hash_algorithm: HashAlgorithm::Blake3,
+                inode: None,
+                path,
+            }
+        };
+        let files = [
+            make("inbox/photo.jpg", 200),
+            make("archive/2023/photo.jpg", 300),
+            make("a.jpg", 100),
+            make("curated/best-of/photo.jpg", 100),
+        ];
+
+        assert_eq!(select_canonical(&files, &KeepStrategy::FirstFound), 0);
+        // Files 2 and 3 are equally new; the tie goes to the first found
+        assert_eq!(select_canonical(&files, &KeepStrategy::NewestModified), 2);
+        assert_eq!(select_canonical(&files, &KeepStrategy::OldestModified), 1);
+        assert_eq!(select_canonical(&files, &KeepStrategy::ShortestPath), 2);
+        assert_eq!(select_canonical(&files, &KeepStrategy::LongestPath), 3);
+        assert_eq!(select_canonical(&files, &KeepStrategy::PreferDirectory(dir.path().join("archive"))), 1);
+        assert_eq!(select_canonical(&files, &KeepStrategy::PreferDirectory(dir.path().join("elsewhere"))), 0);
+
+        // Later strategies break the ties of earlier ones
+        assert_eq!(select_keeper(&files, &[KeepStrategy::NewestModified, KeepStrategy::LongestPath]), 3);
+
+        assert_eq!("newest".parse(), Ok(KeepStrategy::NewestModified));
+        assert_eq!("dir:/photos".parse(), Ok(KeepStrategy::PreferDirectory(PathBuf::from("/photos"))));
+        assert!("biggest".parse::<KeepStrategy>().is_err());
+        assert!("dir:".parse::<KeepStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_check_safe_delete() {
+        let dir = tempdir().unwrap();
+        let keeper = dir.path().join("keeper");
+        let twin = dir.path().join("twin");
+        let changed = dir.path().join("changed");
+        let alias = dir.path().join("alias");
+        fs::write(&keeper, b"same").unwrap();
+        fs::write(&twin, b"same").unwrap();
+        fs::write(&changed, b"diff").unwrap();
+        fs::hard_link(&keeper, &alias).unwrap();
+
+        let persistent = is_persistent_storage(&keeper);
+        assert_eq!(check_safe_delete(&keeper, &twin, false).is_ok(), persistent);
+        assert!(check_safe_delete(&keeper, &changed, false).is_err());
+        assert!(check_safe_delete(&keeper, &alias, false).is_err());
+    }
+
+    #[test]
+    fn test_with_retries() {
+        let mut calls = 0;
+        let (outcome, attempts) = with_retries(3, || {
+            calls += 1;
+            if calls < 3 {
+                Err(io::Error::from(io::ErrorKind::Interrupted))
+            } else {
+                Ok(())
+            }
+        });
+        assert!(outcome.is_ok());
+        assert_eq!(attempts, 3);
+
+        // Permanent errors are not retried
+        let (outcome, attempts) = with_retries(3, || -> io::Result<()> {
+            Err(io::Error::from(io::ErrorKind::PermissionDenied))
+        });
+        assert!(outcome.is_err());
+        assert_eq!(attempts, 1);
+
+        // Transient errors give up once retries are exhausted
+        let (outcome, attempts) = with_retries(1, || -> io::Result<()> {
+            Err(io::Error::from(io::ErrorKind::TimedOut))
+        });
+        assert!(outcome.is_err());
+        assert_eq!(attempts, 2);
+
+        assert_eq!(retry_delay(1), RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(3), RETRY_BASE_DELAY * 4);
+        assert_eq!(retry_delay(20), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(u32::MAX), RETRY_MAX_DELAY);
+    }
+
+    #[test]
+    fn test_remove_emptied_dirs() {
+        let root = tempdir().unwrap();
+        let nested = root.path().join("a").join("b");
+        let kept = root.path().join("kept");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(&kept).unwrap();
+        let moved = vec![nested.join("x"), kept.join("y")];
+        fs::write(&moved[0], b"x").unwrap();
+        fs::write(&moved[1], b"y").unwrap();
+        fs::write(kept.join("other"), b"z").unwrap();
+        let roots = vec![root.path().to_path_buf()];
+
+        // Dry run reports the cascade without touching anything
+        let would = remove_emptied_dirs(&moved, &roots, true);
+        assert_eq!(would, vec![nested.clone(), root.path().join("a")]);
+        assert!(nested.exists());
+
+        for path in &moved {
+            fs::remove_file(path).unwrap();
+        }
+        let removed = remove_emptied_dirs(&moved, &roots, false);
+        assert_eq!(removed, would);
+        assert!(!root.path().join("a").exists());
+        assert!(kept.exists());
+        assert!(root.path().exists());
+    }
+
+    #[test]
+    fn test_move_file_without_file_name() {
+        let src = tempdir().unwrap();
+        let target = tempdir().unwrap();
+
+        let op = move_file(&src.path().join(".."), "00", target.path(), &ActionConfig::default(), false).unwrap();
+        assert!(!op.success);
+        assert!(op.error.is_some());
+    }
+}
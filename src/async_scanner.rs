@@ -0,0 +1,299 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::Semaphore;
+
+use crate::{DedupResult, FileInfo, HashAlgorithm, SkipReason};
+use crate::utils::{hash_file, inode, DEFAULT_HASH_LEN};
+
+/// Progress of an `AsyncScanner` run, sent as it happens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanProgress {
+    /// A file passed the filters and will be counted
+    FileDiscovered { path: PathBuf },
+    /// A file shares its size with another and was hashed
+    FileHashed { path: PathBuf, hash: String },
+    /// The result is ready
+    Complete,
+}
+
+/// A scanner for tokio applications that never blocks the calling task
+///
+/// Directories are walked and files stat'ed with `tokio::fs`, and hashing runs
+/// on the blocking thread pool, a few files at a time. It covers the size and
+/// extension filters, hash length and hash algorithm; the other `Scanner`
+/// options (exclude patterns, categories, normalization, sharding and so on)
+/// are only available there. Symlinks are not followed. As with `Scanner`,
+/// subdirectories that can't be read are left out; files that can't be read
+/// are counted under `SkipReason::Unreadable`.
+#[derive(Debug, Clone)]
+pub struct AsyncScanner {
+    min_size: u64,
+    max_size: Option<u64>,
+    include_extensions: HashSet<String>,
+    exclude_extensions: HashSet<String>,
+    hash_algorithm: HashAlgorithm,
+    hash_len: usize,
+    /// Files hashed at the same time on the blocking pool
+    max_concurrent_hashes: usize,
+    progress: Option<Sender<ScanProgress>>,
+}
+
+impl Default for AsyncScanner {
+    fn default() -> Self {
+        Self {
+            min_size: 0,
+            max_size: None,
+            include_extensions: HashSet::new(),
+            exclude_extensions: HashSet::new(),
+            hash_algorithm: HashAlgorithm::default(),
+            hash_len: DEFAULT_HASH_LEN,
+            max_concurrent_hashes: std::thread::available_parallelism().map_or(4, usize::from),
+            progress: None,
+        }
+    }
+}
+
+impl AsyncScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_min_size(&mut self, size: u64) {
+        self.min_size = size;
+    }
+
+    pub fn set_max_size(&mut self, size: u64) {
+        self.max_size = Some(size);
+    }
+
+    pub fn set_include_extensions(&mut self, extensions: Vec<String>) {
+        self.include_extensions = extensions.into_iter()
+            .map(|ext| ext.to_lowercase())
+            .collect();
+    }
+
+    pub fn set_exclude_extensions(&mut self, extensions: Vec<String>) {
+        self.exclude_extensions = extensions.into_iter()
+            .map(|ext| ext.to_lowercase())
+            .collect();
+    }
+
+    pub fn set_hash_algorithm(&mut self, algorithm: HashAlgorithm) {
+        self.hash_algorithm = algorithm;
+    }
+
+    /// Number of hash output bytes per hash
+    pub fn set_hash_len(&mut self, len: usize) {
+        self.hash_len = len;
+    }
+
+    /// How many files may be hashed at once (at least 1); defaults to the number of CPUs
+    pub fn set_max_concurrent_hashes(&mut self, count: usize) {
+        self.max_concurrent_hashes = count.max(1);
+    }
+
+    /// Send progress updates to this channel; a closed channel is ignored
+    ///
+    /// Each update is awaited, so the scan pauses whenever a bounded channel is
+    /// full. Drain the receiver from another task while the scan runs, or give it
+    /// room for every update if it is only read afterwards.
+    pub fn set_progress(&mut self, sender: Sender<ScanProgress>) {
+        self.progress = Some(sender);
+    }
+
+    /// Scan directories for duplicate files
+    ///
+    /// Only files sharing their size with another file are hashed, a few files
+    /// at a time (see `set_max_concurrent_hashes`) on tokio's blocking pool.
+    /// Must be called from within a tokio runtime.
+    pub async fn scan_directories(&self, directories: &[PathBuf]) -> Result<DedupResult> {
+        let mut result = DedupResult::new();
+        result.hash_algorithm = self.hash_algorithm;
+
+        let mut by_size: HashMap<u64, Vec<(PathBuf, usize)>> = HashMap::new();
+        for (root, directory) in directories.iter().enumerate() {
+            for (path, size) in self.collect_files(directory, &mut result).await? {
+                self.send(ScanProgress::FileDiscovered { path: path.clone() }).await;
+                by_size.entry(size).or_default().push((path, root));
+            }
+        }
+
+        // Permits are taken before spawning, so only a few hashes wait on the blocking pool
+        let permits = Arc::new(Semaphore::new(self.max_concurrent_hashes));
+        let mut pending = Vec::new();
+        for (size, files) in by_size {
+            if files.len() < 2 {
+                result.total_files += 1;
+                result.total_size += size;
+                continue;
+            }
+            for (path, root) in files {
+                let permit = permits.clone().acquire_owned().await.context("Hashing stopped")?;
+                let (algorithm, len) = (self.hash_algorithm, self.hash_len);
+                let hashing = tokio::task::spawn_blocking(move || {
+                    let hash = hash_file(&path, algorithm, len);
+                    drop(permit);
+                    (path, hash)
+                });
+                pending.push((hashing, root));
+            }
+        }
+
+        for (hashing, root) in pending {
+            let (path, hash) = hashing.await.context("Hashing task failed")?;
+            let (Ok(hash), Ok(metadata)) = (hash, tokio::fs::metadata(&path).await) else {
+                *result.skipped.entry(SkipReason::Unreadable).or_default() += 1;
+                continue;
+            };
+            self.send(ScanProgress::FileHashed { path: path.clone(), hash: hash.clone() }).await;
+            result.add_file(FileInfo {
+                path,
+                size: metadata.len(),
+                hash,
+                modified: metadata.modified().unwrap_or(std::time::UNIX_EPOCH),
+                detected_type: None,
+                raw_hash: None,
+                source_root: Some(directories[root].clone()),
+                hash_algorithm: self.hash_algorithm,
+                inode: inode(&metadata),
+            })?;
+        }
+
+        result.filter_duplicates();
+        self.send(ScanProgress::Complete).await;
+        Ok(result)
+    }
+
+    /// Every regular file under a directory that passes the filters, with its size
+    ///
+    /// Only the directory itself must be readable; unreadable subdirectories are left out.
+    async fn collect_files(&self, directory: &Path, result: &mut DedupResult) -> Result<Vec<(PathBuf, u64)>> {
+        let mut files = Vec::new();
+        let mut root = Some(tokio::fs::read_dir(directory).await
+            .with_context(|| format!("Failed to read directory {}", directory.display()))?);
+        let mut pending = vec![directory.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            let mut entries = match root.take() {
+                Some(entries) => entries,
+                None => match tokio::fs::read_dir(&dir).await {
+                    Ok(entries) => entries,
+                    Err(_) => continue,
+                },
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+                let Ok(metadata) = tokio::fs::symlink_metadata(&path).await else {
+                    *result.skipped.entry(SkipReason::Unreadable).or_default() += 1;
+                    continue;
+                };
+                if metadata.is_dir() {
+                    pending.push(path);
+                } else if metadata.is_file() {
+                    match self.filter_file(&path, metadata.len()) {
+                        Ok(()) => files.push((path, metadata.len())),
+                        Err(reason) => *result.skipped.entry(reason).or_default() += 1,
+                    }
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    fn filter_file(&self, path: &Path, size: u64) -> Result<(), SkipReason> {
+        if size < self.min_size {
+            return Err(SkipReason::BelowMinSize);
+        }
+        if self.max_size.is_some_and(|max_size| size > max_size) {
+            return Err(SkipReason::AboveMaxSize);
+        }
+        let ext = path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+        if !self.include_extensions.is_empty() && !ext.as_ref().is_some_and(|ext| self.include_extensions.contains(ext)) {
+            return Err(SkipReason::NotIncluded);
+        }
+        if ext.is_some_and(|ext| self.exclude_extensions.contains(&ext)) {
+            return Err(SkipReason::Excluded);
+        }
+        Ok(())
+    }
+
+    async fn send(&self, progress: ScanProgress) {
+        if let Some(sender) = &self.progress {
+            let _ = sender.send(progress).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_async_scan_matches_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("a.txt"), b"same content").unwrap();
+        fs::write(dir.path().join("sub/b.txt"), b"same content").unwrap();
+        fs::write(dir.path().join("c.txt"), b"diff content").unwrap();
+        fs::write(dir.path().join("d.log"), b"same content").unwrap();
+        fs::write(dir.path().join("lonely.txt"), b"unique").unwrap();
+
+        let mut scanner = AsyncScanner::new();
+        scanner.set_exclude_extensions(vec!["LOG".to_string()]);
+        let (sender, mut receiver) = mpsc::channel(64);
+        scanner.set_progress(sender);
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let result = runtime.block_on(scanner.scan_directories(&[dir.path().to_path_buf()])).unwrap();
+        drop(scanner);
+
+        assert_eq!(result.total_files, 4);
+        assert_eq!(result.duplicates.len(), 1);
+        let group = result.duplicates.values().next().unwrap();
+        assert_eq!(group.len(), 2);
+        assert!(group.iter().all(|file| file.source_root.as_deref() == Some(dir.path())));
+        assert_eq!(result.skipped.get(&SkipReason::Excluded), Some(&1));
+
+        let mut updates = Vec::new();
+        while let Ok(update) = receiver.try_recv() {
+            updates.push(update);
+        }
+        let discovered = updates.iter().filter(|u| matches!(u, ScanProgress::FileDiscovered { .. })).count();
+        let hashed = updates.iter().filter(|u| matches!(u, ScanProgress::FileHashed { .. })).count();
+        assert_eq!((discovered, hashed), (4, 3));
+        assert_eq!(updates.last(), Some(&ScanProgress::Complete));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_async_scan_skips_unreadable_entries() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Permissions don't stop root from reading anything
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("locked")).unwrap();
+        fs::write(dir.path().join("locked/a.txt"), b"same content").unwrap();
+        fs::write(dir.path().join("b.txt"), b"same content").unwrap();
+        fs::write(dir.path().join("c.txt"), b"same content").unwrap();
+        fs::write(dir.path().join("secret.txt"), b"same content").unwrap();
+        fs::set_permissions(dir.path().join("secret.txt"), fs::Permissions::from_mode(0o000)).unwrap();
+        fs::set_permissions(dir.path().join("locked"), fs::Permissions::from_mode(0o000)).unwrap();
+
+        let mut scanner = AsyncScanner::new();
+        scanner.set_max_concurrent_hashes(1);
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let result = runtime.block_on(scanner.scan_directories(&[dir.path().to_path_buf()]));
+        fs::set_permissions(dir.path().join("locked"), fs::Permissions::from_mode(0o755)).unwrap();
+        let result = result.unwrap();
+
+        assert_eq!(result.skipped.get(&SkipReason::Unreadable), Some(&1));
+        assert_eq!(result.duplicates.values().next().map(Vec::len), Some(2));
+    }
+}
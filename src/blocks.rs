@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use anyhow::{bail, Context, Result};
+
+use crate::CancelToken;
+
+/// A block content found more than once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepeatedBlock {
+    pub hash: String,
+    pub count: u64,
+    /// Byte offset of the first occurrence
+    pub first_offset: u64,
+}
+
+/// Duplicate fixed-size blocks within a single file or block device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockReport {
+    pub block_size: usize,
+    /// Blocks read, including a shorter final block
+    pub total_blocks: u64,
+    pub unique_blocks: u64,
+    /// The most repeated blocks, most frequent first
+    pub top: Vec<RepeatedBlock>,
+}
+
+impl BlockReport {
+    /// How many times smaller the data would be if every repeated block were stored once
+    pub fn dedup_ratio(&self) -> f64 {
+        if self.unique_blocks == 0 {
+            return 1.0;
+        }
+        self.total_blocks as f64 / self.unique_blocks as f64
+    }
+}
+
+/// Hash every `block_size` block of a file or block device and count repeats
+///
+/// Stops early, with the blocks read so far, once `cancel` is set. `top` limits
+/// how many repeated blocks are listed.
+pub fn analyze_blocks(path: &Path, block_size: usize, top: usize, cancel: &CancelToken) -> Result<BlockReport> {
+    if block_size == 0 {
+        bail!("block size must be at least 1 byte");
+    }
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = BufReader::with_capacity(block_size.max(1 << 20), file);
+
+    let mut seen: HashMap<blake3::Hash, (u64, u64)> = HashMap::new();
+    let mut buffer = vec![0u8; block_size];
+    let mut offset = 0u64;
+    let mut total_blocks = 0u64;
+
+    while !cancel.is_cancelled() {
+        let len = read_block(&mut reader, &mut buffer)
+            .with_context(|| format!("Failed to read {} at offset {}", path.display(), offset))?;
+        if len == 0 {
+            break;
+        }
+        let entry = seen.entry(blake3::hash(&buffer[..len])).or_insert((0, offset));
+        entry.0 += 1;
+        total_blocks += 1;
+        offset += len as u64;
+    }
+
+    let mut repeated: Vec<RepeatedBlock> = seen.iter()
+        .filter(|(_, (count, _))| *count > 1)
+        .map(|(hash, (count, first_offset))| RepeatedBlock {
+            hash: hash.to_hex().to_string(),
+            count: *count,
+            first_offset: *first_offset,
+        })
+        .collect();
+    repeated.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.first_offset.cmp(&b.first_offset)));
+    repeated.truncate(top);
+
+    Ok(BlockReport {
+        block_size,
+        total_blocks,
+        unique_blocks: seen.len() as u64,
+        top: repeated,
+    })
+}
+
+/// Fill the buffer as far as the data allows, returning how many bytes were read
+fn read_block<R: Read>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_repeated_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk.img");
+        let data = [&[0u8; 4][..], b"abcd", &[0; 4], b"wxyz", &[0; 4], b"ab"].concat();
+        fs::write(&path, data).unwrap();
+
+        let report = analyze_blocks(&path, 4, 10, &CancelToken::new()).unwrap();
+        assert_eq!(report.total_blocks, 6);
+        assert_eq!(report.unique_blocks, 4);
+        assert_eq!(report.top.len(), 1);
+        assert_eq!(report.top[0].count, 3);
+        assert_eq!(report.top[0].first_offset, 0);
+        assert_eq!(report.dedup_ratio(), 1.5);
+    }
+}
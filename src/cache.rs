@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::HashAlgorithm;
+use crate::envelope::{load_json, save_json_atomic};
+use crate::utils::DEFAULT_HASH_LEN;
+
+/// A file's content hash together with the metadata it was computed for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedFileInfo {
+    pub hash: String,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// Content hashes from earlier scans, keyed by canonical path
+///
+/// A cached hash is reused as long as the file keeps the size and modification
+/// time it had when hashed. All entries share one hash algorithm and length;
+/// scanning with different ones starts the cache over.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanCache {
+    hash_algorithm: HashAlgorithm,
+    hash_len: usize,
+    entries: HashMap<PathBuf, CachedFileInfo>,
+}
+
+impl Default for ScanCache {
+    fn default() -> Self {
+        Self {
+            hash_algorithm: HashAlgorithm::default(),
+            hash_len: DEFAULT_HASH_LEN,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl ScanCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a cache written by `save`, or start an empty one if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+        load_json(path, "scan cache")
+    }
+
+    /// Write the cache, replacing the previous file only once the new one is complete
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_json_atomic(path, self, "scan cache")
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drop every entry unless they were hashed with this algorithm and length
+    pub fn use_hash(&mut self, algorithm: HashAlgorithm, len: usize) {
+        if (self.hash_algorithm, self.hash_len) != (algorithm, len) {
+            self.entries.clear();
+            self.hash_algorithm = algorithm;
+            self.hash_len = len;
+        }
+    }
+
+    /// The cached hash of a file, if its size and modification time still match
+    pub fn lookup(&self, path: &Path, size: u64, modified: SystemTime) -> Option<&str> {
+        self.entries
+            .get(path)
+            .filter(|entry| entry.size == size && entry.modified == modified)
+            .map(|entry| entry.hash.as_str())
+    }
+
+    pub fn insert(&mut self, path: PathBuf, entry: CachedFileInfo) {
+        self.entries.insert(path, entry);
+    }
+
+    /// Remove entries for files that no longer exist, returning how many were removed
+    pub fn evict_missing(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|path, _| fs::symlink_metadata(path).is_ok());
+        before - self.entries.len()
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use anyhow::{bail, Result};
+
+/// Built-in categories and the detected MIME types they cover; a trailing `*` matches any suffix
+const BUILTIN: &[(&str, &[&str])] = &[
+    ("archive", &[
+        "application/zip", "application/x-tar", "application/gzip", "application/x-bzip2", "application/x-xz",
+        "application/zstd", "application/x-7z-compressed", "application/vnd.rar", "application/x-lzip",
+        "application/x-compress", "application/x-cpio", "application/vnd.ms-cab-compressed",
+    ]),
+    ("audio", &["audio/*"]),
+    ("document", &[
+        "application/pdf", "application/msword", "application/rtf", "application/postscript",
+        "application/epub+zip", "application/x-mobipocket-ebook", "application/vnd.ms-excel",
+        "application/vnd.ms-powerpoint", "application/vnd.oasis.opendocument.*",
+        "application/vnd.openxmlformats-officedocument.*",
+    ]),
+    ("executable", &[
+        "application/x-executable", "application/x-mach-binary", "application/vnd.microsoft.portable-executable",
+        "application/wasm", "application/java",
+    ]),
+    ("font", &["font/*", "application/font-sfnt", "application/font-woff"]),
+    ("image", &["image/*"]),
+    ("video", &["video/*"]),
+];
+
+/// Named content categories, each a set of MIME type patterns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryMap(BTreeMap<String, Vec<String>>);
+
+impl Default for CategoryMap {
+    fn default() -> Self {
+        Self(
+            BUILTIN
+                .iter()
+                .map(|(name, mimes)| (name.to_string(), mimes.iter().map(|mime| mime.to_string()).collect()))
+                .collect(),
+        )
+    }
+}
+
+impl CategoryMap {
+    /// Add a category, or replace a built-in one, from a `name=mime,mime/*` definition
+    pub fn define(&mut self, definition: &str) -> Result<()> {
+        let Some((name, mimes)) = definition.split_once('=') else {
+            bail!("category definition '{}' should look like name=type/subtype,type/*", definition);
+        };
+        let name = name.trim().to_lowercase();
+        let mimes: Vec<String> = mimes.split(',').map(|mime| mime.trim().to_lowercase()).filter(|mime| !mime.is_empty()).collect();
+        if name.is_empty() || mimes.is_empty() {
+            bail!("category definition '{}' needs a name and at least one MIME type", definition);
+        }
+        self.0.insert(name, mimes);
+        Ok(())
+    }
+
+    /// Category names with their MIME type patterns, alphabetically
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.0.iter().map(|(name, mimes)| (name.as_str(), mimes.as_slice()))
+    }
+
+    /// A filter accepting files in any of the named categories
+    pub fn filter(&self, names: &[String]) -> Result<CategoryFilter> {
+        let mut patterns = Vec::new();
+        for name in names {
+            match self.0.get(&name.to_lowercase()) {
+                Some(mimes) => patterns.extend(mimes.iter().cloned()),
+                None => bail!(
+                    "unknown category '{}' (known: {})",
+                    name,
+                    self.0.keys().cloned().collect::<Vec<_>>().join(", ")
+                ),
+            }
+        }
+        Ok(CategoryFilter { patterns })
+    }
+}
+
+/// MIME type patterns of the selected categories
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryFilter {
+    patterns: Vec<String>,
+}
+
+impl CategoryFilter {
+    /// Whether a detected MIME type falls in a selected category; undetected files never do
+    pub fn matches(&self, mime: Option<&str>) -> bool {
+        let Some(mime) = mime else { return false };
+        self.patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => mime.starts_with(prefix),
+            None => mime == pattern,
+        })
+    }
+
+    /// Sniff a file's type from its magic bytes and check it
+    pub fn matches_file(&self, path: &Path) -> bool {
+        self.matches(detect_mime_type(path).as_deref())
+    }
+}
+
+/// MIME type detected from a file's magic bytes
+pub fn detect_mime_type(path: &Path) -> Option<String> {
+    infer::get_from_path(path)
+        .ok()
+        .flatten()
+        .map(|kind| kind.mime_type().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category_filter() {
+        let mut categories = CategoryMap::default();
+        let filter = categories.filter(&["Image".to_string(), "document".to_string()]).unwrap();
+        assert!(filter.matches(Some("image/png")));
+        assert!(filter.matches(Some("application/pdf")));
+        assert!(filter.matches(Some("application/vnd.oasis.opendocument.text")));
+        assert!(!filter.matches(Some("video/mp4")));
+        assert!(!filter.matches(None));
+        assert!(categories.filter(&["spreadsheets".to_string()]).is_err());
+
+        categories.define("image=image/png").unwrap();
+        let filter = categories.filter(&["image".to_string()]).unwrap();
+        assert!(filter.matches(Some("image/png")));
+        assert!(!filter.matches(Some("image/jpeg")));
+        assert!(categories.define("image").is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context};
+use fastcdc::v2020::StreamCDC;
+use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::utils::calculate_percentage;
+
+/// Chunk size bounds and reporting threshold for content-defined chunking
+#[derive(Debug, Clone)]
+pub struct ChunkConfig {
+    pub min_size: u32,
+    pub avg_size: u32,
+    pub max_size: u32,
+    /// Only report pairs sharing at least this percentage of the smaller file
+    pub min_overlap: f64,
+    /// Only compare files whose sizes are within this percentage of each other
+    pub size_tolerance: Option<f64>,
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        Self {
+            min_size: 16 * 1024,
+            avg_size: 64 * 1024,
+            max_size: 256 * 1024,
+            min_overlap: 50.0,
+            size_tolerance: None,
+        }
+    }
+}
+
+/// Two files that share content-defined chunks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkOverlap {
+    pub a: PathBuf,
+    pub b: PathBuf,
+    pub shared_bytes: u64,
+    /// Shared bytes as a percentage of the smaller file
+    pub overlap_percent: f64,
+}
+
+/// Distinct chunks of a file, keyed by their BLAKE3 hash
+struct ChunkedFile {
+    path: PathBuf,
+    size: u64,
+    chunks: HashMap<[u8; 32], u64>,
+}
+
+/// Split a file into content-defined chunks
+fn chunk_file(path: &Path, config: &ChunkConfig) -> Result<ChunkedFile> {
+    let file = fs::File::open(path)
+        .with_context(|| format!("Failed to open file {}", path.display()))?;
+
+    let mut chunks = HashMap::new();
+    let mut size = 0;
+
+    for chunk in StreamCDC::new(file, config.min_size, config.avg_size, config.max_size) {
+        let chunk = chunk.with_context(|| format!("Failed to read file {}", path.display()))?;
+        size += chunk.length as u64;
+        chunks.insert(*blake3::hash(&chunk.data).as_bytes(), chunk.length as u64);
+    }
+
+    Ok(ChunkedFile {
+        path: path.to_path_buf(),
+        size,
+        chunks,
+    })
+}
+
+/// Whether two sizes differ by at most `percent` of the larger one
+pub fn within_size_tolerance(a: u64, b: u64, percent: f64) -> bool {
+    a.abs_diff(b) as f64 <= a.max(b) as f64 * percent / 100.0
+}
+
+/// Keep only files with at least one other file within `percent` of their size
+///
+/// Unlike exact-size bucketing, re-encoded copies whose sizes differ slightly
+/// stay candidates for the similarity check.
+pub fn size_tolerance_candidates(mut files: Vec<(PathBuf, u64)>, percent: f64) -> Vec<PathBuf> {
+    files.sort_by_key(|(_, size)| *size);
+    // Sorted by size, a file's closest sizes are its neighbours
+    (0..files.len())
+        .filter(|&i| {
+            let near = |j: usize| within_size_tolerance(files[i].1, files[j].1, percent);
+            (i > 0 && near(i - 1)) || (i + 1 < files.len() && near(i + 1))
+        })
+        .map(|i| files[i].0.clone())
+        .collect()
+}
+
+/// Find pairs of files that share a significant portion of their chunks
+///
+/// Byte-identical pairs are left out since whole-file hashing already reports them.
+pub fn find_shared_chunks(files: &[PathBuf], config: &ChunkConfig) -> Result<Vec<ChunkOverlap>> {
+    let mut sized = Vec::with_capacity(files.len());
+    for path in files {
+        let size = fs::metadata(path)
+            .with_context(|| format!("Failed to get metadata for {}", path.display()))?
+            .len();
+        if size >= config.min_size as u64 {
+            sized.push((path.clone(), size));
+        }
+    }
+    let files = match config.size_tolerance {
+        Some(percent) => size_tolerance_candidates(sized, percent),
+        None => sized.into_iter().map(|(path, _)| path).collect(),
+    };
+
+    let chunked: Vec<ChunkedFile> = files
+        .par_iter()
+        .map(|path| chunk_file(path, config))
+        .collect::<Result<_>>()?;
+
+    let mut owners: HashMap<&[u8; 32], Vec<usize>> = HashMap::new();
+    for (index, file) in chunked.iter().enumerate() {
+        for hash in file.chunks.keys() {
+            owners.entry(hash).or_default().push(index);
+        }
+    }
+
+    let mut shared: HashMap<(usize, usize), u64> = HashMap::new();
+    for (hash, indices) in &owners {
+        if indices.len() < 2 {
+            continue;
+        }
+        let length = chunked[indices[0]].chunks[*hash];
+        for (i, &a) in indices.iter().enumerate() {
+            for &b in &indices[i + 1..] {
+                *shared.entry((a, b)).or_default() += length;
+            }
+        }
+    }
+
+    let mut overlaps: Vec<ChunkOverlap> = shared
+        .into_iter()
+        .filter_map(|((a, b), shared_bytes)| {
+            let (a, b) = (&chunked[a], &chunked[b]);
+            if config.size_tolerance.is_some_and(|percent| !within_size_tolerance(a.size, b.size, percent)) {
+                return None;
+            }
+            let identical = a.size == b.size
+                && a.chunks.len() == b.chunks.len()
+                && a.chunks.keys().collect::<HashSet<_>>() == b.chunks.keys().collect::<HashSet<_>>();
+            if identical {
+                return None;
+            }
+
+            let overlap_percent = calculate_percentage(shared_bytes, a.size.min(b.size));
+            (overlap_percent >= config.min_overlap).then(|| ChunkOverlap {
+                a: a.path.clone(),
+                b: b.path.clone(),
+                shared_bytes,
+                overlap_percent,
+            })
+        })
+        .collect();
+
+    overlaps.sort_by(|x, y| {
+        y.shared_bytes.cmp(&x.shared_bytes)
+            .then_with(|| x.a.cmp(&y.a))
+            .then_with(|| x.b.cmp(&y.b))
+    });
+
+    Ok(overlaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Deterministic pseudo-random bytes so chunk boundaries are realistic
+    fn noise(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_shared_chunks() {
+        let dir = tempdir().unwrap();
+        let common = noise(1, 2 * 1024 * 1024);
+
+        let mut a = noise(2, 100_000);
+        a.extend_from_slice(&common);
+        let mut b = noise(3, 50_000);
+        b.extend_from_slice(&common);
+
+        let paths = vec![
+            dir.path().join("a.img"),
+            dir.path().join("b.img"),
+            dir.path().join("c.img"),
+            dir.path().join("a-copy.img"),
+        ];
+        fs::write(&paths[0], &a).unwrap();
+        fs::write(&paths[1], &b).unwrap();
+        fs::write(&paths[2], noise(4, 1024 * 1024)).unwrap();
+        fs::write(&paths[3], &a).unwrap();
+
+        let overlaps = find_shared_chunks(&paths, &ChunkConfig::default()).unwrap();
+
+        // a/b and a-copy/b share the common region; a/a-copy are identical and skipped
+        assert_eq!(overlaps.len(), 2);
+        for overlap in &overlaps {
+            assert!(overlap.b == paths[1] || overlap.a == paths[1]);
+            assert!(overlap.overlap_percent > 80.0);
+        }
+
+        // a and b differ by about 2%; a 1% tolerance keeps them apart
+        let strict = ChunkConfig { size_tolerance: Some(1.0), ..ChunkConfig::default() };
+        assert!(find_shared_chunks(&paths, &strict).unwrap().is_empty());
+        let loose = ChunkConfig { size_tolerance: Some(5.0), ..ChunkConfig::default() };
+        assert_eq!(find_shared_chunks(&paths, &loose).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_size_tolerance_candidates() {
+        let files = vec![
+            (PathBuf::from("movie.mkv"), 1_000_000),
+            (PathBuf::from("reencode.mkv"), 1_030_000),
+            (PathBuf::from("trailer.mkv"), 200_000),
+            (PathBuf::from("other.mkv"), 1_500_000),
+        ];
+        assert_eq!(
+            size_tolerance_candidates(files, 5.0),
+            [PathBuf::from("movie.mkv"), PathBuf::from("reencode.mkv")]
+        );
+        assert!(within_size_tolerance(100, 95, 5.0));
+        assert!(!within_size_tolerance(100, 94, 5.0));
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+
+use crate::FileInfo;
+use crate::envelope::{load_json, save_json};
+
+/// Lookup table of hashed files, written by `dedup hash` for later matching
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashTable {
+    pub roots: Vec<PathBuf>,
+    pub files: Vec<FileInfo>,
+}
+
+impl HashTable {
+    pub fn new(roots: Vec<PathBuf>, files: Vec<FileInfo>) -> Self {
+        Self { roots, files }
+    }
+
+    /// Write the table to a JSON file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_json(path, self, "hash table")
+    }
+
+    /// Read a table previously written with `save`
+    pub fn load(path: &Path) -> Result<Self> {
+        load_json(path, "hash table")
+    }
+}
+
+/// A file on the B side together with its A-side twins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMatch {
+    pub file: FileInfo,
+    pub twins: Vec<FileInfo>,
+}
+
+/// Result of matching one hash table against another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossmatchReport {
+    pub matches: Vec<CrossMatch>,
+    pub total_files: usize,
+    pub duplicate_size: u64,
+}
+
+impl CrossmatchReport {
+    /// Write the report to a JSON file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_json(path, self, "report")
+    }
+}
+
+/// Find files in `b` whose content already exists in `a`
+pub fn crossmatch(a: &HashTable, b: &HashTable) -> CrossmatchReport {
+    let mut index: HashMap<(u64, &str), Vec<&FileInfo>> = HashMap::new();
+    for file in &a.files {
+        index.entry((file.size, file.hash.as_str())).or_default().push(file);
+    }
+
+    let mut matches: Vec<CrossMatch> = b.files
+        .iter()
+        .filter_map(|file| {
+            index.get(&(file.size, file.hash.as_str())).map(|twins| CrossMatch {
+                file: file.clone(),
+                twins: twins.iter().map(|&twin| twin.clone()).collect(),
+            })
+        })
+        .collect();
+
+    matches.sort_by(|x, y| x.file.path.cmp(&y.file.path));
+
+    let duplicate_size = matches.iter().map(|m| m.file.size).sum();
+
+    CrossmatchReport {
+        matches,
+        total_files: b.files.len(),
+        duplicate_size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn file(path: &str, size: u64, hash: &str) -> FileInfo {
+        FileInfo::fixture(path, size, hash)
+    }
+
+    #[test]
+    fn test_crossmatch_round_trip() {
+        let dir = tempdir().unwrap();
+        let a = HashTable::new(
+            vec![PathBuf::from("/a")],
+            vec![file("/a/one", 3, "aaa"), file("/a/two", 3, "aaa"), file("/a/three", 5, "bbb")],
+        );
+        let b = HashTable::new(
+            vec![PathBuf::from("/b")],
+            vec![file("/b/copy", 3, "aaa"), file("/b/new", 5, "ccc")],
+        );
+
+        let path = dir.path().join("a.json");
+        a.save(&path).unwrap();
+        let a = HashTable::load(&path).unwrap();
+
+        let report = crossmatch(&a, &b);
+        assert_eq!(report.total_files, 2);
+        assert_eq!(report.matches.len(), 1);
+        assert_eq!(report.matches[0].file.path, PathBuf::from("/b/copy"));
+        assert_eq!(report.matches[0].twins.len(), 2);
+        assert_eq!(report.duplicate_size, 3);
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use flate2::read::MultiGzDecoder;
+
+/// Size of the uncompressed content, from the gzip trailer
+///
+/// The trailer stores the size modulo 2^32, so this is exact for content
+/// under 4 GiB and for multi-member files only covers the last member.
+pub fn decompressed_size(path: &Path) -> Option<u64> {
+    let mut file = fs::File::open(path).ok()?;
+    if file.metadata().ok()?.len() < 18 {
+        return None;
+    }
+    file.seek(SeekFrom::End(-4)).ok()?;
+    let mut trailer = [0; 4];
+    file.read_exact(&mut trailer).ok()?;
+    Some(u32::from_le_bytes(trailer) as u64)
+}
+
+/// Reader over a compressed file's uncompressed content
+pub fn decoder(file: fs::File) -> impl Read {
+    MultiGzDecoder::new(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[test]
+    fn test_decompressed_size_and_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt.gz");
+        let mut encoder = GzEncoder::new(fs::File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(b"hello hello hello").unwrap();
+        encoder.finish().unwrap();
+
+        assert_eq!(decompressed_size(&path), Some(17));
+        let mut content = Vec::new();
+        decoder(fs::File::open(&path).unwrap()).read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"hello hello hello");
+    }
+}
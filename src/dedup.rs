@@ -1,26 +1,71 @@
-use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::io::Write;
+use anyhow::{bail, Context, Result};
 use console::style;
 use humansize::{format_size, DECIMAL};
+use serde::{Serialize, Deserialize};
 
-use crate::{DedupResult, DedupAction};
-use crate::actions::{perform_action, ActionResult};
+use crate::{is_empty_group, is_nul_padded_group, DedupResult, DedupAction, FileInfo, KeepStrategy};
+use crate::utils::{allocation, are_same_file, available_space, device_id, filesystem_block_size, hash_file, is_compressed_file, short_hash};
+use crate::actions::{perform_action, remove_emptied_dirs, select_keeper, ActionConfig, ActionResult, FileOperation, GroupOrder};
+use crate::revert::{revert, RevertLog};
+
+/// Perform deduplication like `perform_deduplication`, also returning a log of
+/// the deletes and moves done so they can be undone with `DedupAction::Revert`
+///
+/// Operations are appended to `config.revert_log` as well when one is set.
+pub fn perform_deduplication_with_revert(
+    scan_result: &DedupResult,
+    action: DedupAction,
+    config: &ActionConfig,
+    dry_run: bool,
+) -> Result<(ActionResult, RevertLog)> {
+    let log = config.revert_log.clone().unwrap_or_default();
+    let config = ActionConfig { revert_log: Some(log.clone()), ..config.clone() };
+    let result = perform_deduplication(scan_result, action, &config, dry_run)?;
+    Ok((result, log))
+}
 
 /// Perform deduplication on the scan results
+///
+/// Returns every operation performed, so callers can tell whether any failed.
 pub fn perform_deduplication(
     scan_result: &DedupResult,
     action: DedupAction,
+    config: &ActionConfig,
     dry_run: bool,
-) -> Result<()> {
+) -> Result<ActionResult> {
     if matches!(action, DedupAction::List) {
         // List action is already handled in the main display function
-        return Ok(());
+        return Ok(ActionResult::new());
+    }
+
+    if let DedupAction::Revert(log_path) = &action {
+        let log = RevertLog::load(log_path)?;
+        println!();
+        println!("{}", style(format!("↩️ Reverting {} recorded operations...", log.entries().len())).cyan().bold());
+        let result = revert(&log, config.revert_backup_dir.as_deref(), dry_run);
+        result.print_summary();
+        return Ok(result);
     }
 
+    let mut json_errors = match &config.json_errors {
+        Some(path) => Some(std::io::BufWriter::new(fs::File::create(path)
+            .with_context(|| format!("Failed to create error log {}", path.display()))?)),
+        None => None,
+    };
+
     let action_name = match action {
         DedupAction::Delete => "Deleting",
         DedupAction::Move(_) => "Moving",
         DedupAction::Hardlink => "Creating hardlinks for",
         DedupAction::Symlink => "Creating symlinks for",
+        DedupAction::Copy(_) => "Copying",
+        DedupAction::Reflink => "Creating reflinks for",
+        DedupAction::DedupInPlace => "Sharing storage of",
+        DedupAction::Revert(_) => "Reverting",
         DedupAction::List => "Listing", // This shouldn't happen due to the check above
     };
 
@@ -32,55 +77,337 @@ pub fn perform_deduplication(
         println!("{}", style(format!("🔄 {} duplicate files...", action_name)).cyan().bold());
     }
 
+    // Groups whose extra copies are all hard links to one another need nothing done
+    let (mut groups, linked): (Vec<(&String, &Vec<FileInfo>)>, Vec<_>) = scan_result.duplicates
+        .iter()
+        .filter(|(_, files)| files.len() > 1)
+        .partition(|(_, files)| is_empty_group(files) || scan_result.group_duplicate_count(files) > 0);
+
+    let mut outside_prefix = 0;
+    if let Some(prefix) = &config.hash_prefix {
+        let before = groups.len();
+        groups.retain(|(_, files)| files[0].hash.starts_with(prefix.as_str()));
+        outside_prefix = before - groups.len();
+    }
+
+    if config.order == GroupOrder::SizeDesc {
+        groups.sort_by(|a, b| group_waste(b.1).cmp(&group_waste(a.1)).then_with(|| a.0.cmp(b.0)));
+    }
+
+    // Running out of space halfway would leave some groups moved and others not
+    if let DedupAction::Move(target_dir) | DedupAction::Copy(target_dir) = &action {
+        let needed = bytes_to_transfer(&groups, scan_result, &action, target_dir, config);
+        if let Some(available) = available_space(target_dir).filter(|available| needed > *available) {
+            let message = format!("{} needs {} on the filesystem of {} but only {} is free ({} short)",
+                action_name,
+                format_size(needed, DECIMAL),
+                target_dir.display(),
+                format_size(available, DECIMAL),
+                format_size(needed - available, DECIMAL)
+            );
+            if !dry_run && !config.ignore_free_space {
+                bail!(message);
+            }
+            println!("{}", style(format!("⚠️ {}", message)).yellow());
+        }
+    }
+
     let mut total_result = ActionResult::new();
     let mut group_count = 0;
+    let mut untouched_groups = 0;
+    let mut sub_block_groups = 0;
 
-    for (hash, files) in &scan_result.duplicates {
-        if files.len() > 1 {
-            group_count += 1;
-            
-            if dry_run || matches!(action, DedupAction::Delete | DedupAction::Move(_)) {
-                println!();
-                println!("{} {} ({})", 
-                    style(format!("Processing group {}:", group_count)).bold(),
-                    &hash[..12],
-                    format_size(files[0].size, DECIMAL)
-                );
-                println!("  📄 Keeping: {}", files[0].path.display());
+    for (_, files) in &groups {
+        if config.cancel.is_cancelled() {
+            println!();
+            println!("{}", style("⚠️ Interrupted - no further operations were started").yellow().bold());
+            break;
+        }
+
+        if let Some(limit) = config.stop_after {
+            if total_result.total_space_saved >= limit {
+                untouched_groups = groups.len() - group_count;
+                break;
             }
+        }
 
-            let result = perform_action(files, &action, dry_run)?;
-            
-            // Merge results
-            for operation in result.operations {
-                total_result.add_operation(operation);
+        if let Some(block_size) = config.min_block_size {
+            if files[0].size < block_size && !matches!(action, DedupAction::Copy(_)) {
+                sub_block_groups += 1;
+                continue;
             }
         }
+
+        group_count += 1;
+
+        log::debug!("group {} ({} files, {} bytes each)", &files[0].hash, files.len(), files[0].size);
+
+        if let Some(reason) = report_only_reason(files, &action, config) {
+            println!();
+            println!("{} {}",
+                style(format!("Skipping group {}:", group_count)).bold(),
+                style(reason).yellow()
+            );
+            continue;
+        }
+        
+        if dry_run || matches!(action, DedupAction::Delete | DedupAction::Move(_)) {
+            println!();
+            println!("{} {} ({})", 
+                style(format!("Processing group {}:", group_count)).bold(),
+                short_hash(&files[0].hash, 12),
+                format_size(files[0].size, DECIMAL)
+            );
+            println!("  📄 Keeping: {}", files[select_keeper(files, &config.keep)].path.display());
+        }
+
+        let result = perform_action(files, &action, config, dry_run)?;
+
+        if config.post_verify && !dry_run {
+            let checked = post_verify_group(files, &result.operations, &action, config);
+            total_result.files_verified += checked.0;
+            total_result.verify_failures.extend(checked.1);
+        }
+        
+        // Merge results
+        for operation in result.operations {
+            log_operation(&operation, dry_run);
+            if let (Some(out), false) = (&mut json_errors, operation.success) {
+                serde_json::to_writer(&mut *out, &operation)?;
+                writeln!(out)?;
+            }
+            total_result.add_operation(operation);
+        }
+    }
+
+    let mut removed_dirs = Vec::new();
+    if config.delete_empty_after_move && matches!(action, DedupAction::Move(_)) {
+        let moved: Vec<PathBuf> = total_result.operations
+            .iter()
+            .filter(|op| op.success)
+            .map(|op| op.path.clone())
+            .collect();
+        removed_dirs = remove_emptied_dirs(&moved, &config.scan_roots, dry_run);
     }
 
     // Print summary
     total_result.print_summary();
 
-    if !dry_run {
+    if config.delete_empty_after_move {
+        println!("Empty directories removed: {}", removed_dirs.len());
+    }
+
+    if let Some(prefix) = &config.hash_prefix {
+        println!("Groups skipped (hash not starting with {}): {}", prefix, outside_prefix);
+    }
+
+    if !linked.is_empty() {
+        println!("Groups skipped (already hard linked): {}", linked.len());
+    }
+
+    if sub_block_groups > 0 {
+        println!("Groups skipped (smaller than one {} block): {}",
+            format_size(config.min_block_size.unwrap_or_default(), DECIMAL),
+            sub_block_groups
+        );
+    }
+
+    if let Some(limit) = config.stop_after {
+        println!();
+        println!("{} freed {} of {} target, {} groups left untouched",
+            style("🎯 Stop-after:").bold(),
+            format_size(total_result.total_space_saved, DECIMAL),
+            format_size(limit, DECIMAL),
+            untouched_groups
+        );
+    }
+
+    if !dry_run && !config.cancel.is_cancelled() {
         println!();
         println!("{}", style("✅ Deduplication complete!").green().bold());
     }
 
-    Ok(())
+    if let Some(mut out) = json_errors {
+        out.flush()?;
+    }
+
+    Ok(total_result)
+}
+
+/// Bytes a move or copy would write to the filesystem of `target_dir`
+///
+/// Moves within that filesystem are renames and need no space, and files the
+/// action will leave alone (hard links, report-only and sub-block groups) aren't
+/// counted.
+fn bytes_to_transfer(
+    groups: &[(&String, &Vec<FileInfo>)],
+    scan_result: &DedupResult,
+    action: &DedupAction,
+    target_dir: &Path,
+    config: &ActionConfig,
+) -> u64 {
+    let target_device = target_dir.ancestors().find_map(device_id);
+    groups
+        .iter()
+        .filter(|(_, files)| report_only_reason(files, action, config).is_none())
+        .map(|(_, files)| {
+            let keeper = select_keeper(files, &config.keep);
+            if let DedupAction::Copy(_) = action {
+                return files[keeper].size;
+            }
+            if config.min_block_size.is_some_and(|block_size| files[0].size < block_size) {
+                return 0;
+            }
+            files.iter()
+                .enumerate()
+                .filter(|(i, file)| *i != keeper && !scan_result.hardlinked.contains(&file.path))
+                .filter(|(_, file)| target_device.is_none() || device_id(&file.path) != target_device)
+                .map(|(_, file)| file.size)
+                .sum()
+        })
+        .sum()
+}
+
+/// Apply an action to a single duplicate group, e.g. once a user approves it in an interactive tool
+///
+/// The keeper is chosen by `keep`, falling back to the first file when it doesn't
+/// decide. Groups that are report-only in `perform_deduplication` are refused
+/// with an error; every other setting uses the `ActionConfig` defaults.
+pub fn apply_action_to_group(
+    group: &[FileInfo],
+    action: &DedupAction,
+    keep: KeepStrategy,
+    dry_run: bool,
+) -> Result<ActionResult> {
+    if group.len() < 2 {
+        return Ok(ActionResult::new());
+    }
+
+    let config = ActionConfig {
+        keep: vec![keep],
+        ..ActionConfig::default()
+    };
+    if let Some(reason) = report_only_reason(group, action, &config) {
+        bail!("group {} is report-only: {}", short_hash(&group[0].hash, 12), reason);
+    }
+
+    perform_action(group, action, &config, dry_run)
+}
+
+/// Why an action must leave a group alone, for groups that only match after
+/// decompression or text normalization
+fn report_only_reason(files: &[FileInfo], action: &DedupAction, config: &ActionConfig) -> Option<&'static str> {
+    if !matches!(action, DedupAction::Copy(_)) && is_decompressed_group(files) {
+        return Some("compressed and uncompressed copies only match after decompression and are report-only");
+    }
+
+    let rewrites = |action: &DedupAction| {
+        matches!(action, DedupAction::Delete | DedupAction::Move(_) | DedupAction::DedupInPlace)
+    };
+    if (rewrites(action) || config.fallback.as_ref().is_some_and(rewrites)) && is_normalized_group(files) {
+        return Some("normalized text matches differ byte-for-byte and are never deleted, moved or rewritten");
+    }
+
+    if matches!(action, DedupAction::DedupInPlace) && is_nul_padded_group(files) {
+        return Some("NUL-padded matches differ in length, so their extents can't be shared");
+    }
+
+    None
+}
+
+/// Check that a processed group still holds its content: the keeper hashes as
+/// scanned, and each linked or cloned duplicate resolves to that content
+///
+/// Returns how many files were checked and the problems found.
+fn post_verify_group(
+    files: &[FileInfo],
+    operations: &[FileOperation],
+    action: &DedupAction,
+    config: &ActionConfig,
+) -> (usize, Vec<(PathBuf, String)>) {
+    let keeper = &files[select_keeper(files, &config.keep)];
+    let expected = keeper.content_hash();
+    let hash_matches = |path: &Path| -> Result<(), String> {
+        match hash_file(path, keeper.hash_algorithm, keeper.hash_len()) {
+            Ok(hash) if hash == expected => Ok(()),
+            Ok(_) => Err("content differs from the scanned hash".to_string()),
+            Err(e) => Err(format!("could not be read: {}", e)),
+        }
+    };
+
+    let mut checked = 1;
+    let mut problems = Vec::new();
+    if let Err(problem) = hash_matches(&keeper.path) {
+        problems.push((keeper.path.clone(), format!("keeper {}", problem)));
+    }
+
+    for op in operations.iter().filter(|op| op.success && op.space_saved > 0) {
+        let result = match action {
+            DedupAction::Hardlink => match are_same_file(&op.path, &keeper.path) {
+                Ok(true) => Ok(()),
+                Ok(false) => Err("is not a hard link to the keeper".to_string()),
+                Err(e) => Err(format!("could not be checked: {}", e)),
+            },
+            DedupAction::Symlink => match (fs::canonicalize(&op.path), fs::canonicalize(&keeper.path)) {
+                (Ok(target), Ok(keeper)) if target == keeper => Ok(()),
+                (Ok(target), Ok(_)) => Err(format!("links to {} instead of the keeper", target.display())),
+                (Err(e), _) | (_, Err(e)) => Err(format!("does not resolve: {}", e)),
+            },
+            DedupAction::Reflink | DedupAction::DedupInPlace => hash_matches(&op.path),
+            _ => continue,
+        };
+        checked += 1;
+        if let Err(problem) = result {
+            problems.push((op.path.clone(), problem));
+        }
+    }
+
+    (checked, problems)
+}
+
+/// Emit a log event for a finished operation, for audit trails outside the console
+fn log_operation(operation: &FileOperation, dry_run: bool) {
+    let mode = if dry_run { " (dry run)" } else { "" };
+    match &operation.error {
+        None => log::info!(
+            "{}{} {}: freed {} bytes, {} attempts",
+            operation.action, mode, operation.path.display(), operation.space_saved, operation.attempts
+        ),
+        Some(error) => log::error!(
+            "{}{} {} failed after {} attempts: {}",
+            operation.action, mode, operation.path.display(), operation.attempts, error
+        ),
+    }
+}
+
+/// Space wasted by the extra copies in a group
+fn group_waste(files: &[FileInfo]) -> u64 {
+    files[0].size * (files.len() as u64 - 1)
+}
+
+/// Whether a group only matched after text normalization, i.e. its members' raw bytes differ
+pub fn is_normalized_group(files: &[FileInfo]) -> bool {
+    files.iter().any(|file| file.content_hash() != files[0].content_hash()) && !is_nul_padded_group(files)
+}
+
+/// Whether a group only matched because compressed members were hashed decompressed
+pub fn is_decompressed_group(files: &[FileInfo]) -> bool {
+    is_normalized_group(files) && files.iter().any(|file| is_compressed_file(&file.path))
 }
 
 /// Analyze the scan results and provide recommendations
 pub fn analyze_duplicates(scan_result: &DedupResult) -> DedupAnalysis {
     let mut analysis = DedupAnalysis::new();
     
-    for (_, files) in &scan_result.duplicates {
-        if files.len() > 1 {
+    for files in scan_result.duplicates.values() {
+        let duplicate_count = scan_result.group_duplicate_count(files);
+        if duplicate_count > 0 {
             let file_size = files[0].size;
-            let duplicate_count = files.len() - 1;
-            
+
             analysis.total_groups += 1;
             analysis.total_duplicates += duplicate_count;
-            analysis.total_wasted_space += file_size * duplicate_count as u64;
+            let group_waste = scan_result.group_wasted_space(files);
+            analysis.total_wasted_space += group_waste;
             
             // Categorize by size
             match file_size {
@@ -90,18 +417,381 @@ pub fn analyze_duplicates(scan_result: &DedupResult) -> DedupAnalysis {
             }
             
             // Track largest waste
-            let group_waste = file_size * duplicate_count as u64;
             if group_waste > analysis.largest_waste.1 {
                 analysis.largest_waste = (files[0].path.clone(), group_waste);
             }
         }
     }
-    
+
+    analysis.dedup_ratio = scan_result.dedup_ratio();
+    analysis.reclaimable_percent = scan_result.reclaimable_percentage();
     analysis
 }
 
+/// Space an action would free on one filesystem, by file size and by allocated blocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilesystemSavings {
+    pub device: Option<u64>,
+    /// The first duplicate found on this filesystem, to name it by
+    pub example: PathBuf,
+    pub block_size: u64,
+    /// Duplicates that would be removed or linked
+    pub files: usize,
+    /// Sum of their sizes, as in the scan summary
+    pub logical: u64,
+    /// Blocks actually released; nothing for files whose data has other names
+    pub allocated: u64,
+}
+
+/// Estimate per filesystem what removing or linking every duplicate would free
+///
+/// Each duplicate other than the keeper frees the blocks it has allocated,
+/// which for small files is a whole block and for sparse files less than their
+/// size. Members already sharing storage, and files with hard links elsewhere
+/// that keep their data alive, free nothing. Groups smaller than `min_block_size`
+/// are left out, as `perform_deduplication` skips them.
+pub fn estimate_savings(scan_result: &DedupResult, keep: &[KeepStrategy], min_block_size: Option<u64>) -> Vec<FilesystemSavings> {
+    let mut by_device: BTreeMap<Option<u64>, FilesystemSavings> = BTreeMap::new();
+
+    for files in scan_result.duplicates.values() {
+        if files.len() < 2 || is_empty_group(files) || min_block_size.is_some_and(|min| files[0].size < min) {
+            continue;
+        }
+        let keeper = select_keeper(files, keep);
+        for (i, file) in files.iter().enumerate() {
+            if i == keeper {
+                continue;
+            }
+            let device = device_id(&file.path);
+            let savings = by_device.entry(device).or_insert_with(|| FilesystemSavings {
+                device,
+                example: file.path.clone(),
+                block_size: filesystem_block_size(file.path.parent().unwrap_or(Path::new("."))),
+                files: 0,
+                logical: 0,
+                allocated: 0,
+            });
+            if scan_result.already_shared.contains(&file.path) || scan_result.hardlinked.contains(&file.path) {
+                continue;
+            }
+            savings.files += 1;
+            savings.logical += file.size;
+            if let Some((allocated, 1)) = allocation(&file.path, savings.block_size) {
+                savings.allocated += allocated;
+            }
+        }
+    }
+
+    by_device.into_values().filter(|savings| savings.files > 0).collect()
+}
+
+/// Print logical and block-accurate savings for each filesystem
+pub fn print_savings_estimate(estimates: &[FilesystemSavings]) {
+    println!();
+    println!("{}", style("💽 Estimated Savings").cyan().bold());
+    println!("{}", style("=".repeat(30)).cyan());
+
+    let (mut logical, mut allocated) = (0, 0);
+    for savings in estimates {
+        logical += savings.logical;
+        allocated += savings.allocated;
+        println!("{} ({} blocks): {} files, {} by size, {} on disk",
+            savings.example.parent().unwrap_or(&savings.example).display(),
+            format_size(savings.block_size, DECIMAL),
+            savings.files,
+            format_size(savings.logical, DECIMAL),
+            format_size(savings.allocated, DECIMAL)
+        );
+    }
+
+    println!();
+    println!("Total: {} by size, {} in allocated blocks", format_size(logical, DECIMAL), format_size(allocated, DECIMAL));
+}
+
+/// A file name found with more than one distinct content
+#[derive(Debug, Clone)]
+pub struct NameConflict {
+    pub name: String,
+    /// Files grouped by content, the group holding the most recently modified file first
+    pub versions: Vec<Vec<FileInfo>>,
+}
+
+/// Find file names that exist with different contents, the inverse of duplicate detection
+///
+/// Conflicts with the most versions come first.
+pub fn find_name_conflicts(files: Vec<FileInfo>) -> Vec<NameConflict> {
+    let mut by_name: BTreeMap<String, BTreeMap<String, Vec<FileInfo>>> = BTreeMap::new();
+    for file in files {
+        let Some(name) = file.path.file_name() else { continue };
+        by_name.entry(name.to_string_lossy().into_owned())
+            .or_default()
+            .entry(file.hash.clone())
+            .or_default()
+            .push(file);
+    }
+
+    let mut conflicts: Vec<NameConflict> = by_name.into_iter()
+        .filter(|(_, versions)| versions.len() > 1)
+        .map(|(name, versions)| {
+            let mut versions: Vec<Vec<FileInfo>> = versions.into_values().collect();
+            for files in &mut versions {
+                files.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.path.cmp(&b.path)));
+            }
+            versions.sort_by(|a, b| b[0].modified.cmp(&a[0].modified).then_with(|| a[0].path.cmp(&b[0].path)));
+            NameConflict { name, versions }
+        })
+        .collect();
+    conflicts.sort_by(|a, b| b.versions.len().cmp(&a.versions.len()).then_with(|| a.name.cmp(&b.name)));
+    conflicts
+}
+
+/// Print the names that exist in several versions, newest version first
+pub fn print_name_conflicts(conflicts: &[NameConflict], verbose: bool) {
+    println!();
+    println!("{}", style("🏷️  Same Name, Different Content").cyan().bold());
+    println!("{}", style("=".repeat(40)).cyan());
+
+    if conflicts.is_empty() {
+        println!("No file name exists with more than one content");
+        return;
+    }
+
+    for conflict in conflicts {
+        let copies: usize = conflict.versions.iter().map(|files| files.len()).sum();
+        println!();
+        println!("{} {} versions in {} files",
+            style(&conflict.name).bold(),
+            conflict.versions.len(),
+            copies
+        );
+        for files in &conflict.versions {
+            let shown = if verbose { files.len() } else { 1 };
+            for (i, file) in files.iter().take(shown).enumerate() {
+                let marker = if i == 0 { "📄" } else { "  " };
+                println!("  {} {} ({}, {})",
+                    marker,
+                    file.path.display(),
+                    short_hash(&file.hash, 12),
+                    format_size(file.size, DECIMAL)
+                );
+            }
+            if files.len() > shown {
+                println!("     and {} more with the same content", files.len() - shown);
+            }
+        }
+    }
+
+    println!();
+    println!("Names with several versions: {}", conflicts.len());
+}
+
+/// A directory whose duplicate files can be removed while keeping a copy of everything
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recommendation {
+    pub directory: PathBuf,
+    pub files: Vec<PathBuf>,
+    pub space_saved: u64,
+}
+
+/// Recommend directories to clear of duplicates, ranked by reclaimed space
+///
+/// This is a greedy set-cover: each round picks the directory whose removable
+/// duplicates free the most bytes, where a file is removable as long as its
+/// group still has another surviving copy.
+pub fn recommend_removals(scan_result: &DedupResult) -> Vec<Recommendation> {
+    let groups: Vec<&Vec<FileInfo>> = scan_result.duplicates
+        .values()
+        .filter(|files| files.len() > 1)
+        .collect();
+    let mut removed: Vec<Vec<bool>> = groups.iter().map(|files| vec![false; files.len()]).collect();
+
+    let mut by_dir: BTreeMap<PathBuf, Vec<(usize, usize)>> = BTreeMap::new();
+    for (g, files) in groups.iter().enumerate() {
+        for (i, file) in files.iter().enumerate() {
+            let dir = file.path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+            by_dir.entry(dir).or_default().push((g, i));
+        }
+    }
+
+    let mut recommendations = Vec::new();
+
+    loop {
+        let mut best = None;
+        let mut space_saved = 0u64;
+
+        for (dir, members) in &by_dir {
+            let picks = removable_members(members, &removed);
+            let saved: u64 = picks.iter().map(|&(g, _)| groups[g][0].size).sum();
+            if saved > space_saved {
+                space_saved = saved;
+                best = Some((dir.clone(), picks));
+            }
+        }
+
+        let Some((directory, picks)) = best else {
+            break;
+        };
+
+        for &(g, i) in &picks {
+            removed[g][i] = true;
+        }
+        by_dir.remove(&directory);
+
+        recommendations.push(Recommendation {
+            directory,
+            files: picks.iter().map(|&(g, i)| groups[g][i].path.clone()).collect(),
+            space_saved,
+        });
+    }
+
+    recommendations
+}
+
+/// Members of a directory that can be removed while each group keeps a surviving copy
+fn removable_members(members: &[(usize, usize)], removed: &[Vec<bool>]) -> Vec<(usize, usize)> {
+    let mut per_group: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for &(g, i) in members {
+        if !removed[g][i] {
+            per_group.entry(g).or_default().push(i);
+        }
+    }
+
+    let mut picks = Vec::new();
+    for (g, indices) in per_group {
+        let surviving = removed[g].iter().filter(|&&r| !r).count();
+        // Keep one copy here if this directory holds every surviving copy
+        let keep = if surviving > indices.len() { 0 } else { 1 };
+        picks.extend(indices.into_iter().skip(keep).map(|i| (g, i)));
+    }
+    picks
+}
+
+/// Print a ranked list of removal recommendations
+pub fn print_recommendations(recommendations: &[Recommendation]) {
+    println!();
+    println!("{}", style("🗂️  Recommended Removals").cyan().bold());
+    println!("{}", style("=".repeat(30)).cyan());
+
+    if recommendations.is_empty() {
+        println!("Nothing can be removed without losing the last copy of a file");
+        return;
+    }
+
+    let mut total = 0u64;
+    for (rank, rec) in recommendations.iter().enumerate() {
+        total += rec.space_saved;
+        println!("{}. {} — {} files, {}",
+            rank + 1,
+            rec.directory.display(),
+            rec.files.len(),
+            format_size(rec.space_saved, DECIMAL)
+        );
+    }
+
+    println!();
+    println!("Reclaimable while keeping one copy of everything: {}", format_size(total, DECIMAL));
+}
+
+/// Duplicates under one directory and the space they waste, rolled up from its subdirectories
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryWaste {
+    pub directory: PathBuf,
+    /// Duplicates anywhere under the directory
+    pub duplicate_files: usize,
+    pub wasted_space: u64,
+    /// Duplicates directly in the directory
+    pub own_duplicate_files: usize,
+    pub own_wasted_space: u64,
+    /// Subdirectories holding duplicates, most wasted space first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<DirectoryWaste>,
+}
+
+/// Attribute each duplicate's wasted space to its directory and every ancestor
+///
+/// Duplicates are counted as in the scan summary: every group member after the
+/// first, except hard links, and already shared storage wastes nothing. Returns
+/// the top-level directories, most wasted space first.
+pub fn waste_by_directory(scan_result: &DedupResult) -> Vec<DirectoryWaste> {
+    let mut own: BTreeMap<PathBuf, (usize, u64)> = BTreeMap::new();
+    for files in scan_result.duplicates.values() {
+        if files.len() < 2 || is_empty_group(files) {
+            continue;
+        }
+        for file in files.iter().skip(1).filter(|file| !scan_result.hardlinked.contains(&file.path)) {
+            let dir = file.path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+            let entry = own.entry(dir).or_default();
+            entry.0 += 1;
+            if !scan_result.already_shared.contains(&file.path) {
+                entry.1 += file.size;
+            }
+        }
+    }
+
+    let mut nodes: BTreeMap<PathBuf, DirectoryWaste> = BTreeMap::new();
+    for (dir, (files, wasted)) in own {
+        let node = nodes.entry(dir.clone()).or_insert_with(|| DirectoryWaste { directory: dir.clone(), ..Default::default() });
+        node.own_duplicate_files = files;
+        node.own_wasted_space = wasted;
+        for ancestor in dir.ancestors().filter(|ancestor| !ancestor.as_os_str().is_empty()) {
+            let node = nodes.entry(ancestor.to_path_buf())
+                .or_insert_with(|| DirectoryWaste { directory: ancestor.to_path_buf(), ..Default::default() });
+            node.duplicate_files += files;
+            node.wasted_space += wasted;
+        }
+    }
+
+    // Deepest paths sort last, so popping hands every child to its parent before the parent moves on
+    let mut roots = Vec::new();
+    while let Some((dir, mut node)) = nodes.pop_last() {
+        node.children.sort_by(|a, b| b.wasted_space.cmp(&a.wasted_space).then_with(|| a.directory.cmp(&b.directory)));
+        match dir.parent().and_then(|parent| nodes.get_mut(parent)) {
+            Some(parent) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    }
+    roots.sort_by(|a, b| b.wasted_space.cmp(&a.wasted_space).then_with(|| a.directory.cmp(&b.directory)));
+    roots
+}
+
+/// Print the directories with the most wasted space, up to `limit` rows
+///
+/// Directories that only pass their single subdirectory's totals through (like
+/// `/` and `/home` above `/home/me/downloads`) are left out, as they add nothing.
+pub fn print_directory_waste(tree: &[DirectoryWaste], limit: Option<usize>) {
+    println!();
+    println!("{}", style("📂 Wasted Space by Directory").cyan().bold());
+    println!("{}", style("=".repeat(30)).cyan());
+
+    let mut rows: Vec<&DirectoryWaste> = Vec::new();
+    let mut pending: Vec<&DirectoryWaste> = tree.iter().collect();
+    while let Some(node) = pending.pop() {
+        if node.own_duplicate_files > 0 || node.children.len() != 1 {
+            rows.push(node);
+        }
+        pending.extend(&node.children);
+    }
+    if rows.is_empty() {
+        println!("No duplicates found");
+        return;
+    }
+    rows.sort_by(|a, b| b.wasted_space.cmp(&a.wasted_space).then_with(|| a.directory.cmp(&b.directory)));
+
+    let shown = limit.unwrap_or(rows.len()).min(rows.len());
+    for row in &rows[..shown] {
+        println!("{:>10}  {:>6} files  {}",
+            format_size(row.wasted_space, DECIMAL),
+            row.duplicate_files,
+            row.directory.display()
+        );
+    }
+    if shown < rows.len() {
+        println!("... and {} more directories (use --verbose to list all)", rows.len() - shown);
+    }
+}
+
 /// Analysis results for duplicate files
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DedupAnalysis {
     pub total_groups: usize,
     pub total_duplicates: usize,
@@ -110,6 +800,12 @@ pub struct DedupAnalysis {
     pub medium_files: usize,   // 1KB - 1MB
     pub large_files: usize,    // > 1MB
     pub largest_waste: (std::path::PathBuf, u64), // (path, wasted_bytes)
+    /// Bytes scanned per unique byte
+    #[serde(default)]
+    pub dedup_ratio: f64,
+    /// Percentage of the scanned bytes taken by removable duplicates
+    #[serde(default)]
+    pub reclaimable_percent: f64,
 }
 
 impl DedupAnalysis {
@@ -122,6 +818,8 @@ impl DedupAnalysis {
             medium_files: 0,
             large_files: 0,
             largest_waste: (std::path::PathBuf::new(), 0),
+            dedup_ratio: 1.0,
+            reclaimable_percent: 0.0,
         }
     }
 
@@ -133,6 +831,7 @@ impl DedupAnalysis {
         println!("Duplicate groups found: {}", self.total_groups);
         println!("Total duplicate files: {}", self.total_duplicates);
         println!("Total wasted space: {}", format_size(self.total_wasted_space, DECIMAL));
+        println!("Deduplication ratio: {:.2}:1 ({:.1}% reclaimable)", self.dedup_ratio, self.reclaimable_percent);
         
         println!();
         println!("{}", style("📊 File Size Distribution:").bold());
@@ -172,4 +871,255 @@ impl Default for DedupAnalysis {
     fn default() -> Self {
         Self::new()
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashAlgorithm;
+    use std::time::UNIX_EPOCH;
+
+    fn add(result: &mut DedupResult, path: &str, size: u64, hash: &str) {
+        result.add_file(FileInfo {
+            path: PathBuf::from(path),
+            size,
+            hash: hash.to_string(),
+            modified: UNIX_EPOCH,
+            detected_type: None,
+            raw_hash: None,
+            source_root: None,
+            hash_algorithm: HashAlgorithm::Blake3,
+            inode: None,
+        }).unwrap();
+    }
+
+    #[test]
+    fn test_recommend_removals_keeps_a_copy() {
+        let mut result = DedupResult::new();
+        add(&mut result, "/keep/a", 100, "a");
+        add(&mut result, "/backup/a", 100, "a");
+        add(&mut result, "/backup/b", 50, "b");
+        add(&mut result, "/old/b", 50, "b");
+        add(&mut result, "/old/c", 10, "c");
+        add(&mut result, "/old/c2", 10, "c");
+        result.filter_duplicates();
+
+        let recs = recommend_removals(&result);
+
+        assert_eq!(recs[0].directory, PathBuf::from("/backup"));
+        assert_eq!(recs[0].space_saved, 150);
+        // /old now holds the last copy of "b" and both copies of "c"
+        assert_eq!(recs[1].directory, PathBuf::from("/old"));
+        assert_eq!(recs[1].space_saved, 10);
+        assert_eq!(recs.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_bytes_to_transfer() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut result = DedupResult::new();
+        for name in ["a", "b"] {
+            fs::write(dir.path().join(name), b"data").unwrap();
+            add(&mut result, dir.path().join(name).to_str().unwrap(), 4, "d");
+        }
+        add(&mut result, "/nonexistent/x", 7, "x");
+        add(&mut result, "/nonexistent/y", 7, "x");
+        result.filter_duplicates();
+
+        let groups: Vec<(&String, &Vec<FileInfo>)> = result.duplicates.iter().collect();
+        let target = dir.path().join("out");
+        let config = ActionConfig::default();
+        // Only the move from another (here: unknown) filesystem needs room on the target
+        assert_eq!(bytes_to_transfer(&groups, &result, &DedupAction::Move(target.clone()), &target, &config), 7);
+        assert_eq!(bytes_to_transfer(&groups, &result, &DedupAction::Copy(target.clone()), &target, &config), 11);
+    }
+
+    #[test]
+    fn test_waste_by_directory() {
+        let mut result = DedupResult::new();
+        add(&mut result, "/keep/a", 100, "a");
+        add(&mut result, "/home/me/downloads/a", 100, "a");
+        add(&mut result, "/home/me/downloads/old/a", 100, "a");
+        add(&mut result, "/keep/b", 50, "b");
+        add(&mut result, "/home/me/docs/b", 50, "b");
+        result.filter_duplicates();
+
+        let tree = waste_by_directory(&result);
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].directory, PathBuf::from("/"));
+        assert_eq!((tree[0].duplicate_files, tree[0].wasted_space), (3, 250));
+
+        let me = &tree[0].children[0].children[0];
+        assert_eq!(me.directory, PathBuf::from("/home/me"));
+        let children: Vec<(&Path, u64)> = me.children.iter().map(|c| (c.directory.as_path(), c.wasted_space)).collect();
+        assert_eq!(children, [(Path::new("/home/me/downloads"), 200), (Path::new("/home/me/docs"), 50)]);
+        let downloads = &me.children[0];
+        assert_eq!((downloads.own_duplicate_files, downloads.own_wasted_space), (1, 100));
+        assert_eq!(downloads.children[0].directory, PathBuf::from("/home/me/downloads/old"));
+    }
+
+    #[test]
+    fn test_name_conflicts() {
+        let file = |path: &str, hash: &str, age: u64| FileInfo {
+            path: PathBuf::from(path),
+            size: 1,
+            hash: hash.to_string(),
+            modified: UNIX_EPOCH + std::time::Duration::from_secs(1000 - age),
+            detected_type: None,
+            raw_hash: None,
+            source_root: None,
+            hash_algorithm: HashAlgorithm::Blake3,
+            inode: None,
+        };
+        let conflicts = find_name_conflicts(vec![
+            file("/a/report.docx", "v1", 30),
+            file("/b/report.docx", "v2", 10),
+            file("/c/report.docx", "v1", 20),
+            file("/a/notes.txt", "n", 5),
+            file("/b/notes.txt", "n", 5),
+            file("/a/todo.txt", "t1", 5),
+            file("/b/todo.txt", "t2", 1),
+            file("/c/todo.txt", "t3", 9),
+        ]);
+
+        let names: Vec<&str> = conflicts.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["todo.txt", "report.docx"]);
+        let report = &conflicts[1];
+        assert_eq!(report.versions[0][0].path, PathBuf::from("/b/report.docx"));
+        let older: Vec<_> = report.versions[1].iter().map(|f| f.path.clone()).collect();
+        assert_eq!(older, [PathBuf::from("/c/report.docx"), PathBuf::from("/a/report.docx")]);
+    }
+
+    #[test]
+    fn test_apply_action_to_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let (keep_dir, other_dir) = (dir.path().join("keep"), dir.path().join("other"));
+        fs::create_dir_all(&keep_dir).unwrap();
+        fs::create_dir_all(&other_dir).unwrap();
+        let file = |path: PathBuf, raw_hash: Option<&str>| {
+            fs::write(&path, b"same").unwrap();
+            FileInfo {
+                path,
+                size: 4,
+                hash: "h".to_string(),
+                modified: UNIX_EPOCH,
+                detected_type: None,
+                raw_hash: raw_hash.map(str::to_string),
+                source_root: None,
+                hash_algorithm: HashAlgorithm::Blake3,
+                inode: None,
+            }
+        };
+        let group = vec![file(other_dir.join("a"), None), file(keep_dir.join("a"), None)];
+
+        let keep = KeepStrategy::DirectoryPriority(vec![keep_dir.clone()]);
+        let result = apply_action_to_group(&group, &DedupAction::Delete, keep.clone(), false).unwrap();
+        assert_eq!(result.success_count(), 1);
+        assert!(keep_dir.join("a").exists());
+        assert!(!other_dir.join("a").exists());
+
+        // Normalized text matches are never deleted
+        let normalized = vec![file(other_dir.join("b"), Some("raw")), file(keep_dir.join("b"), None)];
+        assert!(apply_action_to_group(&normalized, &DedupAction::Delete, keep, false).is_err());
+        assert!(other_dir.join("b").exists());
+    }
+
+    #[test]
+    fn test_hash_prefix_limits_groups() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut result = DedupResult::new();
+        for (name, hash) in [("a", "ab12"), ("b", "ab12"), ("c", "cd34"), ("d", "cd34")] {
+            let path = dir.path().join(name);
+            fs::write(&path, hash).unwrap();
+            result.add_file(FileInfo {
+                path,
+                size: 4,
+                hash: hash.to_string(),
+                modified: UNIX_EPOCH,
+                detected_type: None,
+                raw_hash: None,
+                source_root: None,
+                hash_algorithm: HashAlgorithm::Blake3,
+                inode: None,
+            }).unwrap();
+        }
+
+        let config = ActionConfig { hash_prefix: Some("ab".to_string()), ..ActionConfig::default() };
+        let actions = perform_deduplication(&result, DedupAction::Delete, &config, false).unwrap();
+        assert_eq!(actions.success_count(), 1);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+        assert!(dir.path().join("c").exists() && dir.path().join("d").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_estimate_savings_counts_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let mut result = DedupResult::new();
+        for name in ["a", "b", "c"] {
+            let path = dir.path().join(name);
+            fs::write(&path, b"tiny").unwrap();
+            add(&mut result, path.to_str().unwrap(), 4, "h");
+        }
+        // c's data stays reachable through another name, so removing c frees nothing
+        fs::hard_link(dir.path().join("c"), outside.path().join("c")).unwrap();
+
+        let estimates = estimate_savings(&result, &[], None);
+        assert_eq!(estimates.len(), 1);
+        assert_eq!(estimates[0].files, 2);
+        assert_eq!(estimates[0].logical, 8);
+        let (b_blocks, _) = allocation(&dir.path().join("b"), estimates[0].block_size).unwrap();
+        assert_eq!(estimates[0].allocated, b_blocks);
+    }
+
+    #[test]
+    fn test_post_verify_hardlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let keeper = dir.path().join("keeper");
+        let linked = dir.path().join("linked");
+        let copied = dir.path().join("copied");
+        fs::write(&keeper, b"same").unwrap();
+        fs::hard_link(&keeper, &linked).unwrap();
+        fs::write(&copied, b"same").unwrap();
+
+        let hash = blake3::hash(b"same").to_hex().to_string();
+        let files: Vec<FileInfo> = [&keeper, &linked, &copied]
+            .iter()
+            .map(|path| FileInfo {
+                path: path.to_path_buf(),
+                size: 4,
+                hash: hash.clone(),
+                modified: UNIX_EPOCH,
+                detected_type: None,
+                raw_hash: None,
+                source_root: None,
+                hash_algorithm: HashAlgorithm::Blake3,
+                inode: None,
+            })
+            .collect();
+        let op = |path: &Path| FileOperation {
+            path: path.to_path_buf(),
+            action: "hardlink".to_string(),
+            success: true,
+            error: None,
+            space_saved: 4,
+            attempts: 1,
+            error_kind: None,
+            os_error: None,
+        };
+
+        let (checked, problems) = post_verify_group(
+            &files, &[op(&linked), op(&copied)], &DedupAction::Hardlink, &ActionConfig::default(),
+        );
+        assert_eq!(checked, 3);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].0, copied);
+
+        fs::write(&keeper, b"edit").unwrap();
+        let (_, problems) = post_verify_group(&files, &[], &DedupAction::Delete, &ActionConfig::default());
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].1.starts_with("keeper"));
+    }
+}
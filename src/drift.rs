@@ -0,0 +1,199 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+
+use crate::{CancelToken, HashAlgorithm};
+use crate::report::{unix_seconds, Report, ReportGroup};
+use crate::utils::{hash_file, identical_ignoring_nul_padding};
+
+/// How a file listed in a report differs from what is on disk now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileDrift {
+    /// The file is gone or can no longer be read
+    Missing,
+    /// Its size or modification time changed since the report was written
+    Changed,
+    /// Same size and time, but its content no longer hashes to the group's hash
+    Diverged,
+}
+
+/// A report group with at least one file that no longer matches the report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupDrift {
+    pub hash: String,
+    /// The drifted files, in report order
+    pub files: Vec<(PathBuf, FileDrift)>,
+    /// Files of the group that still match the report
+    pub intact: usize,
+}
+
+/// Outcome of checking a saved report against the files on disk
+#[derive(Debug, Default)]
+pub struct ReportDrift {
+    pub groups_checked: usize,
+    pub files_checked: usize,
+    pub drifted: Vec<GroupDrift>,
+    /// Groups whose hash can't be recomputed from the raw bytes (normalized,
+    /// decompressed, prefix-only or name-only groups), so only metadata was checked
+    pub not_rehashed: usize,
+}
+
+impl ReportDrift {
+    /// Whether every listed file still matches the report
+    pub fn is_accurate(&self) -> bool {
+        self.drifted.is_empty()
+    }
+}
+
+/// Check that every file in a saved report still exists with the size and
+/// modification time it had, and with `rehash` that it still has the group's hash
+///
+/// Reports written before modification times were recorded are checked by size
+/// only. Stops early, with the groups checked so far, once `cancel` is set.
+pub fn verify_report(report: &Report, rehash: bool, cancel: &CancelToken) -> ReportDrift {
+    let mut drift = ReportDrift::default();
+    let raw_hashes = report.summary.prefix_only.is_none() && !report.summary.size_and_name_only;
+
+    for group in &report.groups {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let plain = !group.normalized && !group.decompressed && !group.nul_padded;
+        let rehash_group = rehash && (plain || group.nul_padded) && raw_hashes;
+        if rehash && !rehash_group {
+            drift.not_rehashed += 1;
+        }
+
+        let paths = report.file_paths(group);
+        let checks: Vec<Option<FileDrift>> = paths
+            .par_iter()
+            .enumerate()
+            .map(|(i, path)| check_file(group, i, path, &paths[0], plain, rehash_group.then_some(report.summary.hash_algorithm)))
+            .collect();
+
+        drift.groups_checked += 1;
+        drift.files_checked += paths.len();
+        let files: Vec<(PathBuf, FileDrift)> = paths
+            .into_iter()
+            .zip(checks)
+            .filter_map(|(path, check)| Some((path, check?)))
+            .collect();
+        if !files.is_empty() {
+            drift.drifted.push(GroupDrift {
+                hash: group.hash.clone(),
+                intact: group.files.len() - files.len(),
+                files,
+            });
+        }
+    }
+
+    drift
+}
+
+/// How the `index`th file of a group drifted, if at all, rehashing it with the
+/// given algorithm if any
+///
+/// Members of normalized, decompressed or NUL-padded groups may differ in size,
+/// so their size isn't compared. The padded members of a NUL-padded group have
+/// hashes of their own, so they are compared with the group's first file instead.
+fn check_file(group: &ReportGroup, index: usize, path: &Path, first: &Path, plain: bool, rehash: Option<HashAlgorithm>) -> Option<FileDrift> {
+    let Ok(metadata) = fs::metadata(path) else {
+        return Some(FileDrift::Missing);
+    };
+    if !metadata.is_file() {
+        return Some(FileDrift::Missing);
+    }
+    let modified = metadata.modified().map(unix_seconds).ok();
+    let resized = plain && metadata.len() != group.size;
+    let touched = group.modified.get(index).is_some_and(|&recorded| modified != Some(recorded));
+    if resized || touched {
+        return Some(FileDrift::Changed);
+    }
+    if rehash.is_some() && group.nul_padded && index > 0 {
+        return match identical_ignoring_nul_padding(first, path) {
+            Ok(true) => None,
+            _ => Some(FileDrift::Diverged),
+        };
+    }
+    if let Some(algorithm) = rehash {
+        return match hash_file(path, algorithm, group.hash.len() / 2) {
+            Ok(hash) if hash == group.hash => None,
+            Ok(_) => Some(FileDrift::Diverged),
+            Err(_) => Some(FileDrift::Missing),
+        };
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DedupResult, FileInfo};
+    use crate::utils::DEFAULT_HASH_LEN;
+
+    #[test]
+    fn test_missing_changed_and_diverged_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut result = DedupResult::new();
+        for name in ["a", "b", "c", "d"] {
+            let path = dir.path().join(name);
+            fs::write(&path, b"same data").unwrap();
+            result.add_file(FileInfo {
+                hash: hash_file(&path, HashAlgorithm::Blake3, DEFAULT_HASH_LEN).unwrap(),
+                size: 9,
+                modified: fs::metadata(&path).unwrap().modified().unwrap(),
+                path,
+                detected_type: None,
+                raw_hash: None,
+                source_root: None,
+                hash_algorithm: HashAlgorithm::Blake3,
+                inode: None,
+            }).unwrap();
+        }
+        result.filter_duplicates();
+        let report = Report::from_result(&result);
+        let cancel = CancelToken::new();
+        assert!(verify_report(&report, true, &cancel).is_accurate());
+
+        let mtime = filetime::FileTime::from_last_modification_time(&fs::metadata(dir.path().join("c")).unwrap());
+        fs::remove_file(dir.path().join("a")).unwrap();
+        fs::write(dir.path().join("b"), b"more data!").unwrap();
+        fs::write(dir.path().join("c"), b"diff data").unwrap();
+        filetime::set_file_mtime(dir.path().join("c"), mtime).unwrap();
+
+        let metadata_only = verify_report(&report, false, &cancel);
+        assert_eq!(metadata_only.files_checked, 4);
+        assert_eq!(metadata_only.drifted[0].files, [
+            (dir.path().join("a"), FileDrift::Missing),
+            (dir.path().join("b"), FileDrift::Changed),
+        ]);
+
+        let rehashed = verify_report(&report, true, &cancel);
+        assert_eq!(rehashed.drifted[0].files.len(), 3);
+        assert_eq!(rehashed.drifted[0].files[2], (dir.path().join("c"), FileDrift::Diverged));
+        assert_eq!(rehashed.drifted[0].intact, 1);
+    }
+
+    #[test]
+    fn test_nul_padded_group_is_not_drift() {
+        let dir = tempfile::tempdir().unwrap();
+        let (short, padded) = (dir.path().join("out.bin"), dir.path().join("out.padded.bin"));
+        fs::write(&short, b"payload").unwrap();
+        fs::write(&padded, b"payload\0\0\0").unwrap();
+        let mut scanner = crate::Scanner::new();
+        scanner.set_nul_padding(true);
+        let report = Report::from_result(&scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap());
+        assert!(report.groups[0].nul_padded);
+        let cancel = CancelToken::new();
+        assert!(verify_report(&report, false, &cancel).is_accurate());
+        let rehashed = verify_report(&report, true, &cancel);
+        assert!(rehashed.is_accurate());
+        assert_eq!(rehashed.not_rehashed, 0);
+
+        let mtime = filetime::FileTime::from_last_modification_time(&fs::metadata(&padded).unwrap());
+        fs::write(&padded, b"paylox\0\0\0\0").unwrap();
+        filetime::set_file_mtime(&padded, mtime).unwrap();
+        assert!(verify_report(&report, false, &cancel).is_accurate());
+        assert_eq!(verify_report(&report, true, &cancel).drifted[0].files, [(padded, FileDrift::Diverged)]);
+    }
+}
@@ -0,0 +1,131 @@
+use std::fs;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Name written into every envelope's `tool` field
+pub const TOOL_NAME: &str = "dedup";
+
+/// Version of the JSON formats; bump when fields change incompatibly
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Wrapper around every JSON document the tool writes, so consumers can detect format changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub tool: String,
+    pub schema_version: u32,
+    /// Seconds since the Unix epoch
+    pub generated_at: u64,
+    pub data: T,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            tool: TOOL_NAME.to_string(),
+            schema_version: SCHEMA_VERSION,
+            generated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            data,
+        }
+    }
+}
+
+/// Write `data` wrapped in an envelope to a pretty-printed JSON file
+pub fn save_json<T: Serialize>(path: &Path, data: &T, what: &str) -> Result<()> {
+    write_json(path, data, what).map(drop)
+}
+
+/// Like `save_json`, but write to a temporary file beside `path` and rename it into place
+///
+/// A crash mid-write leaves the previous file intact instead of a truncated one.
+/// The temporary file is synced before the rename, so the new file can't turn
+/// up empty after a crash either.
+pub fn save_json_atomic<T: Serialize>(path: &Path, data: &T, what: &str) -> Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = Path::new(&temp);
+    write_json(temp, data, what)?
+        .sync_all()
+        .with_context(|| format!("Failed to write {} {}", what, temp.display()))?;
+    fs::rename(temp, path)
+        .with_context(|| format!("Failed to replace {} {}", what, path.display()))
+}
+
+/// Write the envelope JSON through a buffer, returning the flushed file
+fn write_json<T: Serialize>(path: &Path, data: &T, what: &str) -> Result<fs::File> {
+    let file = fs::File::create(path)
+        .with_context(|| format!("Failed to create {} {}", what, path.display()))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, &Envelope::new(data))
+        .with_context(|| format!("Failed to write {} {}", what, path.display()))?;
+    writer.into_inner()
+        .map_err(|e| e.into_error())
+        .with_context(|| format!("Failed to write {} {}", what, path.display()))
+}
+
+/// Read a JSON file written by `save_json`, checking the tool and schema version
+pub fn load_json<T: DeserializeOwned>(path: &Path, what: &str) -> Result<T> {
+    let file = fs::File::open(path)
+        .with_context(|| format!("Failed to open {} {}", what, path.display()))?;
+    let envelope: Envelope<T> = serde_json::from_reader(std::io::BufReader::new(file))
+        .with_context(|| format!("Failed to parse {} {}", what, path.display()))?;
+
+    if envelope.tool != TOOL_NAME {
+        bail!("{} was written by '{}', not {}", path.display(), envelope.tool, TOOL_NAME);
+    }
+    if envelope.schema_version != SCHEMA_VERSION {
+        bail!(
+            "{} uses schema version {}, but this version of {} reads version {}",
+            path.display(), envelope.schema_version, TOOL_NAME, SCHEMA_VERSION
+        );
+    }
+
+    Ok(envelope.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_envelope_fields() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.json");
+        save_json(&path, &vec![1, 2, 3], "test file").unwrap();
+
+        let value: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value["tool"], "dedup");
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+        assert!(value["generated_at"].as_u64().unwrap() > 0);
+        assert_eq!(value["data"], serde_json::json!([1, 2, 3]));
+
+        let data: Vec<i32> = load_json(&path, "test file").unwrap();
+        assert_eq!(data, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_atomic_save_replaces_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.json");
+        fs::write(&path, "old").unwrap();
+        save_json_atomic(&path, &vec![4], "test file").unwrap();
+
+        assert_eq!(load_json::<Vec<i32>>(&path, "test file").unwrap(), [4]);
+        assert!(!dir.path().join("out.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_load_rejects_other_versions() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("old.json");
+        fs::write(&path, r#"{"tool":"dedup","schema_version":0,"generated_at":0,"data":[]}"#).unwrap();
+        assert!(load_json::<Vec<i32>>(&path, "test file").is_err());
+    }
+}
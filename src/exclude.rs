@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{bail, Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Exclude rules read from a file, one pattern per line, in the style of `rsync --exclude-from`
+///
+/// Blank lines and lines starting with `#` are ignored. A pattern without a `/`
+/// matches a file or directory name at any depth; one with a `/` is anchored to
+/// the scan root. A trailing `/` is accepted and ignored. Names and patterns
+/// using `*`, `?`, `[` or `{` become globs; other anchored lines are literal paths.
+#[derive(Debug, Clone, Default)]
+pub struct ExcludeRules {
+    /// Glob patterns, already rewritten to match paths relative to a scan root
+    pub globs: Vec<String>,
+    /// Literal paths relative to a scan root
+    pub paths: HashSet<PathBuf>,
+}
+
+impl ExcludeRules {
+    /// Parse the contents of an exclude file, reporting every bad line by number
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rules = Self::default();
+        let mut errors = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Err(e) = rules.add(line) {
+                errors.push(format!("line {}: '{}': {}", index + 1, line, e));
+            }
+        }
+
+        if !errors.is_empty() {
+            bail!("invalid exclude patterns:\n  {}", errors.join("\n  "));
+        }
+        Ok(rules)
+    }
+
+    /// Read and parse an exclude file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read exclude file {}", path.display()))?;
+        Self::parse(&text)
+            .with_context(|| format!("Failed to parse exclude file {}", path.display()))
+    }
+
+    /// Add the rules of another file
+    pub fn extend(&mut self, other: ExcludeRules) {
+        self.globs.extend(other.globs);
+        self.paths.extend(other.paths);
+    }
+
+    /// Compile the glob patterns for matching
+    pub fn glob_set(&self) -> Result<GlobSet> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &self.globs {
+            builder.add(compile(pattern)?);
+        }
+        Ok(builder.build()?)
+    }
+
+    fn add(&mut self, line: &str) -> Result<(), String> {
+        if line.starts_with('!') {
+            return Err("negated patterns are not supported".to_string());
+        }
+
+        let anchored = line.starts_with('/');
+        let pattern = line.trim_start_matches('/').trim_end_matches('/');
+        if pattern.is_empty() {
+            return Err("pattern would exclude the whole scan root".to_string());
+        }
+        let has_slash = anchored || pattern.contains('/');
+
+        if has_slash && !pattern.contains(['*', '?', '[', '{']) {
+            self.paths.insert(PathBuf::from(pattern));
+            return Ok(());
+        }
+
+        let pattern = if has_slash {
+            pattern.to_string()
+        } else {
+            format!("**/{}", pattern)
+        };
+        compile(&pattern).map_err(|e| e.to_string())?;
+        self.globs.push(pattern);
+        Ok(())
+    }
+}
+
+fn compile(pattern: &str) -> Result<globset::Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
+
+/// Compile globs matched against whole paths, where `*` stays within one component and `**` spans any number
+pub fn path_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(compile(pattern).with_context(|| format!("invalid glob pattern '{}'", pattern))?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Whether a path relative to its scan root, or a directory above it, is hit by the exclude globs or paths
+pub fn is_excluded(globs: &GlobSet, paths: &HashSet<PathBuf>, relative: &Path) -> bool {
+    relative.ancestors()
+        .filter(|p| !p.as_os_str().is_empty())
+        .any(|p| globs.is_match(p) || paths.contains(p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_match() {
+        let rules = ExcludeRules::parse(
+            "# build output\n\nnode_modules/\n*.log\n/cache/tmp\nsrc/**/*.bak\n"
+        ).unwrap();
+        let globs = rules.glob_set().unwrap();
+        let excluded = |p: &str| is_excluded(&globs, &rules.paths, Path::new(p));
+
+        assert!(excluded("node_modules"));
+        assert!(excluded("web/node_modules/react/index.js"));
+        assert!(excluded("debug.log"));
+        assert!(excluded("deep/dir/debug.log"));
+        assert!(excluded("cache/tmp/a.bin"));
+        assert!(excluded("src/x/y/old.bak"));
+
+        assert!(!excluded("other/cache/tmp/a.bin"));
+        assert!(!excluded("old.bak"));
+        assert!(!excluded("logs/readme.txt"));
+        assert!(!excluded("src/cache/tmp"));
+    }
+
+    #[test]
+    fn test_bad_lines_reported_with_numbers() {
+        let err = ExcludeRules::parse("ok.txt\n[unclosed\n# fine\n!negated\n/\n").unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("line 2: '[unclosed'"), "{}", message);
+        assert!(message.contains("line 4: '!negated'"), "{}", message);
+        assert!(message.contains("line 5: '/'"), "{}", message);
+        assert!(!message.contains("line 1"), "{}", message);
+    }
+}
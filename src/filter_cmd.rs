@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+
+/// A user command that decides which files are scanned
+///
+/// The command runs once per candidate file through the shell, with the file's
+/// path appended as its last argument. Exiting with status 0 keeps the file;
+/// any other status, or running past the timeout, leaves it out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterCommand {
+    pub command: String,
+    /// How many commands may run at once
+    pub jobs: usize,
+    /// How long one run may take before it is killed and its file left out
+    pub timeout: Duration,
+}
+
+/// Files sorted by a filter command
+#[derive(Debug, Default)]
+pub struct Filtered {
+    pub kept: Vec<PathBuf>,
+    pub rejected: Vec<PathBuf>,
+    /// Files whose command was killed for running past the timeout (also left out)
+    pub timed_out: Vec<PathBuf>,
+}
+
+impl FilterCommand {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            jobs: 4,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Run the command for every file, at most `jobs` at a time
+    ///
+    /// Fails only if the command can't be started at all.
+    pub fn filter(&self, files: Vec<PathBuf>) -> Result<Filtered> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.jobs.max(1))
+            .build()
+            .context("Failed to start filter command workers")?;
+        let verdicts: Vec<(PathBuf, Option<bool>)> = pool.install(|| {
+            files
+                .into_par_iter()
+                .map(|path| {
+                    let verdict = self.accepts(&path)?;
+                    Ok((path, verdict))
+                })
+                .collect::<Result<_>>()
+        })?;
+
+        let mut filtered = Filtered::default();
+        for (path, verdict) in verdicts {
+            match verdict {
+                Some(true) => filtered.kept.push(path),
+                Some(false) => filtered.rejected.push(path),
+                None => filtered.timed_out.push(path),
+            }
+        }
+        Ok(filtered)
+    }
+
+    /// Whether the command accepts one file, or `None` if it timed out
+    pub fn accepts(&self, path: &Path) -> Result<Option<bool>> {
+        let mut child = self.shell_command(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to run filter command '{}'", self.command))?;
+
+        let deadline = Instant::now() + self.timeout;
+        let mut delay = Duration::from_millis(1);
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(Some(status.success()));
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(None);
+            }
+            thread::sleep(delay);
+            delay = (delay * 2).min(Duration::from_millis(50));
+        }
+    }
+
+    #[cfg(not(windows))]
+    fn shell_command(&self, path: &Path) -> Command {
+        // The path goes in as a positional argument, so it never needs quoting
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(format!("{} \"$@\"", self.command)).arg("sh").arg(path);
+        command
+    }
+
+    #[cfg(windows)]
+    fn shell_command(&self, path: &Path) -> Command {
+        use std::os::windows::process::CommandExt;
+
+        let mut command = Command::new("cmd");
+        command.arg("/C").raw_arg(format!("{} \"{}\"", self.command, path.display()));
+        command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_status_and_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let (keep, drop) = (dir.path().join("keep me.txt"), dir.path().join("drop.txt"));
+        fs::write(&keep, b"yes").unwrap();
+        fs::write(&drop, b"no").unwrap();
+
+        let filtered = FilterCommand::new("grep -q yes").filter(vec![keep.clone(), drop.clone()]).unwrap();
+        assert_eq!(filtered.kept, [keep.as_path()]);
+        assert_eq!(filtered.rejected, [drop]);
+
+        let slow = FilterCommand { timeout: Duration::from_millis(50), ..FilterCommand::new("sleep 5; true") };
+        let filtered = slow.filter(vec![keep.clone()]).unwrap();
+        assert_eq!(filtered.timed_out, [keep]);
+    }
+}
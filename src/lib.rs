@@ -2,32 +2,277 @@ pub mod scanner;
 pub mod dedup;
 pub mod actions;
 pub mod utils;
+pub mod crossmatch;
+pub mod report;
+pub mod envelope;
+pub mod shard;
+pub mod exclude;
+pub mod profile;
+pub mod blocks;
+pub mod category;
+pub mod filter_cmd;
+pub mod wizard;
+pub mod trash;
+pub mod drift;
+pub mod revert;
+pub mod cache;
+#[cfg(feature = "chunking")]
+pub mod chunking;
+#[cfg(feature = "decompress")]
+pub mod decompress;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "async")]
+pub mod async_scanner;
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use anyhow::{bail, Result};
+use serde::{Serialize, Deserialize};
 
-pub use scanner::Scanner;
-pub use dedup::perform_deduplication;
+use crate::utils::calculate_percentage;
+
+pub use scanner::{Scanner, SkipReason};
+#[cfg(feature = "async")]
+pub use async_scanner::{AsyncScanner, ScanProgress};
+pub use dedup::{apply_action_to_group, perform_deduplication, perform_deduplication_with_revert};
 
 /// Represents a file with metadata used for deduplication
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileInfo {
     pub path: PathBuf,
     pub size: u64,
     pub hash: String,
     pub modified: std::time::SystemTime,
+    /// MIME type sniffed from the file's magic bytes, when type detection is enabled
+    #[serde(default)]
+    pub detected_type: Option<String>,
+    /// Hash of the raw bytes when text normalization changed them; `hash` is then the normalized hash
+    #[serde(default)]
+    pub raw_hash: Option<String>,
+    /// Input directory the file was found under
+    #[serde(default)]
+    pub source_root: Option<PathBuf>,
+    /// Function `hash` (and `raw_hash`) was computed with
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    /// Inode number of the file on Unix, shared by all of its hard links
+    #[serde(default)]
+    pub inode: Option<u64>,
+}
+
+impl FileInfo {
+    /// Hash of the file's bytes as stored on disk, ignoring any text normalization
+    pub fn content_hash(&self) -> &str {
+        self.raw_hash.as_deref().unwrap_or(&self.hash)
+    }
+
+    /// Number of hash output bytes the hash was computed with
+    pub fn hash_len(&self) -> usize {
+        self.content_hash().len() / 2
+    }
+
+    /// A BLAKE3-hashed file with only a path, size and hash, for tests
+    #[cfg(test)]
+    pub(crate) fn fixture(path: &str, size: u64, hash: &str) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            size,
+            hash: hash.to_string(),
+            modified: std::time::UNIX_EPOCH,
+            detected_type: None,
+            raw_hash: None,
+            source_root: None,
+            hash_algorithm: HashAlgorithm::Blake3,
+            inode: None,
+        }
+    }
+}
+
+/// Function used to hash file contents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    /// BLAKE3, with output of any length
+    #[default]
+    Blake3,
+    /// SHA-256, for environments that require it; at most 32 bytes
+    Sha256,
+    /// SHA-1; at most 20 bytes
+    Sha1,
+    /// MD5, to compare with existing checksum lists; at most 16 bytes
+    Md5,
+    /// 128-bit XXH3, fastest but not cryptographic; at most 16 bytes
+    XxHash3,
+}
+
+impl HashAlgorithm {
+    /// Bytes of output the algorithm produces, `None` for extendable output
+    pub fn digest_len(self) -> Option<usize> {
+        match self {
+            HashAlgorithm::Blake3 => None,
+            HashAlgorithm::Sha256 => Some(32),
+            HashAlgorithm::Sha1 => Some(20),
+            HashAlgorithm::Md5 | HashAlgorithm::XxHash3 => Some(16),
+        }
+    }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            HashAlgorithm::Blake3 => "BLAKE3",
+            HashAlgorithm::Sha256 => "SHA-256",
+            HashAlgorithm::Sha1 => "SHA-1",
+            HashAlgorithm::Md5 => "MD5",
+            HashAlgorithm::XxHash3 => "XXH3-128",
+        };
+        f.write_str(name)
+    }
 }
 
 /// Results of a directory scan for duplicate files
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DedupResult {
+    /// Groups of identical files, keyed by group key (the hash plus any extra grouping criteria)
     pub duplicates: HashMap<String, Vec<FileInfo>>,
     pub total_files: usize,
     pub total_size: u64,
+    /// Key groups on size as well as hash, so files of different sizes never group
+    /// even with truncated hashes. Off when hashes cover transformed content
+    /// (normalized text, decompressed data) whose size differs from the file's.
+    #[serde(default = "default_group_by_size")]
+    pub group_by_size: bool,
+    /// Only group files whose names also match
+    pub require_same_name: bool,
+    /// Only group files found under the same input directory
+    pub per_root: bool,
+    /// Files skipped because hashing exceeded the timeout
+    pub timed_out: Vec<PathBuf>,
+    /// Duplicates that already share physical storage with an earlier group member
+    /// (e.g. previous reflinks), so deduplicating them again frees nothing
+    pub already_shared: HashSet<PathBuf>,
+    /// Duplicates that are hard links to an earlier group member; they are neither
+    /// counted nor acted on, so a second hardlink run finds nothing to do. Only
+    /// covers links that ended up in a group with a real copy of the file
+    #[serde(default)]
+    pub hardlinked: HashSet<PathBuf>,
+    /// Every name of each file found through several hard links, one list per
+    /// file, whether or not it has a real copy elsewhere. Only the first name is
+    /// read; the others join its group and are listed in `hardlinked`, and a file
+    /// with no other copy is counted without forming a group at all
+    #[serde(default)]
+    pub already_linked: Vec<Vec<PathBuf>>,
+    /// Files left out by the scan filters, counted by reason
+    #[serde(default)]
+    pub skipped: BTreeMap<SkipReason, usize>,
+    /// Groups were formed from the hash of only this many leading bytes, so they are unverified
+    #[serde(default)]
+    pub prefix_only: Option<u64>,
+    /// Symlinks under the roots whose target doesn't exist, when reporting them was asked for
+    #[serde(default)]
+    pub broken_links: Vec<PathBuf>,
+    /// Files outside the hash-only extensions, counted but never hashed
+    #[serde(default)]
+    pub size_only: SizeOnlyStats,
+    /// The scan stopped at its runtime budget, so files may be missing from the groups
+    #[serde(default)]
+    pub runtime_exceeded: bool,
+    /// Groups were formed from size and name alone, without reading any content
+    #[serde(default)]
+    pub size_and_name_only: bool,
+    /// Files whose size changed between stat and hashing, likely still being
+    /// written; their recorded size is the number of bytes actually hashed
+    #[serde(default)]
+    pub size_changed: Vec<PathBuf>,
+    /// Function every file's hash was computed with; hashes of different
+    /// algorithms are never mixed in one result
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    /// Files without a duplicate are still listed as single-file groups, so a
+    /// scan of another tree can be merged in to find duplicates across both
+    #[serde(default)]
+    pub keeps_unique: bool,
+}
+
+/// Files counted by size alone because hashing was restricted to some extensions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeOnlyStats {
+    pub files: usize,
+    pub bytes: u64,
+    /// Of those, files sharing their size with another file, so they might be duplicates
+    pub same_size: usize,
+}
+
+/// Shared flag used to stop a scan or an action run early
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Rule for choosing which file of a group survives an action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeepStrategy {
+    /// Keep a file from the earliest listed directory that holds a group member
+    DirectoryPriority(Vec<PathBuf>),
+    /// Keep a file whose extension appears earliest in the list, compared case-insensitively
+    PreferExtension(Vec<String>),
+    /// Keep the file the scan found first
+    FirstFound,
+    /// Keep the most recently modified file
+    NewestModified,
+    /// Keep the least recently modified file
+    OldestModified,
+    /// Keep the file with the shortest path
+    ShortestPath,
+    /// Keep the file with the longest path
+    LongestPath,
+    /// Keep a file under this directory
+    PreferDirectory(PathBuf),
+}
+
+/// Parses the `--keep` names: `first`, `newest`, `oldest`, `shortest-path`,
+/// `longest-path` or `dir:PATH`
+impl std::str::FromStr for KeepStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(dir) = s.strip_prefix("dir:") {
+            if dir.is_empty() {
+                return Err("dir: needs a directory, e.g. dir:/photos/curated".to_string());
+            }
+            return Ok(KeepStrategy::PreferDirectory(PathBuf::from(dir)));
+        }
+        match s {
+            "first" => Ok(KeepStrategy::FirstFound),
+            "newest" => Ok(KeepStrategy::NewestModified),
+            "oldest" => Ok(KeepStrategy::OldestModified),
+            "shortest-path" => Ok(KeepStrategy::ShortestPath),
+            "longest-path" => Ok(KeepStrategy::LongestPath),
+            _ => Err(format!(
+                "unknown keep strategy '{}' (expected first, newest, oldest, shortest-path, longest-path or dir:PATH)", s
+            )),
+        }
+    }
 }
 
 /// Actions that can be performed on duplicate files
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde-support", derive(Serialize, Deserialize))]
 pub enum DedupAction {
     /// List duplicate files without taking any action
     List,
@@ -39,6 +284,28 @@ pub enum DedupAction {
     Hardlink,
     /// Create symbolic links for duplicate files
     Symlink,
+    /// Copy one file per group into a directory, skipping content already there
+    Copy(PathBuf),
+    /// Replace duplicates with copy-on-write clones that share the keeper's blocks
+    Reflink,
+    /// Share the keeper's blocks under each duplicate's existing inode, keeping its
+    /// name, permissions and timestamps
+    DedupInPlace,
+    /// Undo the deletes and moves recorded in this revert log, ignoring the groups
+    Revert(PathBuf),
+}
+
+/// Whether a duplicate group holds zero-length files
+pub fn is_empty_group(files: &[FileInfo]) -> bool {
+    files.first().is_some_and(|file| file.size == 0)
+}
+
+/// Whether a group only matched once trailing NUL padding was ignored
+///
+/// Such groups are the only ones whose members have different hashes with no
+/// normalization involved.
+pub fn is_nul_padded_group(files: &[FileInfo]) -> bool {
+    files.iter().all(|file| file.raw_hash.is_none()) && files.iter().any(|file| file.hash != files[0].hash)
 }
 
 impl DedupResult {
@@ -47,41 +314,492 @@ impl DedupResult {
             duplicates: HashMap::new(),
             total_files: 0,
             total_size: 0,
+            group_by_size: true,
+            require_same_name: false,
+            per_root: false,
+            timed_out: Vec::new(),
+            already_shared: HashSet::new(),
+            hardlinked: HashSet::new(),
+            already_linked: Vec::new(),
+            skipped: BTreeMap::new(),
+            prefix_only: None,
+            broken_links: Vec::new(),
+            size_only: SizeOnlyStats::default(),
+            runtime_exceeded: false,
+            size_and_name_only: false,
+            size_changed: Vec::new(),
+            hash_algorithm: HashAlgorithm::default(),
+            keeps_unique: false,
         }
     }
 
-    pub fn add_file(&mut self, file: FileInfo) {
+    /// Add a file to its group
+    ///
+    /// Fails if the file was hashed with a different algorithm than this result's.
+    pub fn add_file(&mut self, file: FileInfo) -> Result<()> {
+        if file.hash_algorithm != self.hash_algorithm {
+            bail!(
+                "{} was hashed with {}, but this result holds {} hashes",
+                file.path.display(), file.hash_algorithm, self.hash_algorithm
+            );
+        }
         self.total_files += 1;
         self.total_size += file.size;
         
         self.duplicates
-            .entry(file.hash.clone())
-            .or_insert_with(Vec::new)
+            .entry(self.group_key(&file))
+            .or_default()
             .push(file);
+        Ok(())
+    }
+
+    /// Key of the group a file belongs to
+    ///
+    /// Hash and size never contain the separators, and a file name never holds a
+    /// `/`. An `@` in the name is escaped (with `%` escaping itself), so the first
+    /// `@` always starts the root, which comes last and needs no escaping.
+    fn group_key(&self, file: &FileInfo) -> String {
+        let mut key = file.hash.clone();
+        if self.group_by_size {
+            key = format!("{}:{}", key, file.size);
+        }
+        if self.require_same_name {
+            let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+            key = format!("{}/{}", key, name.replace('%', "%25").replace('@', "%40"));
+        }
+        if self.per_root {
+            let root = file.source_root.as_deref().unwrap_or(Path::new(""));
+            key = format!("{}@{}", key, root.display());
+        }
+        key
     }
 
     pub fn get_duplicate_count(&self) -> usize {
         self.duplicates
             .values()
-            .map(|files| if files.len() > 1 { files.len() - 1 } else { 0 })
+            .map(|files| self.group_duplicate_count(files))
             .sum()
     }
 
+    /// Extra copies in a group that an action would touch, leaving out hard links to another member
+    pub fn group_duplicate_count(&self, files: &[FileInfo]) -> usize {
+        if is_empty_group(files) {
+            return 0;
+        }
+        files
+            .iter()
+            .skip(1)
+            .filter(|file| !self.hardlinked.contains(&file.path))
+            .count()
+    }
+
+    /// The group a file belongs to, itself included, or `None` if it has no duplicates
+    pub fn duplicates_of(&self, path: &Path) -> Option<&[FileInfo]> {
+        self.duplicates
+            .values()
+            .find(|files| files.len() > 1 && files.iter().any(|file| file.path == path))
+            .map(|files| files.as_slice())
+    }
+
+    /// Groups of zero-length files, which are reported apart from real duplicates
+    pub fn empty_groups(&self) -> impl Iterator<Item = &Vec<FileInfo>> {
+        self.duplicates
+            .values()
+            .filter(|files| files.len() > 1 && is_empty_group(files))
+    }
+
+    /// Number of zero-length files found in duplicate groups
+    pub fn get_empty_file_count(&self) -> usize {
+        self.empty_groups().map(|files| files.len()).sum()
+    }
+
+    /// Space taken by extra copies across all groups
+    ///
+    /// Each member is counted at its own size, which only differs within a group
+    /// when `group_by_size` is off.
     pub fn get_wasted_space(&self) -> u64 {
         self.duplicates
             .values()
-            .map(|files| {
-                if files.len() > 1 {
-                    files[0].size * (files.len() - 1) as u64
-                } else {
-                    0
-                }
-            })
+            .map(|files| self.group_wasted_space(files))
+            .sum()
+    }
+
+    /// Bytes left if every group were reduced to one copy: the scanned total minus the wasted space
+    pub fn unique_bytes(&self) -> u64 {
+        self.total_size.saturating_sub(self.get_wasted_space())
+    }
+
+    /// Bytes scanned per unique byte, e.g. 1.3 when 30% more is stored than needed
+    pub fn dedup_ratio(&self) -> f64 {
+        match self.unique_bytes() {
+            0 => 1.0,
+            unique => self.total_size as f64 / unique as f64,
+        }
+    }
+
+    /// Share of the scanned bytes that removing duplicates would reclaim
+    pub fn reclaimable_percentage(&self) -> f64 {
+        calculate_percentage(self.get_wasted_space(), self.total_size)
+    }
+
+    /// Space taken by a group's extra copies, leaving out those already sharing storage or hard linked
+    ///
+    /// Empty files never count: removing them frees at most a directory entry.
+    pub fn group_wasted_space(&self, files: &[FileInfo]) -> u64 {
+        if is_empty_group(files) {
+            return 0;
+        }
+        files
+            .iter()
+            .skip(1)
+            .filter(|file| !self.already_shared.contains(&file.path) && !self.hardlinked.contains(&file.path))
+            .map(|file| file.size)
             .sum()
     }
 
+    /// Combine another scan's results into this one
+    ///
+    /// Groups with the same key are unioned and totals accumulate, including
+    /// files `other` counted without grouping. A path present in both results
+    /// is only counted once. Groups that are still single files afterwards are
+    /// dropped. Singleton groups already removed by `filter_duplicates` can't be
+    /// recovered, so merge results that keep unique files to find duplicates that
+    /// span both scans. Fails if the two were hashed with different algorithms
+    /// or grouped on different criteria.
+    pub fn merge(&mut self, other: DedupResult) -> Result<()> {
+        self.merge_all(vec![other])
+    }
+
+    /// Merge several results at once, dropping single-file groups only at the end
+    ///
+    /// Merging one at a time would drop a file whose twin is only in a later result.
+    pub fn merge_all(&mut self, others: Vec<DedupResult>) -> Result<()> {
+        for other in others {
+            self.absorb(other)?;
+        }
+        self.filter_duplicates();
+        Ok(())
+    }
+
+    /// Add another result's groups and counts to this one, keeping single-file groups
+    fn absorb(&mut self, other: DedupResult) -> Result<()> {
+        if other.hash_algorithm != self.hash_algorithm {
+            bail!("can't merge {} hashes into a result of {} hashes", other.hash_algorithm, self.hash_algorithm);
+        }
+        let grouping = |result: &DedupResult| {
+            (result.group_by_size, result.require_same_name, result.per_root, result.prefix_only, result.size_and_name_only)
+        };
+        if grouping(&other) != grouping(self) {
+            bail!("can't merge results whose files were grouped differently (size, name, per-root, prefix-only or size-and-name settings)");
+        }
+        self.total_files += other.total_files;
+        self.total_size += other.total_size;
+        for (key, files) in other.duplicates {
+            let group = self.duplicates.entry(key).or_default();
+            for file in files {
+                if group.iter().any(|existing| existing.path == file.path) {
+                    self.total_files -= 1;
+                    self.total_size -= file.size;
+                    continue;
+                }
+                group.push(file);
+            }
+        }
+        self.timed_out.extend(other.timed_out);
+        self.already_shared.extend(other.already_shared);
+        self.hardlinked.extend(other.hardlinked);
+        self.already_linked.extend(other.already_linked);
+        for (reason, count) in other.skipped {
+            *self.skipped.entry(reason).or_default() += count;
+        }
+        self.broken_links.extend(other.broken_links);
+        self.size_only.files += other.size_only.files;
+        self.size_only.bytes += other.size_only.bytes;
+        self.size_only.same_size += other.size_only.same_size;
+        self.runtime_exceeded |= other.runtime_exceeded;
+        self.size_changed.extend(other.size_changed);
+        self.keeps_unique &= other.keeps_unique;
+        Ok(())
+    }
+
+    /// The groups of this result whose key `baseline` doesn't have, e.g. the
+    /// duplicates that appeared since a baseline scan
+    ///
+    /// Counts and other fields are this result's.
+    pub fn diff(&self, baseline: &DedupResult) -> DedupResult {
+        DedupResult {
+            duplicates: self.duplicates
+                .iter()
+                .filter(|(key, _)| !baseline.duplicates.contains_key(*key))
+                .map(|(key, files)| (key.clone(), files.clone()))
+                .collect(),
+            total_files: self.total_files,
+            total_size: self.total_size,
+            group_by_size: self.group_by_size,
+            require_same_name: self.require_same_name,
+            per_root: self.per_root,
+            timed_out: self.timed_out.clone(),
+            already_shared: self.already_shared.clone(),
+            hardlinked: self.hardlinked.clone(),
+            already_linked: self.already_linked.clone(),
+            skipped: self.skipped.clone(),
+            prefix_only: self.prefix_only,
+            broken_links: self.broken_links.clone(),
+            size_only: self.size_only.clone(),
+            runtime_exceeded: self.runtime_exceeded,
+            size_and_name_only: self.size_and_name_only,
+            size_changed: self.size_changed.clone(),
+            hash_algorithm: self.hash_algorithm,
+            keeps_unique: self.keeps_unique,
+        }
+    }
+
     /// Filter out groups that don't have actual duplicates
     pub fn filter_duplicates(&mut self) {
         self.duplicates.retain(|_, files| files.len() > 1);
+        self.keeps_unique = false;
+    }
+
+    /// Drop groups with fewer than `min_copies` members
+    pub fn filter_min_copies(&mut self, min_copies: usize) {
+        self.duplicates.retain(|_, files| files.len() >= min_copies);
+    }
+}
+
+/// Write a scan result to a JSON file, so actions can be decided on later without rescanning
+#[cfg(feature = "serde-support")]
+pub fn save_result(result: &DedupResult, path: &Path) -> Result<()> {
+    envelope::save_json(path, result, "scan result")
+}
+
+/// Read a scan result written by `save_result`
+#[cfg(feature = "serde-support")]
+pub fn load_result(path: &Path) -> Result<DedupResult> {
+    envelope::load_json(path, "scan result")
+}
+
+fn default_group_by_size() -> bool {
+    true
+}
+
+impl Default for DedupResult {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn file(path: &str, hash: &str) -> FileInfo {
+        FileInfo::fixture(path, 1, hash)
+    }
+
+    #[test]
+    fn test_sizes_split_groups_with_equal_hashes() {
+        let mut result = DedupResult::new();
+        result.add_file(file("/a", "x")).unwrap();
+        result.add_file(FileInfo { size: 2, ..file("/b", "x") }).unwrap();
+        result.filter_duplicates();
+        assert!(result.duplicates.is_empty());
+
+        let mut result = DedupResult::new();
+        result.group_by_size = false;
+        result.add_file(file("/a", "x")).unwrap();
+        result.add_file(FileInfo { size: 2, ..file("/b", "x") }).unwrap();
+        result.filter_duplicates();
+        assert_eq!(result.duplicates["x"].len(), 2);
+    }
+
+    #[test]
+    fn test_already_shared_not_wasted() {
+        let mut result = DedupResult::new();
+        result.add_file(file("/a", "x")).unwrap();
+        result.add_file(file("/b", "x")).unwrap();
+        result.add_file(file("/c", "x")).unwrap();
+        assert_eq!(result.get_wasted_space(), 2);
+
+        result.already_shared.insert(PathBuf::from("/c"));
+        assert_eq!(result.get_wasted_space(), 1);
+        assert_eq!(result.get_duplicate_count(), 2);
+
+        result.hardlinked.insert(PathBuf::from("/b"));
+        assert_eq!(result.get_wasted_space(), 0);
+        assert_eq!(result.get_duplicate_count(), 1);
+    }
+
+    #[test]
+    fn test_dedup_ratio() {
+        let mut result = DedupResult::new();
+        assert_eq!(result.dedup_ratio(), 1.0);
+        assert_eq!(result.reclaimable_percentage(), 0.0);
+
+        for path in ["/a", "/b", "/c", "/d"] {
+            result.add_file(FileInfo { size: 10, ..file(path, "x") }).unwrap();
+        }
+        result.add_file(FileInfo { size: 20, ..file("/e", "y") }).unwrap();
+        result.filter_duplicates();
+
+        assert_eq!(result.unique_bytes(), 30);
+        assert_eq!(result.dedup_ratio(), 2.0);
+        assert_eq!(result.reclaimable_percentage(), 50.0);
+    }
+
+    #[test]
+    fn test_duplicates_of() {
+        let mut result = DedupResult::new();
+        result.add_file(file("/a", "x")).unwrap();
+        result.add_file(file("/b", "x")).unwrap();
+        result.add_file(file("/c", "y")).unwrap();
+
+        let group = result.duplicates_of(Path::new("/b")).unwrap();
+        assert_eq!(group.len(), 2);
+        assert!(group.iter().any(|f| f.path == Path::new("/a")));
+        assert!(result.duplicates_of(Path::new("/c")).is_none());
+        assert!(result.duplicates_of(Path::new("/missing")).is_none());
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_filter_min_copies() {
+        let mut result = DedupResult::new();
+        for (hash, copies) in [("two", 2), ("three", 3), ("five", 5)] {
+            for i in 0..copies {
+                result.add_file(file(&format!("/{}/{}", hash, i), hash)).unwrap();
+            }
+        }
+        result.filter_duplicates();
+
+        result.filter_min_copies(3);
+        let mut kept: Vec<&str> = result.duplicates.keys().map(|k| k.as_str()).collect();
+        kept.sort();
+        assert_eq!(kept, ["five:1", "three:1"]);
+
+        result.filter_min_copies(5);
+        assert_eq!(result.duplicates.keys().collect::<Vec<_>>(), ["five:1"]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = DedupResult::new();
+        a.add_file(file("/nas/photo.jpg", "aa")).unwrap();
+        a.add_file(file("/nas/only-here.txt", "bb")).unwrap();
+
+        let mut b = DedupResult::new();
+        b.add_file(file("/usb/photo.jpg", "aa")).unwrap();
+        b.add_file(file("/usb/copy.jpg", "aa")).unwrap();
+        b.add_file(file("/nas/photo.jpg", "aa")).unwrap();
+
+        a.merge(b).unwrap();
+
+        assert_eq!(a.total_files, 4);
+        assert_eq!(a.total_size, 4);
+        assert_eq!(a.duplicates.len(), 1);
+        let group = &a.duplicates["aa:1"];
+        assert_eq!(group.len(), 3);
+        assert!(group.iter().any(|f| f.path == Path::new("/nas/photo.jpg")));
+        assert!(group.iter().any(|f| f.path == Path::new("/usb/copy.jpg")));
+    }
+
+    #[test]
+    fn test_merge_all_and_diff() {
+        let tree = |paths: &[(&str, &str)]| {
+            let mut result = DedupResult { keeps_unique: true, ..DedupResult::new() };
+            for (path, hash) in paths {
+                result.add_file(file(path, hash)).unwrap();
+            }
+            result
+        };
+        let baseline = {
+            let mut result = tree(&[("/nas/a", "aa"), ("/nas/b", "bb")]);
+            result.merge(tree(&[("/usb/a", "aa")])).unwrap();
+            result
+        };
+        assert_eq!(baseline.duplicates.keys().collect::<Vec<_>>(), ["aa:1"]);
+        assert!(!baseline.keeps_unique);
+
+        // "bb" only finds its twin in the last result
+        let mut merged = tree(&[("/nas/a", "aa"), ("/nas/b", "bb")]);
+        merged.merge_all(vec![tree(&[("/usb/a", "aa")]), tree(&[("/cloud/b", "bb"), ("/cloud/c", "cc")])]).unwrap();
+        assert_eq!(merged.total_files, 5);
+        assert_eq!(merged.duplicates.len(), 2);
+        assert_eq!(merged.duplicates["bb:1"].len(), 2);
+
+        let new = merged.diff(&baseline);
+        assert_eq!(new.duplicates.keys().collect::<Vec<_>>(), ["bb:1"]);
+        assert_eq!(new.total_files, 5);
+
+        let by_name = DedupResult { require_same_name: true, ..DedupResult::new() };
+        assert!(merged.merge(by_name).is_err());
+    }
+
+    #[cfg(feature = "serde-support")]
+    #[test]
+    fn test_save_and_load_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.json");
+        let mut result = DedupResult { hash_algorithm: HashAlgorithm::Sha256, ..DedupResult::new() };
+        result.add_file(FileInfo { hash_algorithm: HashAlgorithm::Sha256, ..file("/a/x", "aa") }).unwrap();
+        result.add_file(FileInfo { hash_algorithm: HashAlgorithm::Sha256, ..file("/b/x", "aa") }).unwrap();
+        result.skipped.insert(SkipReason::Excluded, 3);
+        save_result(&result, &path).unwrap();
+
+        let loaded = load_result(&path).unwrap();
+        assert_eq!(loaded.total_files, 2);
+        assert_eq!(loaded.hash_algorithm, HashAlgorithm::Sha256);
+        assert_eq!(loaded.skipped[&SkipReason::Excluded], 3);
+        let paths: Vec<&Path> = loaded.duplicates["aa:1"].iter().map(|f| f.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("/a/x"), Path::new("/b/x")]);
+        assert!(load_result(&dir.path().join("missing.json")).is_err());
+    }
+
+    #[test]
+    fn test_mixed_hash_algorithms_rejected() {
+        let mut result = DedupResult::new();
+        result.add_file(file("/a", "aa")).unwrap();
+        let md5 = FileInfo { hash_algorithm: HashAlgorithm::Md5, ..file("/b", "aa") };
+        assert!(result.add_file(md5.clone()).is_err());
+        assert_eq!(result.total_files, 1);
+
+        let mut other = DedupResult { hash_algorithm: HashAlgorithm::Md5, ..DedupResult::new() };
+        other.add_file(md5).unwrap();
+        assert!(result.merge(other).is_err());
+    }
+
+    #[test]
+    fn test_require_same_name() {
+        let files = [file("/a/logo.png", "aa"), file("/b/logo.png", "aa"), file("/c/logo_copy.png", "aa")];
+
+        let mut result = DedupResult::new();
+        for f in &files {
+            result.add_file(f.clone()).unwrap();
+        }
+        result.filter_duplicates();
+        assert_eq!(result.get_duplicate_count(), 2);
+
+        let mut result = DedupResult::new();
+        result.require_same_name = true;
+        for f in &files {
+            result.add_file(f.clone()).unwrap();
+        }
+        result.filter_duplicates();
+        assert_eq!(result.duplicates.len(), 1);
+        assert_eq!(result.get_duplicate_count(), 1);
+        assert!(result.duplicates.values().all(|g| g.iter().all(|f| f.path.ends_with("logo.png"))));
+    }
+
+    #[test]
+    fn test_name_and_root_keys_stay_apart() {
+        let mut result = DedupResult::new();
+        result.require_same_name = true;
+        result.per_root = true;
+        let at_in_name = FileInfo { source_root: Some(PathBuf::from("c")), ..file("c/a@b", "aa") };
+        let at_in_root = FileInfo { source_root: Some(PathBuf::from("b@c")), ..file("b@c/a", "aa") };
+        result.add_file(at_in_name.clone()).unwrap();
+        result.add_file(at_in_name).unwrap();
+        result.add_file(at_in_root).unwrap();
+        assert_eq!(result.duplicates.len(), 2);
+        assert!(result.duplicates.values().any(|group| group.len() == 2));
+    }
+}
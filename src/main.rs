@@ -1,8 +1,28 @@
-use clap::{Parser, ValueEnum};
-use anyhow::Result;
+use clap::{Parser, Subcommand, ValueEnum};
+use anyhow::{Context, Result};
 use std::path::PathBuf;
 use console::style;
-use file_deduplication::{Scanner, DedupAction, DedupResult, perform_deduplication};
+use file_deduplication::{is_nul_padded_group, CancelToken, Scanner, DedupAction, DedupResult, FileInfo, HashAlgorithm, KeepStrategy, perform_deduplication};
+use file_deduplication::actions::{action_name, select_keeper, ActionConfig, GroupOrder, MoveManifest};
+use file_deduplication::dedup::{
+    estimate_savings, find_name_conflicts, is_decompressed_group, is_normalized_group, print_name_conflicts,
+    print_directory_waste, print_recommendations, print_savings_estimate, recommend_removals, waste_by_directory,
+};
+use file_deduplication::blocks::{analyze_blocks, BlockReport};
+use file_deduplication::category::CategoryMap;
+use file_deduplication::crossmatch::{self, CrossmatchReport, HashTable};
+use file_deduplication::drift::{verify_report, FileDrift, ReportDrift};
+use file_deduplication::envelope::save_json;
+use file_deduplication::exclude::ExcludeRules;
+use file_deduplication::filter_cmd::FilterCommand;
+use file_deduplication::report::Report;
+use file_deduplication::revert::RevertLog;
+use file_deduplication::wizard::run_wizard;
+use file_deduplication::shard::{merge_shards, Shard, ShardReport};
+#[cfg(feature = "serde-support")]
+use file_deduplication::{load_result, save_result};
+use regex::Regex;
+use file_deduplication::utils::{filesystem_block_size, format_duration, parse_duration, root_containing, short_hash, NameTemplate, DEFAULT_BLOCK_SIZE, DEFAULT_HASH_LEN, MIN_SAFE_HASH_LEN};
 
 #[derive(Debug, Clone, ValueEnum)]
 enum ActionType {
@@ -16,6 +36,53 @@ enum ActionType {
     Hardlink,
     /// Create symbolic links for duplicate files
     Symlink,
+    /// Copy one file per group into a directory (non-destructive)
+    Copy,
+    /// Replace duplicates with copy-on-write clones (ReFS on Windows)
+    Reflink,
+    /// Share storage with the kept file while leaving each duplicate's path and metadata intact
+    DedupInPlace,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+enum OrderBy {
+    /// Process groups in scan order
+    None,
+    /// Process groups wasting the most space first
+    SizeDesc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// One line per group, or every file with --verbose
+    Normal,
+    /// Exactly one line per group: wasted bytes, duplicate count, keeper name and short hash
+    Compact,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum PrintSelect {
+    /// The file each group would keep
+    Keepers,
+    /// Every other file in each group
+    Victims,
+    /// All files in each group
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum HashAlgo {
+    /// BLAKE3: fast and cryptographic
+    Blake3,
+    /// SHA-256, where compliance requires it
+    Sha256,
+    /// SHA-1
+    Sha1,
+    /// MD5, to match existing checksum lists
+    Md5,
+    /// 128-bit XXH3: fastest, but not cryptographic
+    #[value(name = "xxh3")]
+    XxHash3,
 }
 
 #[derive(Parser)]
@@ -26,6 +93,9 @@ enum ActionType {
     long_about = "Scan directories, identify duplicate files based on hash comparisons, and provide options to delete, move, or link duplicates to save disk space."
 )]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Directory paths to scan for duplicates
     #[arg(
         short,
@@ -45,6 +115,15 @@ struct Cli {
     )]
     action: ActionType,
 
+    /// Action to use where the main one can't apply
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ACTION",
+        help = "Action to try when the main action can't apply to a file (e.g. hardlink across filesystems)"
+    )]
+    fallback: Option<ActionType>,
+
     /// Directory to move duplicate files to (required for move action)
     #[arg(
         long,
@@ -53,187 +132,2021 @@ struct Cli {
     )]
     move_to: Option<PathBuf>,
 
-    /// Perform a dry run without making actual changes
+    /// Record each completed move as a JSON line
     #[arg(
         long,
-        help = "Show what would be done without making changes"
+        value_name = "FILE",
+        requires = "move_to",
+        help = "Append every completed move (source, target, size, hash) to FILE as JSON lines, synced after each move"
     )]
-    dry_run: bool,
+    move_manifest: Option<PathBuf>,
 
-    /// Minimum file size to consider (in bytes)
+    /// Record deletes and moves so they can be undone
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write every delete and move to FILE so `dedup revert FILE` can undo them"
+    )]
+    revert_log: Option<PathBuf>,
+
+    /// Allow --move-to or --copy-to inside a scanned directory
+    #[arg(
+        long,
+        help = "Allow the move or copy target to be inside a scanned directory (warn instead of failing)"
+    )]
+    allow_target_in_scan: bool,
+
+    /// Order in which duplicate groups are processed
+    #[arg(
+        long,
+        value_enum,
+        default_value = "none",
+        help = "Order in which duplicate groups are processed"
+    )]
+    order_by: OrderBy,
+
+    /// Stop once this many bytes have been freed
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Stop processing groups once this many bytes have been freed"
+    )]
+    stop_after: Option<u64>,
+
+    /// Only act on groups whose hash starts with this prefix
+    #[arg(
+        long,
+        value_name = "HEX",
+        help = "Only act on groups whose hash starts with this hex prefix, for cleaning up in deterministic stages (e.g. 0 to f)"
+    )]
+    only_hash_prefix: Option<String>,
+
+    /// Retry transient I/O failures during actions
     #[arg(
         long,
         default_value = "0",
-        help = "Minimum file size in bytes to consider"
+        help = "Retry transient I/O errors during actions this many times, with exponential backoff capped at 30s"
     )]
-    min_size: u64,
+    retries: u32,
 
-    /// Maximum file size to consider (in bytes)
+    /// Freedesktop trash directory to delete into
     #[arg(
         long,
-        help = "Maximum file size in bytes to consider"
+        value_name = "DIR",
+        help = "Move deleted duplicates into this freedesktop.org trash directory (e.g. /mnt/data/.Trash-1000) so they can be restored"
     )]
-    max_size: Option<u64>,
+    trash_dir: Option<PathBuf>,
 
-    /// File extensions to include (e.g., jpg,png,pdf)
+    /// Remove source directories emptied by the move action
+    #[arg(
+        long,
+        help = "Remove source directories left empty after moving duplicates"
+    )]
+    delete_empty_after_move: bool,
+
+    /// Move or copy even when the target filesystem looks too full
+    #[arg(
+        long,
+        help = "Move or copy even if the target filesystem doesn't have room for every planned file"
+    )]
+    ignore_free_space: bool,
+
+    /// Skip duplicates that are open or locked by another process
+    #[arg(
+        long,
+        help = "Skip duplicates that are open or locked by another process"
+    )]
+    skip_in_use: bool,
+
+    /// Byte-compare duplicates with the keeper before acting
+    #[arg(
+        long,
+        help = "Compare each duplicate byte for byte with its keeper before acting on it"
+    )]
+    verify: bool,
+
+    /// Re-check keepers and links once the action has run
+    #[arg(
+        long,
+        help = "After acting, re-hash each keeper and check that links and clones resolve to its content"
+    )]
+    post_verify: bool,
+
+    /// Conservative mode for deletions
+    #[arg(
+        long,
+        help = "Only delete duplicates verified byte for byte against a separate keeper on persistent storage"
+    )]
+    safe: bool,
+
+    /// Block size below which duplicates free no space (detected from the filesystem by default)
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Skip files smaller than this in destructive actions [default: filesystem block size]"
+    )]
+    min_block_size: Option<u64>,
+
+    /// Act on files smaller than one filesystem block too
+    #[arg(
+        long,
+        help = "Also act on files smaller than one filesystem block, even though removing them frees nothing"
+    )]
+    include_sub_block: bool,
+
+    /// Link duplicates to the real file when the keeper is a symlink
+    #[arg(
+        long,
+        help = "Resolve a symlinked keeper to its target before hardlinking/symlinking"
+    )]
+    dereference_keeper: bool,
+
+    /// Give the keeper the newest mtime of its group
+    #[arg(
+        long,
+        help = "After acting on a group, set the keeper's modification time to the newest among its members"
+    )]
+    touch_keeper_newest: bool,
+
+    /// Directories to keep files from, highest priority first
+    #[arg(
+        long = "priority-dir",
+        value_name = "PATH",
+        help = "Keep the copy from the earliest listed directory that has one (repeatable, order matters)"
+    )]
+    priority_dirs: Vec<PathBuf>,
+
+    /// Extensions to keep, most preferred first (e.g., jpg,png)
     #[arg(
         long,
         value_delimiter = ',',
-        help = "File extensions to include (comma-separated)"
+        value_name = "EXTS",
+        help = "Keep the copy whose extension comes earliest in the list, case-insensitive (comma-separated, applied after --priority-dir)"
     )]
-    include_ext: Vec<String>,
+    prefer_ext: Vec<String>,
 
-    /// File extensions to exclude (e.g., tmp,log)
+    /// Rules for the copy to keep, applied after --priority-dir and --prefer-ext
+    #[arg(
+        long,
+        value_name = "STRATEGY",
+        help = "Keep the first, newest, oldest, shortest-path or longest-path copy, or one under dir:PATH (repeatable, later rules break ties)"
+    )]
+    keep: Vec<KeepStrategy>,
+
+    /// Treat different path spellings of the same file as one file
+    #[arg(
+        long,
+        help = "Resolve each path to its canonical form so a file reached twice isn't its own duplicate"
+    )]
+    canonicalize_paths: bool,
+
+    /// Follow symlinks inside the scanned directories
+    #[arg(
+        long,
+        help = "Follow symlinks inside the scanned directories, walking each linked directory once even through cycles"
+    )]
+    follow_symlinks: bool,
+
+    /// Only treat files as duplicates when their names also match
+    #[arg(
+        long,
+        help = "Only group files whose file names also match"
+    )]
+    require_same_name: bool,
+
+    /// Only compare files within the same --dir
+    #[arg(
+        long,
+        help = "Find duplicates within each --dir separately, never across them"
+    )]
+    per_dir: bool,
+
+    /// Ignore line endings and a UTF-8 BOM when hashing text files
+    #[arg(
+        long,
+        help = "Group text files that differ only in line endings or a UTF-8 BOM (never deleted or moved)"
+    )]
+    normalize_text: bool,
+
+    /// Match files that differ only by trailing NUL padding
+    #[arg(
+        long,
+        help = "Also group files that differ only by trailing NUL bytes, keeping the unpadded copy by default"
+    )]
+    match_nul_padding: bool,
+
+    /// Detect file content types from magic bytes
+    #[arg(
+        long,
+        help = "Detect each file's content type from its magic bytes"
+    )]
+    detect_type: bool,
+
+    /// Content categories to scan (e.g., image,video,document)
     #[arg(
         long,
         value_delimiter = ',',
-        help = "File extensions to exclude (comma-separated)"
+        value_name = "CATEGORIES",
+        help = "Only scan files whose detected content type is in these categories (comma-separated: archive, audio, document, executable, font, image, video)"
     )]
-    exclude_ext: Vec<String>,
+    category: Vec<String>,
 
-    /// Skip confirmation prompts
+    /// Category definitions overriding or adding to the built-in ones
+    #[arg(
+        long = "category-def",
+        value_name = "NAME=MIMES",
+        requires = "category",
+        help = "Define or replace a category as MIME types, '*' matching any suffix (e.g. raw=image/x-canon-cr2,image/tiff; repeatable)"
+    )]
+    category_defs: Vec<String>,
+
+    /// Shell command deciding per file whether it is scanned
     #[arg(
-        short,
         long,
-        help = "Skip confirmation prompts (use with caution)"
+        value_name = "COMMAND",
+        help = "Only scan files for which this shell command, run with the file's path appended, exits with status 0 (slow: one process per file)"
     )]
-    yes: bool,
+    filter_cmd: Option<String>,
 
-    /// Verbose output
+    /// How many filter commands run at once
     #[arg(
-        short,
         long,
-        help = "Enable verbose output"
+        value_name = "N",
+        default_value = "4",
+        requires = "filter_cmd",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "How many filter commands may run at once"
     )]
-    verbose: bool,
+    filter_cmd_jobs: u64,
 
-    /// Number of threads to use for parallel processing
+    /// How long one filter command may run
     #[arg(
         long,
-        default_value = "0",
-        help = "Number of threads (0 = auto-detect)"
+        value_name = "DURATION",
+        default_value = "10s",
+        requires = "filter_cmd",
+        value_parser = parse_duration,
+        help = "Kill a filter command after this long and leave its file out"
     )]
-    threads: usize,
-}
+    filter_cmd_timeout: std::time::Duration,
 
-fn main() -> Result<()> {
-    let args = Cli::parse();
+    /// Suggest directories to clear of duplicates
+    #[arg(
+        long,
+        help = "Recommend directories whose duplicates can be removed for the most savings"
+    )]
+    recommend: bool,
 
-    // Set up thread pool if specified
-    if args.threads > 0 {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(args.threads)
-            .build_global()
-            .unwrap();
-    }
+    /// Roll wasted space up by directory
+    #[arg(
+        long,
+        help = "Show wasted space and duplicate counts rolled up by directory, largest first"
+    )]
+    by_directory: bool,
 
-    // Validate arguments
-    if args.dir.is_empty() {
-        eprintln!("{}", style("Error: At least one directory must be specified").red());
-        std::process::exit(1);
-    }
+    /// Write the directory rollup as a JSON tree
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write wasted space rolled up by directory to a JSON tree file"
+    )]
+    by_directory_out: Option<PathBuf>,
 
-    if matches!(args.action, ActionType::Move) && args.move_to.is_none() {
-        eprintln!("{}", style("Error: --move-to is required when using move action").red());
-        std::process::exit(1);
-    }
+    /// Hash gzip files by their uncompressed content
+    #[cfg(feature = "decompress")]
+    #[arg(
+        long,
+        help = "Group .gz files with uncompressed copies of their content (report only)"
+    )]
+    decompress_aware: bool,
 
-    // Create scanner with filters
-    let mut scanner = Scanner::new();
-    scanner.set_min_size(args.min_size);
-    if let Some(max_size) = args.max_size {
-        scanner.set_max_size(max_size);
-    }
-    scanner.set_include_extensions(args.include_ext);
-    scanner.set_exclude_extensions(args.exclude_ext);
-    scanner.set_verbose(args.verbose);
+    /// Pick the keeper of each group in a guided prompt
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["print0", "list_keepers", "list_victims", "bench", "yes"],
+        help = "Go through the groups one by one, showing file details and choosing which file to keep before acting"
+    )]
+    interactive: bool,
 
-    println!("{}", style("🔍 Scanning directories for duplicate files...").cyan().bold());
+    /// Review groups interactively before acting
+    #[cfg(feature = "tui")]
+    #[arg(
+        long,
+        conflicts_with = "interactive",
+        help = "Review duplicate groups in an interactive table, choose which files to keep and apply the action from there"
+    )]
+    tui: bool,
 
-    // Scan directories
-    let scan_result = scanner.scan_directories(&args.dir)?;
-    
-    if scan_result.duplicates.is_empty() {
-        println!("{}", style("✅ No duplicate files found!").green().bold());
-        return Ok(());
-    }
+    /// Report files that share content-defined chunks
+    #[cfg(feature = "chunking")]
+    #[arg(
+        long,
+        help = "Report pairs of files that share large regions of content"
+    )]
+    chunk_report: bool,
 
-    // Display results
-    display_results(&scan_result, args.verbose)?;
+    /// Minimum shared percentage for the chunk report
+    #[cfg(feature = "chunking")]
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value = "50",
+        help = "Minimum shared percentage of the smaller file to report a pair"
+    )]
+    chunk_min_overlap: f64,
 
-    // Perform action
-    let action = match args.action {
-        ActionType::List => DedupAction::List,
-        ActionType::Delete => DedupAction::Delete,
-        ActionType::Move => DedupAction::Move(args.move_to.unwrap()),
-        ActionType::Hardlink => DedupAction::Hardlink,
-        ActionType::Symlink => DedupAction::Symlink,
-    };
+    /// Size tolerance for near-duplicate candidates
+    #[cfg(feature = "chunking")]
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        requires = "chunk_report",
+        help = "Only compare files in the chunk report whose sizes are within PERCENT of each other (exact duplicates are unaffected)"
+    )]
+    dedup_within_size_tolerance: Option<f64>,
 
-    if !matches!(action, DedupAction::List) {
-        if args.dry_run {
-            println!("{}", style("🧪 Dry run mode - no changes will be made").yellow().bold());
-        } else if !args.yes {
-            let proceed = dialoguer::Confirm::new()
-                .with_prompt("Do you want to proceed with the selected action?")
-                .interact()?;
-            
-            if !proceed {
-                println!("{}", style("Operation cancelled").yellow());
-                return Ok(());
-            }
-        }
+    /// Write a JSON report of the scan
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write a JSON report with duplicate groups and aggregate statistics"
+    )]
+    report: Option<PathBuf>,
 
-        perform_deduplication(&scan_result, action, args.dry_run)?;
-    }
+    /// Seconds between autosaves of the in-progress report
+    #[arg(
+        long,
+        value_name = "SECS",
+        requires = "report",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Rewrite the --report file with the files hashed so far every SECS seconds, so a crash doesn't lose them"
+    )]
+    autosave_interval: Option<u64>,
 
-    Ok(())
-}
+    /// File of hashes kept between scans
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Keep content hashes in FILE and only rehash files whose size or modification time changed since the last scan"
+    )]
+    cache: Option<PathBuf>,
 
-fn display_results(result: &DedupResult, verbose: bool) -> Result<()> {
-    use humansize::{format_size, DECIMAL};
-    
-    println!();
-    println!("{}", style("📊 Duplicate Files Found").cyan().bold());
-    println!("{}", style("=".repeat(40)).cyan());
-    
-    let mut total_duplicates = 0;
-    let mut total_waste = 0u64;
-    
-    for (hash, files) in &result.duplicates {
-        if files.len() > 1 {
-            total_duplicates += files.len() - 1; // Don't count the original
-            let file_size = files[0].size;
-            let waste = file_size * (files.len() - 1) as u64;
-            total_waste += waste;
-            
-            if verbose {
-                println!();
-                println!("{} {} ({})", 
-                    style("Hash:").bold(), 
-                    &hash[..16], 
-                    format_size(file_size, DECIMAL)
-                );
-                for (i, file) in files.iter().enumerate() {
-                    let marker = if i == 0 { "📄" } else { "🔗" };
-                    println!("  {} {}", marker, file.path.display());
-                }
-            } else {
-                println!("{} duplicate files for {} ({})", 
-                    files.len() - 1, 
-                    files[0].path.file_name().unwrap_or_default().to_string_lossy(),
-                    format_size(waste, DECIMAL)
-                );
-            }
-        }
-    }
-    
-    println!();
-    println!("{}", style("📈 Summary").green().bold());
-    println!("{}", style("-".repeat(20)).green());
-    println!("Total files scanned: {}", result.total_files);
-    println!("Duplicate files found: {}", total_duplicates);
-    println!("Potential space savings: {}", format_size(total_waste, DECIMAL));
+    /// Write report paths relative to their scan root
+    #[arg(
+        long,
+        requires = "report",
+        help = "Write --report group paths relative to the scan root they were found under, listing the roots separately"
+    )]
+    report_relative_paths: bool,
+
+    /// Directory to copy one file per group into (required for copy action)
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Target directory for copy action"
+    )]
+    copy_to: Option<PathBuf>,
+
+    /// Keep timestamps, permissions and extended attributes on copies
+    #[arg(
+        long,
+        help = "Preserve timestamps, permissions and extended attributes when copying (copy action, cross-device moves)"
+    )]
+    preserve_metadata: bool,
+
+    /// File name template for moved or copied files
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "Name moved or copied files from a template, e.g. '{stem}_{hash8}{ext}' (tokens: stem, ext, hash, hashN, counter, timestamp)"
+    )]
+    move_template: Option<String>,
+
+    /// Perform a dry run without making actual changes
+    #[arg(
+        long,
+        help = "Show what would be done without making changes"
+    )]
+    dry_run: bool,
+
+    /// Minimum file size to consider (in bytes)
+    #[arg(
+        long,
+        default_value = "0",
+        help = "Minimum file size in bytes to consider"
+    )]
+    min_size: u64,
+
+    /// Hash only one slice of the files, for splitting a scan across workers
+    #[arg(
+        long,
+        value_name = "K/N",
+        requires = "shard_out",
+        help = "Only hash files whose path hash modulo N equals K and write a partial report for `dedup merge`"
+    )]
+    shard: Option<Shard>,
+
+    /// Where to write this shard's partial report
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "shard",
+        help = "File to write the partial report to when using --shard"
+    )]
+    shard_out: Option<PathBuf>,
+
+    /// Persist the scan result before acting on it
+    #[cfg(feature = "serde-support")]
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Save the scan result to FILE before any action is taken, to act on it later with --load-result"
+    )]
+    save_result: Option<PathBuf>,
+
+    /// Act on a saved scan result instead of scanning
+    #[cfg(feature = "serde-support")]
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["dir", "shard", "bench", "only_duplicated_names", "save_result"],
+        help = "Skip scanning and act on a result saved with --save-result"
+    )]
+    load_result: Option<PathBuf>,
+
+    /// Hash every file and keep unique ones in the saved result
+    #[cfg(feature = "serde-support")]
+    #[arg(
+        long,
+        requires = "save_result",
+        help = "Hash every file and keep those without a duplicate in --save-result, so another tree's scan can be merged with it"
+    )]
+    keep_unique: bool,
+
+    /// Saved results to combine with this run's
+    #[cfg(feature = "serde-support")]
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["shard", "bench", "only_duplicated_names"],
+        help = "Merge in a result saved with --save-result --keep-unique, finding duplicates that span both trees (repeatable)"
+    )]
+    merge_result: Vec<PathBuf>,
+
+    /// Hash function for file contents
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ALGO",
+        default_value = "blake3",
+        help = "Hash function for file contents"
+    )]
+    hash_algo: HashAlgo,
+
+    /// Number of hash output bytes per content hash
+    #[arg(
+        long,
+        default_value_t = DEFAULT_HASH_LEN,
+        help = "Bytes of hash output per content hash (1-64, capped at the algorithm's digest size); shorter hashes risk collisions"
+    )]
+    hash_bytes: usize,
+
+    /// Group on a hash of each file's first BYTES bytes plus its size
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Fast, unverified mode: group files on their size and a hash of their first BYTES bytes only (destructive actions require --verify)"
+    )]
+    prefix_only: Option<u64>,
+
+    /// Treat files with equal size and name as duplicates without hashing
+    #[arg(
+        long,
+        help = "Trusted data only: treat files with the same size and name as duplicates without reading them (destructive actions require --i-know-what-im-doing)"
+    )]
+    assume_identical_by_size_and_name: bool,
+
+    /// Allow destructive actions on size-and-name groups
+    #[arg(
+        long,
+        help = "Allow destructive actions on groups formed by --assume-identical-by-size-and-name, also when loaded or merged"
+    )]
+    i_know_what_im_doing: bool,
+
+    /// Maximum file size to consider (in bytes)
+    #[arg(
+        long,
+        help = "Maximum file size in bytes to consider"
+    )]
+    max_size: Option<u64>,
+
+    /// How many directory levels to descend below each --dir
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Only descend N directory levels below each --dir (1 scans just the files directly in it)"
+    )]
+    max_depth: Option<usize>,
+
+    /// How many directory levels below each --dir a file must be
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Only scan files at least N levels below each --dir (2 skips the files directly in it)"
+    )]
+    min_depth: Option<usize>,
+
+    /// Only report groups with at least this many copies
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "2",
+        help = "Only consider groups with at least this many copies"
+    )]
+    min_copies: usize,
+
+    /// File extensions to include (e.g., jpg,png,pdf)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "File extensions to include (comma-separated)"
+    )]
+    include_ext: Vec<String>,
+
+    /// File extensions to exclude (e.g., tmp,log)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "File extensions to exclude (comma-separated)"
+    )]
+    exclude_ext: Vec<String>,
+
+    /// Only hash files with these extensions; count everything else by size
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Only hash files with these extensions (comma-separated); other files are still counted by size in the summary"
+    )]
+    hash_only_ext: Vec<String>,
+
+    /// Skip macOS metadata files
+    #[arg(
+        long,
+        help = "Skip macOS metadata: AppleDouble '._' files, .DS_Store and volume/__MACOSX folders"
+    )]
+    ignore_apple_metadata: bool,
+
+    /// Skip hidden files and directories
+    #[arg(
+        long,
+        help = "Skip hidden files: names starting with '.', anything in such a directory, and files with the hidden attribute on Windows"
+    )]
+    exclude_hidden: bool,
+
+    /// Skip operating system files
+    #[arg(
+        long,
+        help = "Skip operating system files such as desktop.ini, Thumbs.db and pagefile.sys"
+    )]
+    exclude_system_files: bool,
+
+    /// List symlinks whose target doesn't exist
+    #[arg(
+        long,
+        help = "Report dangling symlinks found under the scanned directories (count, and the list with --verbose or --report); they are never acted on"
+    )]
+    report_broken_links: bool,
+
+    /// List files found under several hard-linked names
+    #[arg(
+        long,
+        help = "List files found under several names because they are already hard linked; each is read only once"
+    )]
+    show_already_linked: bool,
+
+    /// Files of exclude patterns, one glob or path per line
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read exclude patterns from FILE, one glob or path per line, '#' for comments (can be specified multiple times)"
+    )]
+    exclude_from: Vec<PathBuf>,
+
+    /// Globs a file's full path must match one of
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Only scan files whose full path matches GLOB, e.g. '**/photos/**' (can be specified multiple times)"
+    )]
+    include_pattern: Vec<String>,
+
+    /// Globs of full paths to leave out
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Skip files whose full path matches GLOB, e.g. '**/node_modules/**' (can be specified multiple times)"
+    )]
+    exclude_pattern: Vec<String>,
+
+    /// Regular expression a file's full path must match
+    #[arg(
+        long,
+        value_name = "REGEX",
+        value_parser = Regex::new,
+        help = "Only scan files whose full path matches REGEX"
+    )]
+    include_regex: Option<Regex>,
+
+    /// Regular expression of full paths to leave out
+    #[arg(
+        long,
+        value_name = "REGEX",
+        value_parser = Regex::new,
+        help = "Skip files whose full path matches REGEX"
+    )]
+    exclude_regex: Option<Regex>,
+
+    /// Skip confirmation prompts
+    #[arg(
+        short,
+        long,
+        help = "Skip confirmation prompts (use with caution)"
+    )]
+    yes: bool,
+
+    /// Hide progress indicators
+    #[arg(
+        short,
+        long,
+        help = "Hide progress bars and spinners"
+    )]
+    quiet: bool,
+
+    /// Print duplicate paths separated by NUL bytes
+    #[arg(
+        long,
+        help = "In list mode, print only duplicate paths separated by NUL bytes (for xargs -0)"
+    )]
+    print0: bool,
+
+    /// Which paths --print0 prints
+    #[arg(
+        long,
+        value_enum,
+        default_value = "victims",
+        requires = "print0",
+        help = "Which files --print0 prints"
+    )]
+    print_select: PrintSelect,
+
+    /// Print only the file each group keeps
+    #[arg(
+        long,
+        conflicts_with_all = ["list_victims", "print_select"],
+        help = "In list mode, print only the file each group would keep, one per line (NUL-separated with --print0)"
+    )]
+    list_keepers: bool,
+
+    /// Print only the files each group would act on
+    #[arg(
+        long,
+        conflicts_with = "print_select",
+        help = "In list mode, print only the files an action would remove or replace, one per line (NUL-separated with --print0)"
+    )]
+    list_victims: bool,
+
+    /// Report same-named files with different content instead of duplicates
+    #[arg(
+        long,
+        conflicts_with_all = ["shard", "bench", "print0", "list_keepers", "list_victims", "report"],
+        help = "Report file names that exist with different contents (e.g. several versions of report.docx) instead of duplicates; report only"
+    )]
+    only_duplicated_names: bool,
+
+    /// Time the scan without any other output
+    #[arg(
+        long,
+        help = "Benchmark mode: scan and hash without progress, results or actions, then print timing and throughput"
+    )]
+    bench: bool,
+
+    /// Write a per-phase timing breakdown as JSON
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write time spent per phase (collection, size grouping, hashing, actions) and files left after each stage to FILE as JSON"
+    )]
+    profile: Option<PathBuf>,
+
+    /// Exit with status 2 when duplicates are found
+    #[arg(
+        long,
+        help = "Exit with status 2 if any duplicates are found (for CI checks)"
+    )]
+    fail_on_duplicates: bool,
+
+    /// Print the exit status contract and exit
+    #[arg(
+        long,
+        help = "Print the meaning of each exit status and exit"
+    )]
+    print_exit_codes: bool,
+
+    /// Disable colored output
+    #[arg(
+        long,
+        help = "Disable colored output"
+    )]
+    no_color: bool,
+
+    /// Verbose output
+    #[arg(
+        short,
+        long,
+        help = "Enable verbose output"
+    )]
+    verbose: bool,
+
+    /// How duplicate groups are printed
+    #[arg(
+        long,
+        value_enum,
+        default_value = "normal",
+        help = "Group output format: normal, or compact for one sortable line per group"
+    )]
+    format: OutputFormat,
+
+    /// Write failed operations as JSON lines
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write each failed operation as a JSON line (path, action, error kind, OS error code) to this file"
+    )]
+    json_errors: Option<PathBuf>,
+
+    /// Write a log of every operation to this file
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Append a log of every file operation to this file (RUST_LOG sets the level)"
+    )]
+    log_file: Option<PathBuf>,
+
+    /// Per-file hashing timeout in seconds
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Skip files whose hashing takes longer than this many seconds"
+    )]
+    hash_timeout: Option<u64>,
+
+    /// Time budget for collecting and hashing
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "Stop scanning after this long (e.g. 90s, 15m, 1h30m) and report the partial results"
+    )]
+    max_runtime: Option<std::time::Duration>,
+
+    /// Act on a scan cut short by --max-runtime, in this run or in a loaded result
+    #[arg(
+        long,
+        help = "Allow destructive actions even if --max-runtime cut the scan short, also when loaded or merged"
+    )]
+    allow_incomplete: bool,
+
+    /// Number of threads to use for parallel processing
+    #[arg(
+        long,
+        default_value = "0",
+        help = "Number of threads (0 = auto-detect)"
+    )]
+    threads: usize,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Hash files and write a lookup table for later cross-machine matching
+    Hash {
+        /// Directories to hash
+        #[arg(value_name = "DIR", required = true)]
+        dirs: Vec<PathBuf>,
+
+        /// File to write the lookup table to
+        #[arg(long, value_name = "FILE")]
+        out: PathBuf,
+    },
+    /// Find files in the second lookup table that duplicate files in the first
+    Crossmatch {
+        /// Lookup table of the reference tree (A side)
+        #[arg(value_name = "A")]
+        a: PathBuf,
+
+        /// Lookup table of the tree to check (B side)
+        #[arg(value_name = "B")]
+        b: PathBuf,
+
+        /// Also write the crossmatch report as JSON
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// Combine the reports of a scan split with --shard, then list or act on duplicates
+    Merge {
+        /// Shard reports, one per shard
+        #[arg(value_name = "FILE", required = true)]
+        parts: Vec<PathBuf>,
+    },
+    /// Find repeated fixed-size blocks within one large file or block device (report only)
+    Blocks {
+        /// Disk image, block device or other file to read
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        /// Size of each block in bytes
+        #[arg(long, value_name = "BYTES", default_value = "4096", value_parser = clap::value_parser!(u64).range(1..))]
+        block_size: u64,
+
+        /// How many of the most repeated blocks to list
+        #[arg(long, value_name = "N", default_value = "10")]
+        top: usize,
+    },
+    /// Check that the files in a saved --report are still as reported before acting on it
+    VerifyReport {
+        /// Report written with --report
+        #[arg(value_name = "FILE")]
+        report: PathBuf,
+
+        /// Also rehash every file to catch content changes that kept size and modification time
+        #[arg(long)]
+        rehash: bool,
+    },
+    /// Undo the deletes and moves recorded with --revert-log
+    Revert {
+        /// Revert log written with --revert-log
+        #[arg(value_name = "LOG_FILE")]
+        log: PathBuf,
+
+        /// Directory holding backups of deleted files under their original absolute paths
+        #[arg(long, value_name = "DIR")]
+        backup_dir: Option<PathBuf>,
+
+        /// Show what would be restored without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+// Exit statuses are a stable contract for scripts; see `EXIT_CODES`
+/// Usage errors and fatal failures (an `Err` returned from `main` also exits with 1)
+const EXIT_FATAL: i32 = 1;
+/// Duplicates were found and `--fail-on-duplicates` was given
+const EXIT_DUPLICATES_FOUND: i32 = 2;
+/// Some file operations failed (or failed post-verification)
+const EXIT_PARTIAL_FAILURE: i32 = 3;
+/// The run was interrupted with Ctrl-C
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Every exit status with its meaning, as printed by `--print-exit-codes`
+const EXIT_CODES: &[(i32, &str)] = &[
+    (0, "success (including when no duplicates were found)"),
+    (EXIT_FATAL, "usage error or fatal failure"),
+    (EXIT_DUPLICATES_FOUND, "duplicates found with --fail-on-duplicates"),
+    (EXIT_PARTIAL_FAILURE, "some file operations failed"),
+    (EXIT_INTERRUPTED, "interrupted with Ctrl-C"),
+];
+
+fn main() -> Result<()> {
+    // clap reports usage errors with status 2, which is reserved for found duplicates
+    let args = Cli::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() { EXIT_FATAL } else { 0 });
+    });
+
+    if args.print_exit_codes {
+        for (code, meaning) in EXIT_CODES {
+            println!("{:>3}  {}", code, meaning);
+        }
+        return Ok(());
+    }
+
+    init_logging(args.log_file.as_deref())?;
+
+    if args.no_color {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+
+    // Set up thread pool if specified
+    if args.threads > 0 {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(args.threads)
+            .build_global()
+            .unwrap();
+    }
+
+    let cancel = CancelToken::new();
+    install_interrupt_handler(cancel.clone());
+
+    let shard_parts = match &args.command {
+        Some(Command::Merge { parts }) => Some(parts),
+        Some(command) => return run_command(command, &args, &cancel),
+        None => None,
+    };
+
+    #[cfg(feature = "serde-support")]
+    let saved = args.load_result.as_ref();
+    #[cfg(not(feature = "serde-support"))]
+    let saved: Option<&PathBuf> = None;
+
+    if shard_parts.is_some() && saved.is_some() {
+        eprintln!("{}", style("Error: --load-result can't be combined with merge").red());
+        std::process::exit(EXIT_FATAL);
+    }
+
+    // Validate arguments
+    if args.dir.is_empty() && shard_parts.is_none() && saved.is_none() {
+        eprintln!("{}", style("Error: At least one directory must be specified").red());
+        std::process::exit(EXIT_FATAL);
+    }
+
+    if matches!(args.action, ActionType::Move) && args.move_to.is_none() {
+        eprintln!("{}", style("Error: --move-to is required when using move action").red());
+        std::process::exit(EXIT_FATAL);
+    }
+
+    if let Some(fallback) = &args.fallback {
+        if matches!(args.action, ActionType::List | ActionType::Copy)
+            || matches!(fallback, ActionType::List | ActionType::Copy)
+        {
+            eprintln!("{}", style("Error: --fallback only applies between per-file actions (not list or copy)").red());
+            std::process::exit(EXIT_FATAL);
+        }
+        if matches!(fallback, ActionType::Move) && args.move_to.is_none() {
+            eprintln!("{}", style("Error: --move-to is required when falling back to move").red());
+            std::process::exit(EXIT_FATAL);
+        }
+    }
+
+    if args.assume_identical_by_size_and_name && !matches!(args.action, ActionType::List | ActionType::Copy) {
+        if !args.i_know_what_im_doing {
+            eprintln!("{}", style(
+                "Error: --assume-identical-by-size-and-name never reads file contents; destructive actions require --i-know-what-im-doing"
+            ).red());
+            std::process::exit(EXIT_FATAL);
+        }
+        if args.post_verify {
+            eprintln!("{}", style("Error: --post-verify compares content hashes and can't be used with --assume-identical-by-size-and-name").red());
+            std::process::exit(EXIT_FATAL);
+        }
+    }
+
+    if args.prefix_only.is_some() && !matches!(args.action, ActionType::List | ActionType::Copy) {
+        if !args.verify {
+            eprintln!("{}", style("Error: --prefix-only groups are unverified; destructive actions require --verify").red());
+            std::process::exit(EXIT_FATAL);
+        }
+        if args.post_verify {
+            eprintln!("{}", style("Error: --post-verify compares full-file hashes and can't be used with --prefix-only").red());
+            std::process::exit(EXIT_FATAL);
+        }
+    }
+
+    if let Some(prefix) = &args.only_hash_prefix {
+        if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            eprintln!("{}", style(format!("Error: --only-hash-prefix must be hexadecimal, got '{}'", prefix)).red());
+            std::process::exit(EXIT_FATAL);
+        }
+    }
+
+    let listing = path_listing(&args);
+    if listing.is_some() && !matches!(args.action, ActionType::List) {
+        eprintln!("{}", style("Error: --print0, --list-keepers and --list-victims can only be used with the list action").red());
+        std::process::exit(EXIT_FATAL);
+    }
+
+    if args.interactive && matches!(args.action, ActionType::List) {
+        eprintln!("{}", style("Error: --interactive needs an action to apply (e.g. --action delete)").red());
+        std::process::exit(EXIT_FATAL);
+    }
+
+    if args.trash_dir.is_some() && !matches!(args.action, ActionType::Delete) && !matches!(args.fallback, Some(ActionType::Delete)) {
+        eprintln!("{}", style("Error: --trash-dir can only be used with the delete action or --fallback delete").red());
+        std::process::exit(EXIT_FATAL);
+    }
+
+    if args.only_duplicated_names && !matches!(args.action, ActionType::List) {
+        eprintln!("{}", style("Error: --only-duplicated-names is report-only and can only be used with the list action").red());
+        std::process::exit(EXIT_FATAL);
+    }
+
+    if matches!(args.action, ActionType::Copy) && args.copy_to.is_none() {
+        eprintln!("{}", style("Error: --copy-to is required when using copy action").red());
+        std::process::exit(EXIT_FATAL);
+    }
+
+    let target = match (&args.action, &args.fallback) {
+        (ActionType::Move, _) | (_, Some(ActionType::Move)) => args.move_to.as_ref(),
+        (ActionType::Copy, _) => args.copy_to.as_ref(),
+        _ => None,
+    };
+    if let Some(root) = target.and_then(|target| root_containing(target, &args.dir)) {
+        let message = format!(
+            "{} is inside scanned directory {}; moved or copied files would be scanned again",
+            target.unwrap().display(), root.display()
+        );
+        if !args.allow_target_in_scan {
+            eprintln!("{}", style(format!("Error: {} (use --allow-target-in-scan to proceed anyway)", message)).red());
+            std::process::exit(EXIT_FATAL);
+        }
+        eprintln!("{}", style(format!("Warning: {}", message)).yellow());
+    }
+
+    let move_template = match args.move_template.as_deref().map(NameTemplate::parse).transpose() {
+        Ok(template) => template,
+        Err(e) => {
+            eprintln!("{}", style(format!("Error: Invalid --move-template: {}", e)).red());
+            std::process::exit(EXIT_FATAL);
+        }
+    };
+
+    // Create scanner with filters
+    let scanner = build_scanner(&args, &cancel);
+
+    if args.only_duplicated_names {
+        println!("{}", style("🔍 Scanning directories for same-named files...").cyan().bold());
+        let files = scanner.hash_same_names(&args.dir)?;
+        print_name_conflicts(&find_name_conflicts(files), args.verbose);
+        if cancel.is_cancelled() {
+            exit_interrupted();
+        }
+        return Ok(());
+    }
+
+    let started = std::time::Instant::now();
+    let (roots, mut scan_result) = match (shard_parts, saved) {
+        (Some(parts), _) => {
+            if listing.is_none() {
+                println!("{}", style("🧩 Merging shard reports...").cyan().bold());
+            }
+            let reports = parts.iter().map(|part| ShardReport::load(part)).collect::<Result<Vec<_>>>()?;
+            merge_shards(reports)?
+        }
+        #[cfg(feature = "serde-support")]
+        (None, Some(path)) => {
+            if listing.is_none() {
+                println!("{}", style("📂 Loading saved scan result...").cyan().bold());
+            }
+            let result = load_result(path)?;
+            (result_roots(&result), result)
+        }
+        (None, _) => {
+            if args.assume_identical_by_size_and_name {
+                eprintln!("{}", style(
+                    "⚠️ WARNING: --assume-identical-by-size-and-name compares no content; files with the same size and name are assumed identical"
+                ).red().bold());
+            }
+            if listing.is_none() && !args.bench {
+                println!("{}", style("🔍 Scanning directories for duplicate files...").cyan().bold());
+            }
+            (args.dir.clone(), scanner.scan_directories(&args.dir)?)
+        }
+    };
+
+    // Written now so early exits still leave one; rewritten once actions have run
+    let mut profile = scanner.profile();
+    if let Some(path) = &args.profile {
+        profile.save(path)?;
+    }
+
+    if let Some(shard) = args.shard {
+        if cancel.is_cancelled() {
+            eprintln!("{}", style("⚠️ Scan interrupted - no shard report written").yellow());
+            exit_interrupted();
+        }
+        // --shard requires --shard-out
+        let out = args.shard_out.as_ref().unwrap();
+        ShardReport { shard, roots, result: scan_result }.save(out)?;
+        println!("{} Shard {} written to {}", style("✅").green(), shard, out.display());
+        return Ok(());
+    }
+
+    if args.bench {
+        print_bench(&scan_result, started.elapsed());
+        if cancel.is_cancelled() {
+            exit_interrupted();
+        }
+        return Ok(());
+    }
+
+    #[cfg(feature = "serde-support")]
+    if let Some(path) = &args.save_result {
+        if cancel.is_cancelled() {
+            eprintln!("{}", style("⚠️ Scan interrupted - no result saved").yellow());
+        } else {
+            save_result(&scan_result, path)?;
+            if listing.is_none() {
+                println!("{} Scan result saved to {}", style("✅").green(), path.display());
+            }
+        }
+    }
+
+    #[cfg(feature = "serde-support")]
+    let roots = merge_saved_results(&args.merge_result, roots, &mut scan_result, listing.is_none())?;
+    // Unique files were only kept for saving or merging
+    scan_result.filter_duplicates();
+
+    scan_result.filter_min_copies(args.min_copies);
+    check_unverified_groups(&args, &scan_result);
+
+    if scan_result.runtime_exceeded {
+        eprintln!("{}", style("⏱️ Max runtime reached - results are incomplete, files not yet scanned are missing").yellow().bold());
+    }
+
+    if let Some((select, separator)) = listing {
+        if cancel.is_cancelled() {
+            exit_interrupted();
+        }
+        print_paths(&scan_result, &keep_strategies(&args), select, separator)?;
+        exit_if_duplicates(&args, &scan_result);
+        return Ok(());
+    }
+    
+    if cancel.is_cancelled() {
+        println!();
+        println!("{}", style("⚠️ Scan interrupted - showing partial results").yellow().bold());
+        display_results(&scan_result, args.verbose, args.format)?;
+        exit_interrupted();
+    }
+
+    if let Some(report_path) = &args.report {
+        let mut report = Report::from_result(&scan_result);
+        if args.report_relative_paths {
+            report.relative_to(&roots);
+        }
+        if args.autosave_interval.is_some() {
+            report.save_atomic(report_path)?;
+        } else {
+            report.save(report_path)?;
+        }
+    }
+
+    if scan_result.duplicates.is_empty() {
+        println!("{}", style("✅ No duplicate files found!").green().bold());
+        print_skipped(&scan_result);
+        if args.show_already_linked {
+            print_already_linked(&scan_result);
+        }
+        return Ok(());
+    }
+
+    // Perform action
+    let action = dedup_action(&args.action, &args);
+
+    // A partial scan may miss a group's better keeper, or some of its members
+    let destructive = !matches!(action, DedupAction::List | DedupAction::Copy(_));
+    if scan_result.runtime_exceeded && destructive && !args.dry_run && !args.allow_incomplete {
+        display_results(&scan_result, args.verbose, args.format)?;
+        eprintln!("{}", style(format!(
+            "Error: the scan was cut short by --max-runtime; refusing to {} (pass --allow-incomplete to act anyway)",
+            action_name(&action)
+        )).red());
+        std::process::exit(EXIT_FATAL);
+    }
+
+    if saved.is_some() && destructive && !args.verify && !args.dry_run {
+        eprintln!("{}", style(
+            "Warning: files may have changed since the result was saved; --verify compares each duplicate with its keeper before acting"
+        ).yellow());
+    }
+
+    // The keeper of each reviewed group comes first, so no keep rules apply afterwards
+    #[cfg(feature = "tui")]
+    let reviewed = args.tui || args.interactive;
+    #[cfg(not(feature = "tui"))]
+    let reviewed = args.interactive;
+
+    #[cfg(feature = "tui")]
+    if args.tui {
+        match file_deduplication::tui::review(&scan_result, &keep_strategies(&args), &action)? {
+            Some(plan) => scan_result = plan,
+            None => {
+                println!("{}", style("Review closed without changes").yellow());
+                return Ok(());
+            }
+        }
+    }
+
+    if args.interactive {
+        match run_wizard(&scan_result, &keep_strategies(&args), &action)? {
+            Some(plan) => scan_result = plan,
+            None => {
+                println!("{}", style("Aborted without changes").yellow());
+                return Ok(());
+            }
+        }
+    }
+
+    // Display results
+    if !reviewed {
+        display_results(&scan_result, args.verbose, args.format)?;
+    }
+    if args.show_already_linked {
+        print_already_linked(&scan_result);
+    }
+
+    if args.recommend {
+        print_recommendations(&recommend_removals(&scan_result));
+    }
+
+    if args.by_directory || args.by_directory_out.is_some() {
+        let tree = waste_by_directory(&scan_result);
+        if args.by_directory {
+            print_directory_waste(&tree, (!args.verbose).then_some(20));
+        }
+        if let Some(path) = &args.by_directory_out {
+            save_json(path, &tree, "directory report")?;
+        }
+    }
+
+    #[cfg(feature = "chunking")]
+    if args.chunk_report {
+        display_chunk_report(&scanner, &args)?;
+    }
+
+    if !matches!(action, DedupAction::List)
+        && scan_result.get_duplicate_count() == 0
+        && scan_result.get_empty_file_count() == 0
+    {
+        println!("{}", style("✅ Every duplicate is already hard linked - 0 operations needed").green().bold());
+        print_skipped(&scan_result);
+        return Ok(());
+    }
+
+    if !matches!(action, DedupAction::List) {
+        if args.dry_run {
+            println!("{}", style("🧪 Dry run mode - no changes will be made").yellow().bold());
+        } else if !args.yes && !reviewed {
+            let proceed = dialoguer::Confirm::new()
+                .with_prompt("Do you want to proceed with the selected action?")
+                .interact()?;
+            
+            if !proceed {
+                println!("{}", style("Operation cancelled").yellow());
+                return Ok(());
+            }
+        }
+
+        let config = ActionConfig {
+            dereference_keeper: args.dereference_keeper,
+            keep: if reviewed { Vec::new() } else { keep_strategies(&args) },
+            order: match args.order_by {
+                OrderBy::None => GroupOrder::Unordered,
+                OrderBy::SizeDesc => GroupOrder::SizeDesc,
+            },
+            stop_after: args.stop_after,
+            touch_keeper_newest: args.touch_keeper_newest,
+            hash_prefix: args.only_hash_prefix.as_ref().map(|prefix| prefix.to_ascii_lowercase()),
+            skip_in_use: args.skip_in_use,
+            verify: args.verify,
+            post_verify: args.post_verify,
+            fallback: args.fallback.as_ref().map(|fallback| dedup_action(fallback, &args)),
+            safe: args.safe,
+            json_errors: args.json_errors.clone(),
+            min_block_size: (!args.include_sub_block).then(|| {
+                args.min_block_size.unwrap_or_else(|| {
+                    roots.iter().map(|dir| filesystem_block_size(dir)).max().unwrap_or(DEFAULT_BLOCK_SIZE)
+                })
+            }),
+            move_template,
+            retries: args.retries,
+            preserve_metadata: args.preserve_metadata,
+            move_manifest: match &args.move_manifest {
+                Some(path) if !args.dry_run => Some(MoveManifest::open(path)?),
+                _ => None,
+            },
+            // Entries are written as they happen, so the log survives a run that stops partway
+            revert_log: match &args.revert_log {
+                Some(path) if !args.dry_run => Some(RevertLog::create(path)?),
+                _ => None,
+            },
+            revert_backup_dir: None,
+            trash_dir: args.trash_dir.clone(),
+            delete_empty_after_move: args.delete_empty_after_move,
+            ignore_free_space: args.ignore_free_space,
+            scan_roots: roots.clone(),
+            cancel: cancel.clone(),
+        };
+
+        if args.dry_run && !matches!(action, DedupAction::Move(_) | DedupAction::Copy(_)) {
+            print_savings_estimate(&estimate_savings(&scan_result, &config.keep, config.min_block_size));
+        }
+
+        let started = std::time::Instant::now();
+        let result = perform_deduplication(&scan_result, action, &config, args.dry_run);
+        if let (Some(path), Some(_)) = (&args.revert_log, &config.revert_log) {
+            println!("↩️ Revert log written to {} (undo with `dedup revert {}`)", path.display(), path.display());
+        }
+        let result = result?;
+        if let Some(path) = &args.profile {
+            profile.record("actions", started.elapsed());
+            profile.save(path)?;
+        }
+
+        if cancel.is_cancelled() {
+            exit_interrupted();
+        }
+        if result.error_count() > 0 || !result.verify_failures.is_empty() {
+            std::process::exit(EXIT_PARTIAL_FAILURE);
+        }
+    }
+
+    exit_if_duplicates(&args, &scan_result);
+    Ok(())
+}
+
+/// Scan roots recorded on the files of a saved result
+#[cfg(feature = "serde-support")]
+fn result_roots(result: &DedupResult) -> Vec<PathBuf> {
+    let roots: std::collections::BTreeSet<PathBuf> = result.duplicates
+        .values()
+        .flatten()
+        .filter_map(|file| file.source_root.clone())
+        .collect();
+    roots.into_iter().collect()
+}
+
+/// Merge saved results into this run's, returning the roots of all of them
+#[cfg(feature = "serde-support")]
+fn merge_saved_results(paths: &[PathBuf], mut roots: Vec<PathBuf>, result: &mut DedupResult, announce: bool) -> Result<Vec<PathBuf>> {
+    if paths.is_empty() {
+        return Ok(roots);
+    }
+    let mut others = Vec::new();
+    for path in paths {
+        let other = load_result(path)?;
+        if !other.keeps_unique {
+            eprintln!("{}", style(format!(
+                "Warning: {} was saved without --keep-unique; only its files with a duplicate in their own tree can match this one",
+                path.display()
+            )).yellow());
+        }
+        for root in result_roots(&other) {
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+        others.push(other);
+    }
+    result.merge_all(others)?;
+    if announce {
+        println!("{}", style(format!("🔗 Merged {} saved result(s)", paths.len())).cyan().bold());
+    }
+    Ok(roots)
+}
+
+/// Refuse destructive actions on groups that weren't formed from full contents
+///
+/// Checked on the result itself, so it holds for scanned, loaded and merged
+/// results alike, whatever grouping flags this run was given.
+fn check_unverified_groups(args: &Cli, result: &DedupResult) {
+    if matches!(args.action, ActionType::List | ActionType::Copy) {
+        return;
+    }
+    if let Some(prefix) = result.prefix_only {
+        if !args.verify {
+            eprintln!("{}", style(format!(
+                "Error: the groups were formed from the first {} bytes only (--prefix-only); destructive actions require --verify",
+                prefix
+            )).red());
+            std::process::exit(EXIT_FATAL);
+        }
+        if args.post_verify {
+            eprintln!("{}", style("Error: --post-verify compares full-file hashes and can't be used on --prefix-only groups").red());
+            std::process::exit(EXIT_FATAL);
+        }
+    }
+    if result.size_and_name_only {
+        if !args.i_know_what_im_doing {
+            eprintln!("{}", style(
+                "Error: the groups were formed from size and name only (--assume-identical-by-size-and-name); destructive actions require --i-know-what-im-doing"
+            ).red());
+            std::process::exit(EXIT_FATAL);
+        }
+        if args.post_verify {
+            eprintln!("{}", style("Error: --post-verify compares content hashes and can't be used on size-and-name groups").red());
+            std::process::exit(EXIT_FATAL);
+        }
+    }
+}
+
+/// Exit with `EXIT_DUPLICATES_FOUND` if asked to fail when duplicates exist
+fn exit_if_duplicates(args: &Cli, result: &DedupResult) {
+    if args.fail_on_duplicates && !result.duplicates.is_empty() {
+        std::process::exit(EXIT_DUPLICATES_FOUND);
+    }
+}
+
+/// Turn the first Ctrl-C into a graceful stop; a second one exits immediately
+fn install_interrupt_handler(cancel: CancelToken) {
+    let result = ctrlc::set_handler(move || {
+        if cancel.is_cancelled() {
+            let _ = console::Term::stdout().show_cursor();
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        cancel.cancel();
+    });
+
+    if let Err(e) = result {
+        eprintln!("{}", style(format!("Warning: Failed to install Ctrl-C handler: {}", e)).yellow());
+    }
+}
+
+fn exit_interrupted() -> ! {
+    let _ = console::Term::stdout().show_cursor();
+    std::process::exit(EXIT_INTERRUPTED);
+}
+
+fn build_scanner(args: &Cli, cancel: &CancelToken) -> Scanner {
+    let mut scanner = Scanner::new();
+    scanner.set_min_size(args.min_size);
+    if let Some(max_size) = args.max_size {
+        scanner.set_max_size(max_size);
+    }
+    if let Some(depth) = args.max_depth {
+        scanner.set_max_depth(depth);
+    }
+    if let Some(depth) = args.min_depth {
+        scanner.set_min_depth(depth);
+    }
+    scanner.set_include_extensions(args.include_ext.clone());
+    scanner.set_exclude_extensions(args.exclude_ext.clone());
+    scanner.set_hash_only_extensions(args.hash_only_ext.clone());
+    if !args.category.is_empty() {
+        let categories = args.category_defs.iter().try_fold(CategoryMap::default(), |mut categories, definition| {
+            categories.define(definition)?;
+            anyhow::Ok(categories)
+        });
+        match categories.and_then(|categories| categories.filter(&args.category)) {
+            Ok(filter) => scanner.set_categories(filter),
+            Err(e) => {
+                eprintln!("{}", style(format!("Error: {:#}", e)).red());
+                std::process::exit(EXIT_FATAL);
+            }
+        }
+    }
+    if let Some(command) = &args.filter_cmd {
+        scanner.set_filter_command(FilterCommand {
+            jobs: args.filter_cmd_jobs as usize,
+            timeout: args.filter_cmd_timeout,
+            ..FilterCommand::new(command)
+        });
+    }
+    scanner.set_ignore_apple_metadata(args.ignore_apple_metadata);
+    scanner.set_exclude_hidden(args.exclude_hidden);
+    scanner.set_exclude_system_files(args.exclude_system_files);
+    scanner.set_report_broken_links(args.report_broken_links);
+    if !args.exclude_from.is_empty() {
+        let rules = args.exclude_from.iter().try_fold(ExcludeRules::default(), |mut rules, path| {
+            rules.extend(ExcludeRules::load(path)?);
+            anyhow::Ok(rules)
+        });
+        if let Err(e) = rules.and_then(|rules| scanner.set_exclude_rules(&rules)) {
+            eprintln!("{}", style(format!("Error: {:#}", e)).red());
+            std::process::exit(EXIT_FATAL);
+        }
+    }
+    let patterns = scanner.set_include_patterns(&args.include_pattern)
+        .and_then(|_| scanner.set_exclude_patterns(&args.exclude_pattern));
+    if let Err(e) = patterns {
+        eprintln!("{}", style(format!("Error: {:#}", e)).red());
+        std::process::exit(EXIT_FATAL);
+    }
+    if let Some(regex) = &args.include_regex {
+        scanner.set_include_regex(regex.clone());
+    }
+    if let Some(regex) = &args.exclude_regex {
+        scanner.set_exclude_regex(regex.clone());
+    }
+    scanner.set_verbose(args.verbose);
+    scanner.set_quiet(args.quiet || path_listing(args).is_some() || args.bench);
+    scanner.set_detect_types(args.detect_type);
+    scanner.set_canonicalize_paths(args.canonicalize_paths);
+    scanner.set_follow_symlinks(args.follow_symlinks);
+    scanner.set_require_same_name(args.require_same_name);
+    scanner.set_per_root(args.per_dir);
+    scanner.set_normalize_text(args.normalize_text);
+    scanner.set_nul_padding(args.match_nul_padding);
+    scanner.set_hash_algorithm(match args.hash_algo {
+        HashAlgo::Blake3 => HashAlgorithm::Blake3,
+        HashAlgo::Sha256 => HashAlgorithm::Sha256,
+        HashAlgo::Sha1 => HashAlgorithm::Sha1,
+        HashAlgo::Md5 => HashAlgorithm::Md5,
+        HashAlgo::XxHash3 => HashAlgorithm::XxHash3,
+    });
+    scanner.set_hash_len(args.hash_bytes);
+    #[cfg(feature = "serde-support")]
+    scanner.set_keep_unique(args.keep_unique || !args.merge_result.is_empty());
+    scanner.set_size_and_name_only(args.assume_identical_by_size_and_name);
+    if let Some(bytes) = args.prefix_only {
+        scanner.set_prefix_only(bytes);
+    }
+    #[cfg(feature = "decompress")]
+    scanner.set_decompress(args.decompress_aware);
+    if let Some(shard) = args.shard {
+        scanner.set_shard(shard);
+    }
+    if let Some(budget) = args.max_runtime {
+        scanner.set_max_runtime(budget);
+    }
+    if let Some(secs) = args.hash_timeout {
+        scanner.set_hash_timeout(std::time::Duration::from_secs(secs));
+    }
+    if let Some(cache) = &args.cache {
+        scanner.set_cache_path(cache.clone());
+    }
+    if let (Some(secs), Some(report)) = (args.autosave_interval, &args.report) {
+        scanner.set_autosave(report.clone(), std::time::Duration::from_secs(secs));
+    }
+    scanner.set_cancel_token(cancel.clone());
+
+    if let Err(e) = scanner.config().validate() {
+        eprintln!("{}", style(format!("Error: Invalid filter settings: {}", e)).red());
+        std::process::exit(EXIT_FATAL);
+    }
+    if args.hash_bytes < MIN_SAFE_HASH_LEN {
+        eprintln!("{}", style(format!(
+            "Warning: {}-byte hashes can collide on large trees, grouping different files together",
+            args.hash_bytes
+        )).yellow());
+    }
+
+    scanner
+}
+
+fn run_command(command: &Command, args: &Cli, cancel: &CancelToken) -> Result<()> {
+    match command {
+        Command::Hash { dirs, out } => {
+            let scanner = build_scanner(args, cancel);
+
+            println!("{}", style("🔍 Hashing files...").cyan().bold());
+            let files = scanner.hash_directories(dirs)?;
+            if cancel.is_cancelled() {
+                eprintln!("{}", style("⚠️ Hashing interrupted - no lookup table written").yellow());
+                exit_interrupted();
+            }
+            let table = HashTable::new(dirs.clone(), files);
+            table.save(out)?;
+
+            println!("{} {} files written to {}",
+                style("✅").green(),
+                table.files.len(),
+                out.display()
+            );
+        }
+        Command::Crossmatch { a, b, out } => {
+            let table_a = HashTable::load(a)?;
+            let table_b = HashTable::load(b)?;
+            let report = crossmatch::crossmatch(&table_a, &table_b);
+
+            display_crossmatch(&report, args.verbose);
+
+            if let Some(out) = out {
+                report.save(out)?;
+            }
+        }
+        Command::Blocks { file, block_size, top } => {
+            println!("{}", style(format!("🔍 Hashing {} in {} byte blocks...", file.display(), block_size)).cyan().bold());
+            let report = analyze_blocks(file, *block_size as usize, *top, cancel)?;
+            if cancel.is_cancelled() {
+                println!("{}", style("⚠️ Interrupted - showing the blocks read so far").yellow().bold());
+            }
+            display_block_report(&report);
+            if cancel.is_cancelled() {
+                exit_interrupted();
+            }
+        }
+        Command::VerifyReport { report, rehash } => {
+            let saved = Report::load(report)?;
+            println!("{}", style(format!("🔍 Checking {} groups from {}...", saved.groups.len(), report.display())).cyan().bold());
+            let drift = verify_report(&saved, *rehash, cancel);
+            display_report_drift(&drift, saved.groups.len());
+            if cancel.is_cancelled() {
+                exit_interrupted();
+            }
+        }
+        Command::Revert { log, backup_dir, dry_run } => {
+            let config = ActionConfig { revert_backup_dir: backup_dir.clone(), ..ActionConfig::default() };
+            let result = perform_deduplication(&DedupResult::new(), DedupAction::Revert(log.clone()), &config, *dry_run)?;
+            if result.error_count() > 0 {
+                std::process::exit(EXIT_PARTIAL_FAILURE);
+            }
+        }
+        Command::Merge { .. } => unreachable!("merge runs through the main scan flow"),
+    }
+
+    Ok(())
+}
+
+/// The action to run for an `--action` or `--fallback` choice
+///
+/// Move and copy targets are checked to be present before this is called.
+fn dedup_action(kind: &ActionType, args: &Cli) -> DedupAction {
+    match kind {
+        ActionType::List => DedupAction::List,
+        ActionType::Delete => DedupAction::Delete,
+        ActionType::Move => DedupAction::Move(args.move_to.clone().unwrap()),
+        ActionType::Hardlink => DedupAction::Hardlink,
+        ActionType::Reflink => DedupAction::Reflink,
+        ActionType::DedupInPlace => DedupAction::DedupInPlace,
+        ActionType::Symlink => DedupAction::Symlink,
+        ActionType::Copy => DedupAction::Copy(args.copy_to.clone().unwrap()),
+    }
+}
+
+/// Send `log` events to stderr, or to a log file at info level when one is given
+fn init_logging(log_file: Option<&std::path::Path>) -> Result<()> {
+    let default_level = if log_file.is_some() { "info" } else { "off" };
+    let mut builder = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_level));
+
+    if let Some(path) = log_file {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open log file {}", path.display()))?;
+        builder.target(env_logger::Target::Pipe(Box::new(file)));
+    }
+
+    builder.init();
+    Ok(())
+}
+
+/// Keeper rules from the command line, in the order they are applied
+fn keep_strategies(args: &Cli) -> Vec<KeepStrategy> {
+    let mut keep = Vec::new();
+    if !args.priority_dirs.is_empty() {
+        keep.push(KeepStrategy::DirectoryPriority(args.priority_dirs.clone()));
+    }
+    if !args.prefer_ext.is_empty() {
+        keep.push(KeepStrategy::PreferExtension(args.prefer_ext.clone()));
+    }
+    keep.extend(args.keep.iter().cloned());
+    keep
+}
+
+/// One line per duplicate group, `<waste> <count> <keeper-name> [<hash8>]`, most
+/// wasteful first so the order is stable from run to run
+fn compact_lines(result: &DedupResult) -> Vec<String> {
+    let mut groups: Vec<(u64, usize, &String, &Vec<FileInfo>)> = result.duplicates
+        .iter()
+        .map(|(key, files)| (result.group_wasted_space(files), result.group_duplicate_count(files), key, files))
+        .filter(|(_, count, _, _)| *count > 0)
+        .collect();
+    groups.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.2.cmp(b.2)));
+
+    groups
+        .into_iter()
+        .map(|(waste, count, _, files)| format!("{} {} {} [{}]",
+            waste,
+            count,
+            files[0].path.file_name().unwrap_or_default().to_string_lossy(),
+            short_hash(&files[0].hash, 8)
+        ))
+        .collect()
+}
+
+/// Timing and throughput of a `--bench` run
+fn print_bench(result: &DedupResult, elapsed: std::time::Duration) {
+    use humansize::{format_size, DECIMAL};
+
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    println!("{}", style("⏱️ Benchmark").cyan().bold());
+    println!("{}", style("-".repeat(20)).cyan());
+    println!("Files scanned: {}", result.total_files);
+    println!("Bytes scanned: {}", format_size(result.total_size, DECIMAL));
+    println!("Duplicate groups: {}", result.duplicates.len());
+    println!("Total time: {} ({:.3}s)", format_duration(elapsed), elapsed.as_secs_f64());
+    println!("Throughput: {:.0} files/s, {}/s",
+        result.total_files as f64 / secs,
+        format_size((result.total_size as f64 / secs) as u64, DECIMAL)
+    );
+}
+
+/// Which paths to print instead of the usual output, and the byte ending each one
+fn path_listing(args: &Cli) -> Option<(PrintSelect, u8)> {
+    let separator = if args.print0 { b'\0' } else { b'\n' };
+    if args.list_keepers {
+        Some((PrintSelect::Keepers, separator))
+    } else if args.list_victims {
+        Some((PrintSelect::Victims, separator))
+    } else {
+        args.print0.then_some((args.print_select, separator))
+    }
+}
+
+/// Write the selected paths of every duplicate group to stdout, each followed by `separator`
+fn print_paths(result: &DedupResult, keep: &[KeepStrategy], select: PrintSelect, separator: u8) -> Result<()> {
+    use std::io::Write;
+
+    let mut groups: Vec<_> = result.duplicates.iter().collect();
+    groups.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    for (_, files) in groups {
+        let keeper = select_keeper(files, keep);
+        for (i, file) in files.iter().enumerate() {
+            let selected = match select {
+                PrintSelect::Keepers => i == keeper,
+                PrintSelect::Victims => i != keeper,
+                PrintSelect::Both => true,
+            };
+            if selected {
+                out.write_all(file.path.as_os_str().as_encoded_bytes())?;
+                out.write_all(&[separator])?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// One summary line per reason the filters left files out
+fn print_skipped(result: &DedupResult) {
+    use humansize::{format_size, DECIMAL};
+
+    for (reason, count) in &result.skipped {
+        println!("Files skipped ({}): {}", reason, count);
+    }
+    if !result.broken_links.is_empty() {
+        println!("Broken symlinks: {}", result.broken_links.len());
+    }
+    if !result.already_linked.is_empty() {
+        println!("Files already hard linked, read under one name only: {}", result.already_linked.len());
+    }
+    let size_only = &result.size_only;
+    if size_only.files > 0 {
+        println!(
+            "Counted by size only, not hashed (--hash-only-ext): {} files ({}), {} sharing a size with another file",
+            size_only.files, format_size(size_only.bytes, DECIMAL), size_only.same_size
+        );
+    }
+}
+
+/// Every name of each file reached through several hard links
+fn print_already_linked(result: &DedupResult) {
+    println!();
+    println!("{}", style("🔗 Already Hard Linked").cyan().bold());
+    println!("{}", style("-".repeat(20)).cyan());
+    if result.already_linked.is_empty() {
+        println!("No file was found under more than one name");
+    }
+    for names in &result.already_linked {
+        println!("{} names:", names.len());
+        for name in names {
+            println!("  {}", name.display());
+        }
+    }
+}
+
+fn display_crossmatch(report: &CrossmatchReport, verbose: bool) {
+    use humansize::{format_size, DECIMAL};
+
+    println!();
+    println!("{}", style("📊 Cross-machine Duplicates").cyan().bold());
+    println!("{}", style("=".repeat(40)).cyan());
+
+    for m in &report.matches {
+        println!("🔗 {} ({})", m.file.path.display(), format_size(m.file.size, DECIMAL));
+        if verbose {
+            for twin in &m.twins {
+                println!("  📄 {}", twin.path.display());
+            }
+        } else {
+            println!("  📄 {}", m.twins[0].path.display());
+        }
+    }
+
+    println!();
+    println!("{}", style("📈 Summary").green().bold());
+    println!("{}", style("-".repeat(20)).green());
+    println!("Files checked: {}", report.total_files);
+    println!("Already present on the other side: {}", report.matches.len());
+    println!("Potential space savings: {}", format_size(report.duplicate_size, DECIMAL));
+}
+
+fn display_report_drift(drift: &ReportDrift, total_groups: usize) {
+    println!();
+    println!("{}", style("🔎 Report Drift").cyan().bold());
+    println!("{}", style("=".repeat(40)).cyan());
+    if drift.is_accurate() {
+        println!("Every listed file is still as reported");
+    }
+    for group in &drift.drifted {
+        println!("{} ({} of {} files still match)",
+            style(format!("Group {}", short_hash(&group.hash, 16))).bold(),
+            group.intact,
+            group.intact + group.files.len()
+        );
+        for (path, change) in &group.files {
+            match change {
+                FileDrift::Missing => println!("  ❌ Disappeared: {}", path.display()),
+                FileDrift::Changed => println!("  ✏️  Changed: {}", path.display()),
+                FileDrift::Diverged => println!("  🔀 Content diverged: {}", path.display()),
+            }
+        }
+    }
+
+    println!();
+    println!("{}", style("📈 Summary").green().bold());
+    println!("{}", style("-".repeat(20)).green());
+    println!("Groups checked: {} of {}", drift.groups_checked, total_groups);
+    println!("Files checked: {}", drift.files_checked);
+    println!("Groups drifted: {}", drift.drifted.len());
+    if drift.not_rehashed > 0 {
+        println!("Groups not rehashed (normalized, decompressed, prefix-only or name-only hashes): {}", drift.not_rehashed);
+    }
+}
+
+fn display_block_report(report: &BlockReport) {
+    use humansize::{format_size, DECIMAL};
+
+    let block_size = report.block_size as u64;
+    println!();
+    println!("{}", style("🧱 Most Repeated Blocks").cyan().bold());
+    println!("{}", style("=".repeat(40)).cyan());
+    if report.top.is_empty() {
+        println!("Every block is unique");
+    }
+    for block in &report.top {
+        println!("{} × {} (first at offset {})",
+            block.count,
+            short_hash(&block.hash, 16),
+            block.first_offset
+        );
+    }
+
+    println!();
+    println!("{}", style("📈 Summary").green().bold());
+    println!("{}", style("-".repeat(20)).green());
+    println!("Total blocks: {} ({})", report.total_blocks, format_size(report.total_blocks * block_size, DECIMAL));
+    println!("Unique blocks: {} ({})", report.unique_blocks, format_size(report.unique_blocks * block_size, DECIMAL));
+    println!("Dedup ratio: {:.2}:1", report.dedup_ratio());
+}
+
+#[cfg(feature = "chunking")]
+fn display_chunk_report(scanner: &Scanner, args: &Cli) -> Result<()> {
+    use file_deduplication::chunking::{find_shared_chunks, ChunkConfig};
+    use humansize::{format_size, DECIMAL};
+
+    let config = ChunkConfig {
+        min_overlap: args.chunk_min_overlap,
+        size_tolerance: args.dedup_within_size_tolerance,
+        ..ChunkConfig::default()
+    };
+
+    println!();
+    println!("{}", style("🧩 Chunking files for shared regions...").cyan().bold());
+    let files: Vec<PathBuf> = scanner.collect_files(&args.dir)?.into_iter().map(|(path, _)| path).collect();
+    let overlaps = find_shared_chunks(&files, &config)?;
+
+    println!();
+    println!("{}", style("🧩 Files Sharing Content").cyan().bold());
+    println!("{}", style("=".repeat(40)).cyan());
+
+    if overlaps.is_empty() {
+        println!("No partially overlapping files found");
+    }
+
+    for overlap in &overlaps {
+        println!("{:.1}% shared ({})", overlap.overlap_percent, format_size(overlap.shared_bytes, DECIMAL));
+        println!("  {}", overlap.a.display());
+        println!("  {}", overlap.b.display());
+    }
+
+    Ok(())
+}
+
+fn display_results(result: &DedupResult, verbose: bool, format: OutputFormat) -> Result<()> {
+    use humansize::{format_size, DECIMAL};
+    
+    println!();
+    println!("{}", style("📊 Duplicate Files Found").cyan().bold());
+    println!("{}", style("=".repeat(40)).cyan());
+    if let Some(prefix) = result.prefix_only {
+        println!("{}", style(format!(
+            "⚠️ Unverified: files were grouped on their size and first {} only",
+            format_size(prefix, DECIMAL)
+        )).yellow().bold());
+    }
+    if result.size_and_name_only {
+        println!("{}", style(
+            "⚠️ Unverified: files were grouped on size and name only - no content was compared"
+        ).red().bold());
+    }
+    
+    let mut total_duplicates = 0;
+    let mut total_waste = 0u64;
+
+    if format == OutputFormat::Compact {
+        for line in compact_lines(result) {
+            println!("{}", line);
+        }
+        total_duplicates = result.get_duplicate_count();
+        total_waste = result.get_wasted_space();
+    } else {
+        for files in result.duplicates.values() {
+            let duplicate_count = result.group_duplicate_count(files);
+            if duplicate_count > 0 {
+                total_duplicates += duplicate_count;
+                let file_size = files[0].size;
+                let waste = result.group_wasted_space(files);
+                total_waste += waste;
+            
+                let normalized = if is_decompressed_group(files) {
+                    format!(" {}", style("[decompressed]").yellow())
+                } else if is_normalized_group(files) {
+                    format!(" {}", style("[normalized text]").yellow())
+                } else if is_nul_padded_group(files) {
+                    format!(" {}", style("[NUL padded]").yellow())
+                } else {
+                    String::new()
+                };
+
+                if verbose {
+                    println!();
+                    println!("{} {} ({}){}", 
+                        style("Hash:").bold(), 
+                        short_hash(&files[0].hash, 16),
+                        format_size(file_size, DECIMAL),
+                        normalized
+                    );
+                    for (i, file) in files.iter().enumerate() {
+                        let marker = if i == 0 { "📄" } else { "🔗" };
+                        let linked = if result.hardlinked.contains(&file.path) {
+                            " (hard link)".to_string()
+                        } else if file.size > files[0].size && is_nul_padded_group(files) {
+                            format!(" (+{} NUL bytes)", file.size - files[0].size)
+                        } else {
+                            String::new()
+                        };
+                        match &file.detected_type {
+                            Some(kind) => println!("  {} {} [{}]{}", marker, file.path.display(), kind, linked),
+                            None => println!("  {} {}{}", marker, file.path.display(), linked),
+                        }
+                    }
+                } else {
+                    println!("{} duplicate files for {} ({}){}", 
+                        duplicate_count, 
+                        files[0].path.file_name().unwrap_or_default().to_string_lossy(),
+                        format_size(waste, DECIMAL),
+                        normalized
+                    );
+                }
+            }
+        }
+    }
+    
+    let empty_files = result.get_empty_file_count();
+    if empty_files > 0 {
+        println!();
+        println!("{}", style("📭 Empty Files").yellow().bold());
+        println!("{}", style("-".repeat(20)).yellow());
+        for files in result.empty_groups() {
+            if verbose {
+                for file in files {
+                    println!("  {}", file.path.display());
+                }
+            } else {
+                println!("{} empty files (e.g. {})",
+                    files.len(),
+                    files[0].path.display()
+                );
+            }
+        }
+    }
+
+    println!();
+    println!("{}", style("📈 Summary").green().bold());
+    println!("{}", style("-".repeat(20)).green());
+    println!("Total files scanned: {}", result.total_files);
+    println!("Duplicate files found: {}", total_duplicates);
+    println!("Potential space savings: {}", format_size(total_waste, DECIMAL));
+    println!("Deduplication ratio: {:.2}:1 ({:.1}% of scanned data is redundant)",
+        result.dedup_ratio(),
+        result.reclaimable_percentage()
+    );
+    if empty_files > 0 {
+        println!("{} empty files found (not counted as savings)", empty_files);
+    }
+    if !result.timed_out.is_empty() {
+        println!("Files skipped (hash timeout): {}", result.timed_out.len());
+    }
+    if !result.size_changed.is_empty() {
+        println!("Files that changed size while being hashed: {} (still being written?)", result.size_changed.len());
+    }
+    print_skipped(result);
     
     Ok(())
 } 
\ No newline at end of file
@@ -0,0 +1,51 @@
+use std::path::Path;
+use std::time::Duration;
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+
+use crate::envelope::save_json;
+
+/// Time spent in one phase of a run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhaseTiming {
+    pub phase: String,
+    pub seconds: f64,
+}
+
+/// How many files are left after each stage of the scan
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageCounts {
+    /// Files matching the filters
+    pub files_collected: usize,
+    /// Files sharing a size with another file, i.e. worth hashing
+    pub files_after_size_filter: usize,
+    /// Same-size files whose leading bytes also match another file's, i.e. worth hashing in full
+    #[serde(default)]
+    pub files_after_partial_hash: usize,
+    pub files_hashed: usize,
+    pub bytes_hashed: u64,
+}
+
+/// Per-phase timing breakdown of a run, written by `--profile`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub phases: Vec<PhaseTiming>,
+    pub counts: StageCounts,
+}
+
+impl Profile {
+    /// Add the time spent in a phase; repeated phases (e.g. one per shard) accumulate
+    pub fn record(&mut self, phase: &str, elapsed: Duration) {
+        match self.phases.iter_mut().find(|timing| timing.phase == phase) {
+            Some(timing) => timing.seconds += elapsed.as_secs_f64(),
+            None => self.phases.push(PhaseTiming {
+                phase: phase.to_string(),
+                seconds: elapsed.as_secs_f64(),
+            }),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_json(path, self, "profile")
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+
+use crate::{is_empty_group, is_nul_padded_group, DedupResult, HashAlgorithm, SizeOnlyStats};
+use crate::envelope::{load_json, save_json, save_json_atomic};
+use crate::utils::{get_relative_path, root_containing};
+use crate::dedup::{analyze_duplicates, is_decompressed_group, is_normalized_group, DedupAnalysis};
+
+/// Machine-readable report of a scan, split into named sections
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Report {
+    pub summary: ReportSummary,
+    pub extensions: BTreeMap<String, ExtensionStats>,
+    pub analysis: DedupAnalysis,
+    pub groups: Vec<ReportGroup>,
+    /// Groups of zero-length files, kept out of `groups` and the totals
+    pub empty: Vec<Vec<PathBuf>>,
+    /// Dangling symlinks, only present with `--report-broken-links`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub broken_links: Vec<PathBuf>,
+    /// Scan roots that group paths are relative to, only present with `--report-relative-paths`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<PathBuf>,
+}
+
+/// Aggregate totals for the whole scan
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportSummary {
+    pub total_files: usize,
+    pub total_size: u64,
+    pub duplicate_files: usize,
+    pub wasted_space: u64,
+    pub empty_files: usize,
+    /// Bytes scanned per unique byte
+    #[serde(default)]
+    pub dedup_ratio: f64,
+    /// Percentage of the scanned bytes taken by removable duplicates
+    #[serde(default)]
+    pub reclaimable_percent: f64,
+    /// Written by `--autosave-interval` while hashing is still running; groups may be incomplete
+    #[serde(default)]
+    pub in_progress: bool,
+    /// The scan stopped at `--max-runtime`, so the groups may be incomplete
+    #[serde(default)]
+    pub runtime_exceeded: bool,
+    /// Set when groups only compare size and file name, without reading content
+    #[serde(default)]
+    pub size_and_name_only: bool,
+    /// Set when groups only compare this many leading bytes and are unverified
+    #[serde(default)]
+    pub prefix_only: Option<u64>,
+    /// Files counted by size only, outside the hash-only extensions; never part of a group
+    #[serde(default)]
+    pub size_only: SizeOnlyStats,
+    /// Function the group hashes were computed with
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+}
+
+/// Duplicate counts for a single file extension
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ExtensionStats {
+    pub files: usize,
+    pub duplicate_files: usize,
+    pub wasted_space: u64,
+}
+
+/// A single group of identical files
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportGroup {
+    pub hash: String,
+    pub size: u64,
+    pub detected_type: Option<String>,
+    /// Members only match after text normalization
+    pub normalized: bool,
+    /// Members only match once compressed files are decompressed
+    pub decompressed: bool,
+    /// Members only match once trailing NUL bytes are ignored; `hash` and `size`
+    /// are those of the first, shortest file
+    #[serde(default)]
+    pub nul_padded: bool,
+    pub files: Vec<PathBuf>,
+    /// With relative paths, the index in `roots` each file is relative to
+    /// (`null` for a file outside every root, whose path stays absolute)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub file_roots: Vec<Option<usize>>,
+    /// Modification time of each file in seconds since the Unix epoch, for `verify-report`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modified: Vec<u64>,
+}
+
+/// One file of a duplicate group, flattened for bulk insertion into a database or dataframe
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DuplicateRow {
+    /// Position of the group in hash order, starting at 1
+    pub group_id: usize,
+    pub hash: String,
+    pub path: PathBuf,
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch
+    pub modified: u64,
+    /// The file kept when no keep strategy is given, i.e. the first of its group
+    pub is_keeper: bool,
+    pub source_root: Option<PathBuf>,
+}
+
+/// Flatten duplicate groups into one row per file
+///
+/// Groups of zero-length files are left out, as in the report. Group ids follow
+/// hash order so the same groups get the same ids from run to run.
+pub fn flatten(result: &DedupResult) -> Vec<DuplicateRow> {
+    let mut groups: Vec<(&String, &Vec<_>)> = result.duplicates
+        .iter()
+        .filter(|(_, files)| files.len() > 1 && !is_empty_group(files))
+        .collect();
+    groups.sort_by_key(|(key, _)| *key);
+
+    groups.into_iter()
+        .enumerate()
+        .flat_map(|(index, (_, files))| {
+            files.iter().enumerate().map(move |(i, file)| DuplicateRow {
+                group_id: index + 1,
+                hash: file.hash.clone(),
+                path: file.path.clone(),
+                size: file.size,
+                modified: unix_seconds(file.modified),
+                is_keeper: i == 0,
+                source_root: file.source_root.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Seconds since the Unix epoch, or 0 for earlier times
+pub fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl Report {
+    /// Build a report from scan results
+    pub fn from_result(result: &DedupResult) -> Self {
+        let mut extensions: BTreeMap<String, ExtensionStats> = BTreeMap::new();
+        let mut groups = Vec::new();
+        let mut empty = Vec::new();
+
+        for files in result.duplicates.values() {
+            if files.len() < 2 {
+                continue;
+            }
+            if is_empty_group(files) {
+                let mut paths: Vec<PathBuf> = files.iter().map(|f| f.path.clone()).collect();
+                paths.sort();
+                empty.push(paths);
+                continue;
+            }
+
+            for (i, file) in files.iter().enumerate() {
+                let ext = file.path
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                let stats = extensions.entry(ext).or_default();
+                stats.files += 1;
+                if i > 0 && !result.hardlinked.contains(&file.path) {
+                    stats.duplicate_files += 1;
+                    stats.wasted_space += file.size;
+                }
+            }
+
+            groups.push(ReportGroup {
+                hash: files[0].hash.clone(),
+                size: files[0].size,
+                detected_type: files[0].detected_type.clone(),
+                normalized: is_normalized_group(files),
+                decompressed: is_decompressed_group(files),
+                nul_padded: is_nul_padded_group(files),
+                files: files.iter().map(|f| f.path.clone()).collect(),
+                file_roots: Vec::new(),
+                modified: files.iter().map(|f| unix_seconds(f.modified)).collect(),
+            });
+        }
+
+        groups.sort_by(|a, b| a.hash.cmp(&b.hash).then_with(|| a.files.cmp(&b.files)));
+        empty.sort();
+
+        Self {
+            summary: ReportSummary {
+                total_files: result.total_files,
+                total_size: result.total_size,
+                duplicate_files: result.get_duplicate_count(),
+                wasted_space: result.get_wasted_space(),
+                empty_files: result.get_empty_file_count(),
+                dedup_ratio: result.dedup_ratio(),
+                reclaimable_percent: result.reclaimable_percentage(),
+                in_progress: false,
+                runtime_exceeded: result.runtime_exceeded,
+                size_and_name_only: result.size_and_name_only,
+                prefix_only: result.prefix_only,
+                size_only: result.size_only.clone(),
+                hash_algorithm: result.hash_algorithm,
+            },
+            extensions,
+            analysis: analyze_duplicates(result),
+            groups,
+            empty,
+            broken_links: {
+                let mut links = result.broken_links.clone();
+                links.sort();
+                links
+            },
+            roots: Vec::new(),
+        }
+    }
+
+    /// Rewrite group paths relative to the scan root each file was found under,
+    /// so reports are portable and diffable across machines
+    ///
+    /// The roots are recorded in `roots` and each group's `file_roots` points into
+    /// them. Paths that can't be resolved under a root are left absolute.
+    pub fn relative_to(&mut self, roots: &[PathBuf]) {
+        self.roots = roots.to_vec();
+        for group in &mut self.groups {
+            group.file_roots = group.files
+                .iter_mut()
+                .map(|path| {
+                    let root = root_containing(path, roots)?;
+                    let relative = get_relative_path(&root, path).ok()?;
+                    *path = relative;
+                    roots.iter().position(|candidate| *candidate == root)
+                })
+                .collect();
+        }
+    }
+
+    /// Where a group's files are on disk, resolving paths made relative by `relative_to`
+    pub fn file_paths(&self, group: &ReportGroup) -> Vec<PathBuf> {
+        group.files
+            .iter()
+            .enumerate()
+            .map(|(i, path)| match group.file_roots.get(i).copied().flatten().and_then(|root| self.roots.get(root)) {
+                Some(root) => root.join(path),
+                None => path.clone(),
+            })
+            .collect()
+    }
+
+    /// Read a report previously written with `save`
+    pub fn load(path: &Path) -> Result<Self> {
+        load_json(path, "report")
+    }
+
+    /// Write the report to a JSON file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_json(path, self, "report")
+    }
+
+    /// Write the report through a temporary file so an earlier copy survives a crash mid-write
+    pub fn save_atomic(&self, path: &Path) -> Result<()> {
+        save_json_atomic(path, self, "report")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileInfo;
+
+    fn file(path: &str, size: u64, hash: &str) -> FileInfo {
+        FileInfo::fixture(path, size, hash)
+    }
+
+    #[test]
+    fn test_report_field_names() {
+        let mut result = DedupResult::new();
+        result.add_file(file("/a/photo.JPG", 10, "aa")).unwrap();
+        result.add_file(file("/b/photo.jpg", 10, "aa")).unwrap();
+        result.add_file(file("/c/notes", 4, "bb")).unwrap();
+        result.filter_duplicates();
+
+        let value = serde_json::to_value(Report::from_result(&result)).unwrap();
+
+        let sections: Vec<&str> = value.as_object().unwrap().keys().map(|k| k.as_str()).collect();
+        assert_eq!(sections, ["analysis", "empty", "extensions", "groups", "summary"]);
+
+        let summary = &value["summary"];
+        assert_eq!(summary["total_files"], 3);
+        assert_eq!(summary["total_size"], 24);
+        assert_eq!(summary["duplicate_files"], 1);
+        assert_eq!(summary["wasted_space"], 10);
+        assert_eq!(summary["reclaimable_percent"], 10.0 / 24.0 * 100.0);
+
+        let jpg = &value["extensions"]["jpg"];
+        assert_eq!(jpg["files"], 2);
+        assert_eq!(jpg["duplicate_files"], 1);
+        assert_eq!(jpg["wasted_space"], 10);
+
+        for field in ["total_groups", "total_duplicates", "total_wasted_space", "small_files",
+                      "medium_files", "large_files", "largest_waste", "dedup_ratio", "reclaimable_percent"] {
+            assert!(value["analysis"].get(field).is_some(), "missing analysis.{}", field);
+        }
+
+        let group = &value["groups"][0];
+        assert_eq!(group["hash"], "aa");
+        assert_eq!(group["size"], 10);
+        assert_eq!(group["files"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_relative_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        std::fs::create_dir_all(a.join("photos")).unwrap();
+        std::fs::create_dir(&b).unwrap();
+        for path in [a.join("photos/x.jpg"), b.join("x.jpg")] {
+            std::fs::write(&path, b"data").unwrap();
+        }
+        let mut result = DedupResult::new();
+        result.add_file(file(a.join("photos/x.jpg").to_str().unwrap(), 4, "aa")).unwrap();
+        result.add_file(file(b.join("x.jpg").to_str().unwrap(), 4, "aa")).unwrap();
+        result.add_file(file("/elsewhere/x.jpg", 4, "aa")).unwrap();
+        result.filter_duplicates();
+
+        let mut report = Report::from_result(&result);
+        report.relative_to(&[a.clone(), b.clone()]);
+
+        assert_eq!(report.roots, [a, b]);
+        let group = &report.groups[0];
+        let entries: Vec<(&Path, Option<usize>)> = group.files.iter().map(|p| p.as_path()).zip(group.file_roots.iter().copied()).collect();
+        assert!(entries.contains(&(Path::new("photos/x.jpg"), Some(0))));
+        assert!(entries.contains(&(Path::new("x.jpg"), Some(1))));
+        assert!(entries.contains(&(Path::new("/elsewhere/x.jpg"), None)));
+
+        let mut resolved = report.file_paths(group);
+        resolved.sort();
+        assert_eq!(resolved, [PathBuf::from("/elsewhere/x.jpg"), report.roots[0].join("photos/x.jpg"), report.roots[1].join("x.jpg")]);
+    }
+
+    #[test]
+    fn test_empty_files_reported_separately() {
+        let mut result = DedupResult::new();
+        result.add_file(file("/a/photo.jpg", 10, "aa")).unwrap();
+        result.add_file(file("/b/photo.jpg", 10, "aa")).unwrap();
+        result.add_file(file("/a/.keep", 0, "ee")).unwrap();
+        result.add_file(file("/b/.keep", 0, "ee")).unwrap();
+        result.add_file(file("/c/.keep", 0, "ee")).unwrap();
+        result.filter_duplicates();
+
+        let report = Report::from_result(&result);
+        assert_eq!(report.summary.duplicate_files, 1);
+        assert_eq!(report.summary.wasted_space, 10);
+        assert_eq!(report.summary.empty_files, 3);
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.empty.len(), 1);
+        assert_eq!(report.empty[0].len(), 3);
+        assert_eq!(report.analysis.total_groups, 1);
+    }
+
+    #[test]
+    fn test_flatten_keepers_and_group_ids() {
+        let mut result = DedupResult::new();
+        result.add_file(file("/b/one", 10, "bb")).unwrap();
+        result.add_file(file("/a/one", 10, "bb")).unwrap();
+        result.add_file(file("/c/one", 10, "bb")).unwrap();
+        result.add_file(file("/a/two", 4, "aa")).unwrap();
+        result.add_file(file("/b/two", 4, "aa")).unwrap();
+        result.add_file(file("/a/.keep", 0, "ee")).unwrap();
+        result.add_file(file("/b/.keep", 0, "ee")).unwrap();
+        result.add_file(file("/a/single", 7, "cc")).unwrap();
+        result.filter_duplicates();
+
+        let rows = flatten(&result);
+        assert_eq!(rows.len(), 5);
+
+        let ids: Vec<usize> = rows.iter().map(|r| r.group_id).collect();
+        assert_eq!(ids, [1, 1, 2, 2, 2]);
+        assert_eq!(rows[0].hash, "aa");
+        assert_eq!(rows[2].hash, "bb");
+
+        for id in [1, 2] {
+            let group: Vec<_> = rows.iter().filter(|r| r.group_id == id).collect();
+            assert_eq!(group.iter().filter(|r| r.is_keeper).count(), 1);
+            assert!(group[0].is_keeper);
+            assert!(group.iter().all(|r| r.hash == group[0].hash && r.size == group[0].size));
+        }
+        assert_eq!(flatten(&result), rows);
+    }
+}
@@ -0,0 +1,296 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::actions::{error_kind_id, move_across_devices, ActionResult, FileOperation};
+use crate::envelope::{load_json, save_json};
+use crate::report::unix_seconds;
+
+/// What was done to a file that a revert log can undo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevertKind {
+    /// Unlinked; only a backup copy can bring it back
+    Delete,
+    /// Moved into a trash directory
+    Trash,
+    /// Moved to another directory
+    Move,
+}
+
+/// One delete or move, as recorded in a revert log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevertEntry {
+    /// Where the file was
+    pub source: PathBuf,
+    /// Where it went; `None` for a delete
+    pub target: Option<PathBuf>,
+    pub action: RevertKind,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub size: u64,
+}
+
+/// Deletes and moves done by a run, in order, so they can be undone later
+///
+/// Clones share the same entries, so one log can be handed to every worker.
+#[derive(Debug, Clone, Default)]
+pub struct RevertLog {
+    entries: Arc<Mutex<Vec<RevertEntry>>>,
+    /// File each entry is appended to as it is recorded
+    journal: Option<(PathBuf, Arc<Mutex<fs::File>>)>,
+}
+
+impl RevertLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A log that also writes each entry to `path` as a JSON line the moment it is recorded
+    ///
+    /// A run that stops partway, even on an error, still leaves every operation
+    /// it got to in the file, and `load` reads it like a saved log.
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = fs::File::create(path)
+            .with_context(|| format!("Failed to create revert log {}", path.display()))?;
+        Ok(Self { journal: Some((path.to_path_buf(), Arc::new(Mutex::new(file)))), ..Self::default() })
+    }
+
+    /// Record an operation, before it is carried out where its target is known in advance
+    ///
+    /// An entry whose operation then failed is harmless: reverting finds the
+    /// file still in place and leaves it alone.
+    pub fn record(&self, source: &Path, target: Option<&Path>, action: RevertKind, size: u64) -> Result<()> {
+        let entry = RevertEntry {
+            source: source.to_path_buf(),
+            target: target.map(Path::to_path_buf),
+            action,
+            timestamp: unix_seconds(SystemTime::now()),
+            size,
+        };
+        if let Some((path, file)) = &self.journal {
+            let mut line = serde_json::to_string(&entry)?;
+            line.push('\n');
+            let mut file = file.lock().unwrap();
+            file.write_all(line.as_bytes())
+                .and_then(|_| file.sync_data())
+                .with_context(|| format!("Failed to write revert log {}", path.display()))?;
+        }
+        self.entries.lock().unwrap().push(entry);
+        Ok(())
+    }
+
+    /// The recorded operations, oldest first
+    pub fn entries(&self) -> Vec<RevertEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_json(path, &self.entries(), "revert log")
+    }
+
+    /// Read a log written by `save`, or one written line by line by a log from `create`
+    pub fn load(path: &Path) -> Result<Self> {
+        let entries: Vec<RevertEntry> = match load_json(path, "revert log") {
+            Ok(entries) => entries,
+            Err(e) => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read revert log {}", path.display()))?;
+                text.lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(serde_json::from_str)
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| e)?
+            }
+        };
+        Ok(Self { entries: Arc::new(Mutex::new(entries)), journal: None })
+    }
+}
+
+/// Undo every operation in a log, newest first
+///
+/// Moved and trashed files are moved back. A deleted file is copied back from
+/// `backup_dir`, where it is looked up under its original absolute path (so
+/// `/home/me/a.txt` is expected at `<backup_dir>/home/me/a.txt`) and must still
+/// have its recorded size. A file already back in place is left alone, so a
+/// revert can be run again after a partial failure. Existing files are never
+/// overwritten.
+pub fn revert(log: &RevertLog, backup_dir: Option<&Path>, dry_run: bool) -> ActionResult {
+    let mut result = ActionResult::new();
+    for entry in log.entries().iter().rev() {
+        result.add_operation(revert_entry(entry, backup_dir, dry_run));
+    }
+    result
+}
+
+fn revert_entry(entry: &RevertEntry, backup_dir: Option<&Path>, dry_run: bool) -> FileOperation {
+    let source = &entry.source;
+    let (from, copy) = match (entry.action, &entry.target) {
+        (RevertKind::Move | RevertKind::Trash, Some(target)) => (target.clone(), false),
+        (RevertKind::Delete, _) => match backup_dir {
+            Some(dir) => (backup_path(dir, source), true),
+            None => return failed(source, "deleted file needs a backup directory to be restored from".to_string(), None),
+        },
+        (_, None) => return failed(source, "log entry has no target to move back from".to_string(), None),
+    };
+
+    if source.symlink_metadata().is_ok() {
+        if !copy && from.symlink_metadata().is_ok() {
+            return failed(source, format!("both it and {} exist; leaving them as they are", from.display()), None);
+        }
+        println!("✔️  Already restored: {}", source.display());
+        return restored(source, 0);
+    }
+    match fs::metadata(&from) {
+        Ok(metadata) if copy && metadata.len() != entry.size => {
+            return failed(source, format!("backup {} has a different size", from.display()), None);
+        }
+        Ok(_) => {}
+        Err(e) => return failed(source, format!("can't restore from {}: {}", from.display(), e), Some(&e)),
+    }
+
+    if dry_run {
+        println!("Would restore: {} -> {}", from.display(), source.display());
+        return restored(source, 0);
+    }
+
+    let outcome = source.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| {
+        if copy {
+            fs::copy(&from, source).map(|_| ())
+        } else {
+            fs::rename(&from, source).or_else(|e| match e.kind() {
+                io::ErrorKind::CrossesDevices => move_across_devices(&from, source, true),
+                _ => Err(e),
+            })
+        }
+    });
+    match outcome {
+        Ok(()) => {
+            if entry.action == RevertKind::Trash {
+                remove_trash_info(&from);
+            }
+            println!("↩️  Restored: {} -> {}", from.display(), source.display());
+            restored(source, 1)
+        }
+        Err(e) => failed(source, format!("Failed to restore from {}: {}", from.display(), e), Some(&e)),
+    }
+}
+
+/// Where a deleted file's backup copy is expected, mirroring its absolute path
+fn backup_path(backup_dir: &Path, source: &Path) -> PathBuf {
+    let relative: PathBuf = source
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect();
+    backup_dir.join(relative)
+}
+
+/// Drop the `.trashinfo` entry of a file taken back out of a trash directory's `files/`
+fn remove_trash_info(trashed: &Path) {
+    let (Some(files_dir), Some(name)) = (trashed.parent(), trashed.file_name()) else { return };
+    if let Some(trash_dir) = files_dir.parent() {
+        let mut info = name.to_os_string();
+        info.push(".trashinfo");
+        let _ = fs::remove_file(trash_dir.join("info").join(info));
+    }
+}
+
+fn restored(path: &Path, attempts: u32) -> FileOperation {
+    FileOperation {
+        path: path.to_path_buf(),
+        action: "revert".to_string(),
+        success: true,
+        error: None,
+        space_saved: 0,
+        attempts,
+        error_kind: None,
+        os_error: None,
+    }
+}
+
+fn failed(path: &Path, error_msg: String, error: Option<&io::Error>) -> FileOperation {
+    eprintln!("❌ {}: {}", path.display(), error_msg);
+    FileOperation {
+        path: path.to_path_buf(),
+        action: "revert".to_string(),
+        success: false,
+        error: Some(error_msg),
+        space_saved: 0,
+        attempts: u32::from(error.is_some()),
+        error_kind: Some(error.map_or("cannot_revert", |e| error_kind_id(e.kind()))),
+        os_error: error.and_then(io::Error::raw_os_error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trash::trash_file;
+
+    #[test]
+    fn test_revert_moves_trash_and_deletes_twice() {
+        let dir = tempfile::tempdir().unwrap();
+        let (moved, trashed, deleted) = (dir.path().join("moved"), dir.path().join("trashed"), dir.path().join("deleted"));
+        for path in [&moved, &trashed, &deleted] {
+            fs::write(path, b"data").unwrap();
+        }
+        let log = RevertLog::new();
+
+        let target = dir.path().join("dupes/moved");
+        fs::create_dir(dir.path().join("dupes")).unwrap();
+        fs::rename(&moved, &target).unwrap();
+        log.record(&moved, Some(&target), RevertKind::Move, 4).unwrap();
+
+        let in_trash = trash_file(&trashed, &dir.path().join("trash")).unwrap();
+        log.record(&trashed, Some(&in_trash), RevertKind::Trash, 4).unwrap();
+
+        let backup = dir.path().join("backup");
+        let copy = backup_path(&backup, &deleted);
+        fs::create_dir_all(copy.parent().unwrap()).unwrap();
+        fs::copy(&deleted, &copy).unwrap();
+        fs::remove_file(&deleted).unwrap();
+        log.record(&deleted, None, RevertKind::Delete, 4).unwrap();
+
+        let path = dir.path().join("revert.json");
+        log.save(&path).unwrap();
+        let log = RevertLog::load(&path).unwrap();
+        assert_eq!(log.entries().len(), 3);
+
+        assert_eq!(revert(&log, None, false).error_count(), 1);
+        let result = revert(&log, Some(&backup), false);
+        assert_eq!(result.error_count(), 0);
+        for path in [&moved, &trashed, &deleted] {
+            assert_eq!(fs::read(path).unwrap(), b"data");
+        }
+        assert!(!target.exists() && !in_trash.exists());
+        assert_eq!(fs::read_dir(dir.path().join("trash/info")).unwrap().count(), 0);
+
+        // Running it again finds everything in place
+        assert_eq!(revert(&log, Some(&backup), false).error_count(), 0);
+
+        // A file that came back some other way is not overwritten
+        fs::write(&target, b"new").unwrap();
+        assert_eq!(revert(&log, Some(&backup), false).error_count(), 1);
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+    }
+
+    #[test]
+    fn test_journal_is_readable_without_saving() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("revert.log");
+        let log = RevertLog::create(&path).unwrap();
+        log.record(Path::new("/a"), None, RevertKind::Delete, 1).unwrap();
+        log.record(Path::new("/b"), Some(Path::new("/dupes/b")), RevertKind::Move, 2).unwrap();
+
+        let entries = RevertLog::load(&path).unwrap().entries();
+        assert_eq!(entries, log.entries());
+        assert_eq!(entries[1].target.as_deref(), Some(Path::new("/dupes/b")));
+
+        fs::write(&path, "not a log").unwrap();
+        assert!(RevertLog::load(&path).is_err());
+    }
+}
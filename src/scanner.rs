@@ -1,15 +1,36 @@
 use std::path::{Path, PathBuf};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
 use std::fs;
-use std::io::Read;
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
 use anyhow::{Result, Context};
-use blake3::Hasher;
 use walkdir::WalkDir;
 use rayon::prelude::*;
-use indicatif::{ProgressBar, ProgressStyle};
+use globset::GlobSet;
+use regex::Regex;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use console::style;
+use serde::{Serialize, Deserialize};
 
-use crate::{FileInfo, DedupResult};
+use crate::{CancelToken, FileInfo, DedupResult, HashAlgorithm};
+use crate::cache::{CachedFileInfo, ScanCache};
+use crate::category::{detect_mime_type, CategoryFilter};
+use crate::exclude::{is_excluded, path_glob_set, ExcludeRules};
+use crate::filter_cmd::FilterCommand;
+use crate::profile::Profile;
+use crate::report::Report;
+use crate::shard::Shard;
+use crate::utils::{
+    device_id, file_id, format_duration, identical_ignoring_nul_padding, inode, is_apple_metadata, is_hidden, is_system_file,
+    is_text_file, real_file_id, shared_extents, trailing_nul_len, ContentHasher, DEFAULT_HASH_LEN, MAX_HASH_LEN,
+};
+
+/// Leading bytes hashed to tell same-size files apart before any full hash
+pub const DEFAULT_PARTIAL_HASH_BYTES: u64 = 64 * 1024;
 
 /// Configuration for file scanning
 #[derive(Debug, Clone)]
@@ -18,7 +39,77 @@ pub struct ScanConfig {
     pub max_size: Option<u64>,
     pub include_extensions: HashSet<String>,
     pub exclude_extensions: HashSet<String>,
+    /// Only descend this many levels below each root; 1 keeps the files directly in it
+    pub max_depth: Option<usize>,
+    /// Leave out files fewer than this many levels below their root
+    pub min_depth: Option<usize>,
+    /// When set, only files with these extensions are hashed; the rest are counted by size only
+    pub hash_only_extensions: HashSet<String>,
+    /// Record symlinks whose target doesn't exist
+    pub report_broken_links: bool,
+    /// Leave out AppleDouble companions, `.DS_Store` and other macOS metadata
+    pub ignore_apple_metadata: bool,
+    /// Leave out dotfiles, files in dot-directories and (on Windows) files with the hidden attribute
+    pub exclude_hidden: bool,
+    /// Leave out operating system files such as `desktop.ini` and `Thumbs.db`
+    pub exclude_system_files: bool,
+    /// Globs matched against paths relative to their scan root
+    pub exclude_globs: GlobSet,
+    /// Paths relative to their scan root whose files are left out
+    pub exclude_paths: HashSet<PathBuf>,
+    /// When not empty, only files whose full canonical path matches one of these globs
+    pub include_patterns: GlobSet,
+    /// Globs matched against full canonical paths whose files are left out
+    pub exclude_patterns: GlobSet,
+    /// Only keep files whose full canonical path matches this expression
+    pub include_regex: Option<Regex>,
+    /// Leave out files whose full canonical path matches this expression
+    pub exclude_regex: Option<Regex>,
     pub verbose: bool,
+    pub detect_types: bool,
+    /// Only keep files whose detected content type is in one of these categories
+    pub categories: Option<CategoryFilter>,
+    /// Only keep files this user command accepts
+    pub filter_cmd: Option<FilterCommand>,
+    pub canonicalize_paths: bool,
+    /// Walk into symlinked directories and scan symlinked files as the files they point to
+    pub follow_symlinks: bool,
+    pub require_same_name: bool,
+    pub per_root: bool,
+    pub hash_timeout: Option<Duration>,
+    pub normalize_text: bool,
+    /// Hash compressed files by their uncompressed content (needs the `decompress` feature)
+    pub decompress: bool,
+    /// Function used to hash file contents
+    pub hash_algorithm: HashAlgorithm,
+    /// Bytes of hash output per content hash, capped at the algorithm's digest size
+    pub hash_len: usize,
+    /// Hash only this many leading bytes of each file, a fast but unverified grouping
+    pub prefix_only: Option<u64>,
+    /// Before the full hash, hash this many leading bytes of same-size files and
+    /// fully hash only those that still collide; 0 skips this pass
+    pub partial_hash_bytes: u64,
+    /// Only hash files that share a size (and partial hash) with another file;
+    /// off, every file is fully hashed
+    pub enable_size_prefilter: bool,
+    /// Also group files that only differ by trailing NUL bytes, compared byte for byte
+    pub nul_padding: bool,
+    /// Treat files with the same size and name as identical without reading them
+    pub size_and_name_only: bool,
+    /// Only hash the files owned by this slice of a distributed scan
+    pub shard: Option<Shard>,
+    /// Rewrite a report of what is hashed so far to this file at this interval
+    pub autosave: Option<(PathBuf, Duration)>,
+    /// Reuse hashes kept in this file for files that haven't changed since they were hashed
+    pub cache_path: Option<PathBuf>,
+    /// Hash every file, whatever its size, and keep files without a duplicate in
+    /// the result, so it can be merged with a scan of another tree
+    pub keep_unique: bool,
+    /// Hide progress indicators
+    pub quiet: bool,
+    pub cancel: CancelToken,
+    /// Stop collecting and hashing at this time, as if cancelled
+    pub deadline: Option<Instant>,
 }
 
 impl Default for ScanConfig {
@@ -28,23 +119,273 @@ impl Default for ScanConfig {
             max_size: None,
             include_extensions: HashSet::new(),
             exclude_extensions: HashSet::new(),
+            max_depth: None,
+            min_depth: None,
+            hash_only_extensions: HashSet::new(),
+            report_broken_links: false,
+            ignore_apple_metadata: false,
+            exclude_hidden: false,
+            exclude_system_files: false,
+            exclude_globs: GlobSet::empty(),
+            exclude_paths: HashSet::new(),
+            include_patterns: GlobSet::empty(),
+            exclude_patterns: GlobSet::empty(),
+            include_regex: None,
+            exclude_regex: None,
             verbose: false,
+            detect_types: false,
+            categories: None,
+            filter_cmd: None,
+            canonicalize_paths: false,
+            follow_symlinks: false,
+            require_same_name: false,
+            per_root: false,
+            hash_timeout: None,
+            normalize_text: false,
+            decompress: false,
+            hash_algorithm: HashAlgorithm::default(),
+            hash_len: DEFAULT_HASH_LEN,
+            prefix_only: None,
+            partial_hash_bytes: DEFAULT_PARTIAL_HASH_BYTES,
+            enable_size_prefilter: true,
+            nul_padding: false,
+            size_and_name_only: false,
+            shard: None,
+            autosave: None,
+            cache_path: None,
+            keep_unique: false,
+            quiet: false,
+            cancel: CancelToken::default(),
+            deadline: None,
+        }
+    }
+}
+
+/// Invalid combinations of scan settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// `min_size` is larger than `max_size`, so no file could match
+    SizeRange { min: u64, max: u64 },
+    /// `min_depth` is larger than `max_depth`, so no file could match
+    DepthRange { min: usize, max: usize },
+    /// Extensions listed as both included and excluded
+    ConflictingExtensions(Vec<String>),
+    /// An empty string was given as an extension
+    EmptyExtension,
+    /// An extension was given with a leading dot, which never matches
+    LeadingDot(String),
+    /// Hash length outside 1..=MAX_HASH_LEN bytes
+    HashLen(usize),
+    /// A zero-byte prefix would put every file of a size in one group
+    ZeroPrefix,
+    /// Prefix-only hashing reads raw bytes, so it can't normalize or decompress
+    PrefixWithTransform,
+    /// NUL padding matches are found by comparing full raw contents
+    PaddingWithTransform,
+    /// Size-and-name matching reads no content, so no content-based mode applies
+    NameOnlyWithContent,
+    /// A scan cache only holds hashes of whole raw files
+    CacheWithTransform,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::SizeRange { min, max } => write!(
+                f, "minimum size ({} bytes) is larger than maximum size ({} bytes)", min, max
+            ),
+            ConfigError::DepthRange { min, max } => write!(
+                f, "minimum depth ({}) is larger than maximum depth ({})", min, max
+            ),
+            ConfigError::ConflictingExtensions(exts) => write!(
+                f, "extensions are both included and excluded: {}", exts.join(", ")
+            ),
+            ConfigError::EmptyExtension => write!(f, "an empty extension was given"),
+            ConfigError::LeadingDot(ext) => write!(
+                f, "extension '{}' should be given without the leading dot ('{}')", ext, ext.trim_start_matches('.')
+            ),
+            ConfigError::HashLen(len) => write!(
+                f, "hash length must be between 1 and {} bytes, got {}", MAX_HASH_LEN, len
+            ),
+            ConfigError::ZeroPrefix => write!(f, "prefix length must be at least 1 byte"),
+            ConfigError::PrefixWithTransform => write!(
+                f, "prefix-only hashing can't be combined with text normalization or decompression"
+            ),
+            ConfigError::NameOnlyWithContent => write!(
+                f, "size-and-name matching reads no content and can't be combined with prefix-only hashing, \
+                    text normalization, decompression or NUL padding matching"
+            ),
+            ConfigError::PaddingWithTransform => write!(
+                f, "NUL padding matching can't be combined with prefix-only hashing, text normalization or decompression"
+            ),
+            ConfigError::CacheWithTransform => write!(
+                f, "a scan cache holds whole-file hashes and can't be combined with prefix-only hashing, text normalization or decompression"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ScanConfig {
+    /// Check that the settings can match at least some files
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.hash_len == 0 || self.hash_len > MAX_HASH_LEN {
+            return Err(ConfigError::HashLen(self.hash_len));
+        }
+
+        if let Some(prefix) = self.prefix_only {
+            if prefix == 0 {
+                return Err(ConfigError::ZeroPrefix);
+            }
+            if self.normalize_text || self.decompress {
+                return Err(ConfigError::PrefixWithTransform);
+            }
+        }
+
+        if self.size_and_name_only && (self.prefix_only.is_some() || self.normalize_text || self.decompress || self.nul_padding) {
+            return Err(ConfigError::NameOnlyWithContent);
+        }
+
+        if self.nul_padding && (self.prefix_only.is_some() || self.normalize_text || self.decompress) {
+            return Err(ConfigError::PaddingWithTransform);
+        }
+
+        if self.cache_path.is_some() && self.transforms_content() {
+            return Err(ConfigError::CacheWithTransform);
+        }
+
+        if let Some(max) = self.max_size {
+            if self.min_size > max {
+                return Err(ConfigError::SizeRange { min: self.min_size, max });
+            }
+        }
+
+        if let (Some(min), Some(max)) = (self.min_depth, self.max_depth) {
+            if min > max {
+                return Err(ConfigError::DepthRange { min, max });
+            }
+        }
+
+        let extensions = self.include_extensions.iter()
+            .chain(&self.exclude_extensions)
+            .chain(&self.hash_only_extensions);
+        for ext in extensions {
+            if ext.is_empty() {
+                return Err(ConfigError::EmptyExtension);
+            }
+            if ext.starts_with('.') {
+                return Err(ConfigError::LeadingDot(ext.clone()));
+            }
         }
+
+        let mut conflicts: Vec<String> = self.include_extensions
+            .intersection(&self.exclude_extensions)
+            .cloned()
+            .collect();
+        if !conflicts.is_empty() {
+            conflicts.sort();
+            return Err(ConfigError::ConflictingExtensions(conflicts));
+        }
+
+        Ok(())
+    }
+
+    /// Whether hashes cover something other than each file's whole raw content
+    fn transforms_content(&self) -> bool {
+        self.prefix_only.is_some() || self.normalize_text || self.decompress
+    }
+}
+
+/// Why a file was left out of a scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    BelowMinSize,
+    AboveMaxSize,
+    /// Extension missing from the include list (or no extension at all)
+    NotIncluded,
+    /// Extension on the exclude list
+    Excluded,
+    /// Path matched an exclude file pattern, exclude glob or exclude regex
+    ExcludedPattern,
+    /// Path matched none of the include globs, or not the include regex
+    NotIncludedPattern,
+    /// macOS metadata such as an AppleDouble `._` file
+    AppleMetadata,
+    /// Hidden file, or file in a hidden directory
+    Hidden,
+    /// Operating system file such as `Thumbs.db`
+    SystemFile,
+    /// Detected content type outside the selected categories
+    NotInCategory,
+    /// The filter command rejected the file or timed out on it
+    FilterCommand,
+    /// The file couldn't be stat'ed or read (only counted by `AsyncScanner`)
+    Unreadable,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            SkipReason::BelowMinSize => "below min-size",
+            SkipReason::AboveMaxSize => "above max-size",
+            SkipReason::NotIncluded => "extension not in include list",
+            SkipReason::Excluded => "extension excluded",
+            SkipReason::ExcludedPattern => "matched exclude pattern",
+            SkipReason::NotIncludedPattern => "path not matched by include pattern",
+            SkipReason::AppleMetadata => "macOS metadata",
+            SkipReason::Hidden => "hidden",
+            SkipReason::SystemFile => "system file",
+            SkipReason::NotInCategory => "content type not in selected categories",
+            SkipReason::FilterCommand => "rejected by filter command",
+            SkipReason::Unreadable => "unreadable",
+        };
+        f.write_str(reason)
     }
 }
 
+/// Up-front estimate of how much work a scan will do
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanEstimate {
+    /// Files matching the filters
+    pub total_files: usize,
+    pub total_bytes: u64,
+    /// Files left to hash once sizes nothing else shares are ruled out
+    pub files_to_hash: usize,
+    pub bytes_to_hash: u64,
+}
+
 /// Scanner for finding duplicate files
 pub struct Scanner {
     config: ScanConfig,
+    /// Phase timings and stage counts of the scans run so far
+    profile: Mutex<Profile>,
+    /// Set once a scan stopped because the deadline passed
+    out_of_time: AtomicBool,
+    /// Hashes reused and updated by the incremental scan in progress
+    cache: Mutex<Option<ScanCache>>,
 }
 
 impl Scanner {
     pub fn new() -> Self {
         Self {
             config: ScanConfig::default(),
+            profile: Mutex::new(Profile::default()),
+            out_of_time: AtomicBool::new(false),
+            cache: Mutex::new(None),
         }
     }
 
+    pub fn config(&self) -> &ScanConfig {
+        &self.config
+    }
+
+    /// Time spent in each scan phase and the files left after each stage
+    pub fn profile(&self) -> Profile {
+        self.profile.lock().unwrap().clone()
+    }
+
     pub fn set_min_size(&mut self, size: u64) {
         self.config.min_size = size;
     }
@@ -53,6 +394,16 @@ impl Scanner {
         self.config.max_size = Some(size);
     }
 
+    /// Only descend this many directory levels; 1 scans just the files directly in each root
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.config.max_depth = Some(depth);
+    }
+
+    /// Only scan files at least this many levels below their root; 2 skips the files directly in it
+    pub fn set_min_depth(&mut self, depth: usize) {
+        self.config.min_depth = Some(depth);
+    }
+
     pub fn set_include_extensions(&mut self, extensions: Vec<String>) {
         self.config.include_extensions = extensions.into_iter()
             .map(|ext| ext.to_lowercase())
@@ -65,62 +416,553 @@ impl Scanner {
             .collect();
     }
 
+    /// Only hash files with these extensions, counting every other file by size alone
+    pub fn set_hash_only_extensions(&mut self, extensions: Vec<String>) {
+        self.config.hash_only_extensions = extensions.into_iter()
+            .map(|ext| ext.to_lowercase())
+            .collect();
+    }
+
+    /// List dangling symlinks found while walking the directories
+    pub fn set_report_broken_links(&mut self, report: bool) {
+        self.config.report_broken_links = report;
+    }
+
+    /// Skip macOS metadata files, so `._` companions aren't grouped with each other
+    pub fn set_ignore_apple_metadata(&mut self, ignore: bool) {
+        self.config.ignore_apple_metadata = ignore;
+    }
+
+    /// Skip dotfiles and everything in dot-directories, plus files with the hidden attribute on Windows
+    pub fn set_exclude_hidden(&mut self, exclude: bool) {
+        self.config.exclude_hidden = exclude;
+    }
+
+    /// Skip operating system files such as `desktop.ini`, `Thumbs.db` and `pagefile.sys`
+    pub fn set_exclude_system_files(&mut self, exclude: bool) {
+        self.config.exclude_system_files = exclude;
+    }
+
+    /// Leave out files matching the patterns of an exclude file
+    pub fn set_exclude_rules(&mut self, rules: &ExcludeRules) -> Result<()> {
+        self.config.exclude_globs = rules.glob_set()?;
+        self.config.exclude_paths = rules.paths.clone();
+        Ok(())
+    }
+
+    /// Only scan files whose full canonical path matches one of these globs
+    pub fn set_include_patterns(&mut self, patterns: &[String]) -> Result<()> {
+        self.config.include_patterns = path_glob_set(patterns)?;
+        Ok(())
+    }
+
+    /// Leave out files whose full canonical path matches one of these globs
+    pub fn set_exclude_patterns(&mut self, patterns: &[String]) -> Result<()> {
+        self.config.exclude_patterns = path_glob_set(patterns)?;
+        Ok(())
+    }
+
+    pub fn set_include_regex(&mut self, regex: Regex) {
+        self.config.include_regex = Some(regex);
+    }
+
+    pub fn set_exclude_regex(&mut self, regex: Regex) {
+        self.config.exclude_regex = Some(regex);
+    }
+
     pub fn set_verbose(&mut self, verbose: bool) {
         self.config.verbose = verbose;
     }
 
+    /// Sniff each hashed file's content type from its magic bytes
+    pub fn set_detect_types(&mut self, detect: bool) {
+        self.config.detect_types = detect;
+    }
+
+    /// Only scan files whose content type, sniffed from magic bytes, falls in these categories
+    pub fn set_categories(&mut self, categories: CategoryFilter) {
+        self.config.categories = Some(categories);
+    }
+
+    /// Only scan files a user command accepts, run once per file after every other filter
+    pub fn set_filter_command(&mut self, filter_cmd: FilterCommand) {
+        self.config.filter_cmd = Some(filter_cmd);
+    }
+
+    /// Collapse different spellings of the same file (symlinked dirs, overlapping roots) before hashing
+    pub fn set_canonicalize_paths(&mut self, canonicalize: bool) {
+        self.config.canonicalize_paths = canonicalize;
+    }
+
+    /// Follow symlinks inside the scanned trees; each directory is still walked only once
+    pub fn set_follow_symlinks(&mut self, follow: bool) {
+        self.config.follow_symlinks = follow;
+    }
+
+    /// Only group files whose names also match
+    pub fn set_require_same_name(&mut self, require: bool) {
+        self.config.require_same_name = require;
+    }
+
+    /// Only group files found under the same input directory
+    pub fn set_per_root(&mut self, per_root: bool) {
+        self.config.per_root = per_root;
+    }
+
+    /// Skip files whose hashing takes longer than this
+    ///
+    /// Reads then happen on a separate thread, so a read that never returns (a
+    /// stalled network mount) also times out. That thread stays blocked until the
+    /// read finally returns.
+    pub fn set_hash_timeout(&mut self, timeout: Duration) {
+        self.config.hash_timeout = Some(timeout);
+    }
+
+    /// Hash text files with line endings and a UTF-8 BOM normalized, so CRLF and LF copies group together
+    pub fn set_normalize_text(&mut self, normalize: bool) {
+        self.config.normalize_text = normalize;
+    }
+
+    /// Group files on size and name alone, without hashing: fast, but no content is verified
+    pub fn set_size_and_name_only(&mut self, enabled: bool) {
+        self.config.size_and_name_only = enabled;
+    }
+
+    /// Also group files that differ only by trailing NUL bytes, e.g. zero-padded generated files
+    pub fn set_nul_padding(&mut self, nul_padding: bool) {
+        self.config.nul_padding = nul_padding;
+    }
+
+    /// Hash gzip files by their uncompressed content, so they group with uncompressed twins
+    #[cfg(feature = "decompress")]
+    pub fn set_decompress(&mut self, decompress: bool) {
+        self.config.decompress = decompress;
+    }
+
+    /// Hash file contents with this algorithm instead of BLAKE3
+    pub fn set_hash_algorithm(&mut self, algorithm: HashAlgorithm) {
+        self.config.hash_algorithm = algorithm;
+    }
+
+    /// Number of hash output bytes per hash; shorter hashes make compact reports but collide sooner
+    pub fn set_hash_len(&mut self, len: usize) {
+        self.config.hash_len = len;
+    }
+
+    /// Group files on the hash of their first `bytes` bytes (and their size) without reading the rest
+    ///
+    /// Files sharing a prefix can still differ further on, so groups must be
+    /// verified before anything destructive is done with them.
+    pub fn set_prefix_only(&mut self, bytes: u64) {
+        self.config.prefix_only = Some(bytes);
+    }
+
+    /// Leading bytes hashed to rule out same-size files before the full hash (0 to skip)
+    pub fn set_partial_hash_bytes(&mut self, bytes: u64) {
+        self.config.partial_hash_bytes = bytes;
+    }
+
+    /// Skip the size and partial hash passes and fully hash every file, e.g. to benchmark them
+    pub fn set_size_prefilter(&mut self, enabled: bool) {
+        self.config.enable_size_prefilter = enabled;
+    }
+
+    /// Hash only this shard's files and keep the result unfiltered for a later merge
+    pub fn set_shard(&mut self, shard: Shard) {
+        self.config.shard = Some(shard);
+    }
+
+    /// Periodically write a report of the files hashed so far, so a crash doesn't lose them
+    ///
+    /// Each write goes through a temporary file and a rename; the caller writes the
+    /// final report once the scan is done.
+    pub fn set_autosave(&mut self, path: PathBuf, interval: Duration) {
+        self.config.autosave = Some((path, interval));
+    }
+
+    /// Keep hashes in this file between scans and reuse them for unchanged files
+    pub fn set_cache_path(&mut self, path: PathBuf) {
+        self.config.cache_path = Some(path);
+    }
+
+    /// Hash every file and keep unique ones, for merging with another tree's result
+    pub fn set_keep_unique(&mut self, keep: bool) {
+        self.config.keep_unique = keep;
+    }
+
+    /// Hide the collection and hashing progress indicators
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.config.quiet = quiet;
+    }
+
+    /// Stop collecting and hashing once the token is cancelled, keeping what was hashed so far
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.config.cancel = cancel;
+    }
+
+    /// Stop collecting and hashing once this much time has passed from now; the
+    /// result is then partial and marked `runtime_exceeded`
+    pub fn set_max_runtime(&mut self, budget: Duration) {
+        self.config.deadline = Some(Instant::now() + budget);
+    }
+
+    /// Whether collection and hashing should stop, because of cancellation or the deadline
+    fn should_stop(&self) -> bool {
+        if self.config.cancel.is_cancelled() {
+            return true;
+        }
+        let expired = self.config.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if expired {
+            self.out_of_time.store(true, Ordering::Relaxed);
+        }
+        expired
+    }
+
+    /// Whether the scan was cancelled or ran out of time, without checking the clock again
+    fn stopped_early(&self) -> bool {
+        self.config.cancel.is_cancelled() || self.out_of_time.load(Ordering::Relaxed)
+    }
+
+    /// Progress message for a stage that stopped early
+    fn stopped_message(&self, stage: &str) -> String {
+        if self.out_of_time.load(Ordering::Relaxed) {
+            format!("⏱️ {} stopped at the runtime limit", stage)
+        } else {
+            format!("⚠️ {} interrupted", stage)
+        }
+    }
+
     /// Scan directories for duplicate files
+    ///
+    /// With a cache path set, the scan is incremental: the cache is read from that
+    /// file, pruned of files that are gone and written back afterwards.
     pub fn scan_directories(&self, directories: &[PathBuf]) -> Result<DedupResult> {
+        if let Some(path) = &self.config.cache_path {
+            let mut cache = ScanCache::load(path)?;
+            let result = self.scan_directories_incremental(directories, &mut cache)?;
+            cache.evict_missing();
+            cache.save(path)?;
+            return Ok(result);
+        }
+        self.scan(directories)
+    }
+
+    /// Scan directories, reusing the cached hash of every file whose size and
+    /// modification time are unchanged
+    ///
+    /// Files that are new or changed are hashed as usual and their hashes added
+    /// to the cache. A cache built with another hash algorithm or length is
+    /// cleared first.
+    pub fn scan_directories_incremental(&self, directories: &[PathBuf], cache: &mut ScanCache) -> Result<DedupResult> {
+        self.config.validate()?;
+        if self.config.transforms_content() {
+            return Err(ConfigError::CacheWithTransform.into());
+        }
+
+        cache.use_hash(self.config.hash_algorithm, self.config.hash_len);
+        *self.cache.lock().unwrap() = Some(std::mem::take(cache));
+        let result = self.scan(directories);
+        *cache = self.cache.lock().unwrap().take().unwrap_or_default();
+        result
+    }
+
+    fn scan(&self, directories: &[PathBuf]) -> Result<DedupResult> {
+        self.config.validate()?;
+
         // First pass: collect all files
-        let files = self.collect_files(directories)?;
+        let started = Instant::now();
+        let collected = self.collect(directories)?;
+        {
+            let mut profile = self.profile.lock().unwrap();
+            profile.record("collection", started.elapsed());
+            profile.counts.files_collected += collected.files.len();
+        }
         
+        let mut result = if collected.files.is_empty() {
+            self.empty_result()
+        } else {
+            // Second pass: hash files and build result
+            self.hash_files(collected.files, directories)?
+        };
+        result.skipped = collected.skipped;
+        result.broken_links = collected.broken_links;
+        result.runtime_exceeded = self.out_of_time.load(Ordering::Relaxed);
+        Ok(result)
+    }
+
+    /// Estimate how many files and bytes a scan of the directories would hash, without hashing
+    pub fn estimate(&self, directories: &[PathBuf]) -> Result<ScanEstimate> {
+        self.config.validate()?;
+
+        let files = self.collect_files(directories)?;
+        let buckets = self.bucket_by_size(files);
+
+        Ok(ScanEstimate {
+            total_files: buckets.candidates.len() + buckets.unique.len(),
+            total_bytes: buckets.candidate_bytes() + buckets.unique_bytes(),
+            files_to_hash: buckets.candidates.len(),
+            bytes_to_hash: buckets.candidate_bytes(),
+        })
+    }
+
+    /// Hash every matching file in the directories without grouping them
+    pub fn hash_directories(&self, directories: &[PathBuf]) -> Result<Vec<FileInfo>> {
+        self.config.validate()?;
+
+        let files: Vec<PathBuf> = self.collect_files(directories)?.into_iter().map(|(path, _)| path).collect();
+
         if files.is_empty() {
-            return Ok(DedupResult::new());
+            return Ok(Vec::new());
+        }
+
+        let mut files = self.hash_all(files, directories, None)?.files;
+        for file in &mut files {
+            file.source_root = source_root(&file.path, directories);
+        }
+        Ok(files)
+    }
+
+    /// Hash every matching file whose name appears more than once, for finding same-named files that differ
+    pub fn hash_same_names(&self, directories: &[PathBuf]) -> Result<Vec<FileInfo>> {
+        self.config.validate()?;
+
+        let files = self.collect_files(directories)?;
+        let mut names: HashMap<&std::ffi::OsStr, usize> = HashMap::new();
+        for name in files.iter().filter_map(|(path, _)| path.file_name()) {
+            *names.entry(name).or_default() += 1;
+        }
+        let shared: Vec<PathBuf> = files.iter()
+            .filter(|(path, _)| path.file_name().is_some_and(|name| names[name] > 1))
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        let mut files = self.hash_all(shared, directories, None)?.files;
+        for file in &mut files {
+            file.source_root = source_root(&file.path, directories);
+        }
+        Ok(files)
+    }
+
+    /// Files in the corpus with the same content as `candidate`, e.g. to check
+    /// whether an upload is already stored
+    ///
+    /// Only corpus files of the candidate's size are hashed, unless hashes cover
+    /// normalized or decompressed content. The candidate itself is never returned,
+    /// even when it lies inside the corpus.
+    pub fn find_duplicates_of(&self, candidate: &Path, corpus: &[PathBuf]) -> Result<Vec<FileInfo>> {
+        self.config.validate()?;
+
+        let (wanted, _) = self.hash_file(candidate)?;
+        let candidate_real = fs::canonicalize(candidate).ok();
+        let by_size = !(self.config.normalize_text || self.config.decompress);
+        let files: Vec<PathBuf> = self.collect_files(corpus)?
+            .into_iter()
+            .filter(|(_, size)| !by_size || *size == wanted.size)
+            .map(|(path, _)| path)
+            .filter(|path| candidate_real.is_none() || fs::canonicalize(path).ok() != candidate_real)
+            .collect();
+
+        let mut matches: Vec<FileInfo> = self.hash_all(files, corpus, None)?
+            .files
+            .into_iter()
+            .filter(|file| file.hash == wanted.hash)
+            .collect();
+        for file in &mut matches {
+            file.source_root = source_root(&file.path, corpus);
         }
+        matches.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(matches)
+    }
 
-        // Second pass: hash files and build result
-        self.hash_files(files)
+    /// Collect all files from directories based on filters, with the size each had when found
+    pub fn collect_files(&self, directories: &[PathBuf]) -> Result<Vec<(PathBuf, u64)>> {
+        Ok(self.collect(directories)?.files)
     }
 
-    /// Collect all files from directories based on filters
-    fn collect_files(&self, directories: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    /// Collect matching files, counting the ones filtered out by reason
+    fn collect(&self, directories: &[PathBuf]) -> Result<Collected> {
         let mut files = Vec::new();
+        let mut skipped = BTreeMap::new();
+        let mut broken_links = Vec::new();
+
+        let progress = self.progress_bar(None);
+        progress.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.green} [{elapsed_precise}] {pos} files found {msg}")
+                .unwrap()
+        );
+        progress.enable_steady_tick(Duration::from_millis(100));
+
+        let overlaps = overlapping_roots(directories);
+        let mut visited_dirs = HashSet::new();
+        for (inner, outer) in &overlaps {
+            progress.suspend(|| {
+                eprintln!("{}", style(format!(
+                    "Warning: {} overlaps {}; files reached through both are only counted once",
+                    inner.display(), outer.display()
+                )).yellow());
+            });
+        }
         
         for dir in directories {
             if !dir.exists() {
-                eprintln!("{}", style(format!("Warning: Directory {} does not exist", dir.display())).yellow());
+                progress.suspend(|| {
+                    eprintln!("{}", style(format!("Warning: Directory {} does not exist", dir.display())).yellow());
+                });
                 continue;
             }
 
             if !dir.is_dir() {
-                eprintln!("{}", style(format!("Warning: {} is not a directory", dir.display())).yellow());
+                progress.suspend(|| {
+                    eprintln!("{}", style(format!("Warning: {} is not a directory", dir.display())).yellow());
+                });
                 continue;
             }
 
-            let walker = WalkDir::new(dir)
-                .follow_links(false)
+            // A symlinked input directory is walked as given, so its files keep paths
+            // under `dir`; symlinks inside the tree are only followed when asked for
+            let mut walker = WalkDir::new(dir)
+                .follow_root_links(true)
+                .follow_links(self.config.follow_symlinks);
+            if let Some(depth) = self.config.max_depth {
+                walker = walker.max_depth(depth);
+            }
+            if let Some(depth) = self.config.min_depth {
+                walker = walker.min_depth(depth);
+            }
+            // Following symlinks, a directory reached a second time (through a cycle
+            // or another link to it) is not walked again
+            let walker = walker
                 .into_iter()
-                .filter_map(|e| e.ok())
-                .filter(|e| e.file_type().is_file());
+                .filter_entry(|entry| {
+                    !(self.config.follow_symlinks && entry.file_type().is_dir())
+                        || real_file_id(entry.path()).is_none_or(|id| visited_dirs.insert(id))
+                });
 
             for entry in walker {
+                if self.should_stop() {
+                    break;
+                }
+
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        // A followed symlink whose target is gone can't be walked
+                        if let Some(path) = e.path().filter(|path| path.is_symlink() && !path.exists()) {
+                            if self.config.report_broken_links {
+                                broken_links.push(path.to_path_buf());
+                            }
+                        }
+                        continue;
+                    }
+                };
+
+                // Symlinks left unfollowed are skipped; with reporting on, dangling ones are noted
+                if entry.file_type().is_symlink() && self.config.report_broken_links && !entry.path().exists() {
+                    if self.config.verbose {
+                        progress.suspend(|| println!("broken symlink {}", entry.path().display()));
+                    }
+                    broken_links.push(entry.path().to_path_buf());
+                }
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+
                 let path = entry.path().to_path_buf();
                 
-                if self.should_include_file(&path)? {
-                    files.push(path);
+                let relative = path.strip_prefix(dir).unwrap_or(&path);
+                match self.filter_file(&path, relative)? {
+                    Ok(size) => {
+                        files.push((path, size));
+                        progress.inc(1);
+                    }
+                    Err(reason) => {
+                        if self.config.verbose {
+                            progress.suspend(|| println!("skipped {}: {}", path.display(), reason));
+                        }
+                        *skipped.entry(reason).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        if self.stopped_early() {
+            progress.abandon_with_message(self.stopped_message("Collection"));
+        } else {
+            progress.finish_with_message("✅ Collection complete");
+        }
+
+        if self.config.canonicalize_paths || self.config.follow_symlinks || !overlaps.is_empty() {
+            let aliases = dedup_by_canonical_path(&mut files);
+            if self.config.verbose && aliases > 0 {
+                println!("{} paths were aliases of files already collected", aliases);
+            }
+        }
+
+        // Sniffing content types reads every file, so it runs last and in parallel
+        if let Some(categories) = &self.config.categories {
+            let (kept, off_category): (Vec<_>, Vec<_>) = files
+                .into_par_iter()
+                .partition(|(path, _)| categories.matches_file(path));
+            files = kept;
+            if !off_category.is_empty() {
+                *skipped.entry(SkipReason::NotInCategory).or_default() += off_category.len();
+            }
+        }
+
+        // A process per file is the slowest filter by far, so it only sees what the others let through
+        if let Some(filter_cmd) = &self.config.filter_cmd {
+            let filtered = filter_cmd.filter(files.iter().map(|(path, _)| path.clone()).collect())?;
+            for path in &filtered.timed_out {
+                eprintln!("{}", style(format!(
+                    "Warning: Filter command timed out on {}, leaving it out",
+                    path.display()
+                )).yellow());
+            }
+            if self.config.verbose {
+                for path in &filtered.rejected {
+                    println!("skipped {}: {}", path.display(), SkipReason::FilterCommand);
                 }
             }
+            let left_out = filtered.rejected.len() + filtered.timed_out.len();
+            if left_out > 0 {
+                *skipped.entry(SkipReason::FilterCommand).or_default() += left_out;
+            }
+            let kept: HashSet<PathBuf> = filtered.kept.into_iter().collect();
+            files.retain(|(path, _)| kept.contains(path));
         }
 
         if self.config.verbose {
             println!("{} files found matching criteria", files.len());
         }
 
-        Ok(files)
+        Ok(Collected { files, skipped, broken_links })
+    }
+
+    /// Whether a file's extension is in the hash-only set
+    fn hashes_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|ext| self.config.hash_only_extensions.contains(&ext.to_string_lossy().to_lowercase()))
     }
 
-    /// Check if a file should be included based on filters
-    fn should_include_file(&self, path: &Path) -> Result<bool> {
+    /// The file's size if the filters let it through, otherwise why they leave it out
+    fn filter_file(&self, path: &Path, relative: &Path) -> Result<Result<u64, SkipReason>> {
+        if is_excluded(&self.config.exclude_globs, &self.config.exclude_paths, relative) {
+            return Ok(Err(SkipReason::ExcludedPattern));
+        }
+        if self.config.ignore_apple_metadata && is_apple_metadata(relative) {
+            return Ok(Err(SkipReason::AppleMetadata));
+        }
+        if self.config.exclude_hidden && is_hidden(path, relative) {
+            return Ok(Err(SkipReason::Hidden));
+        }
+        if self.config.exclude_system_files && is_system_file(path) {
+            return Ok(Err(SkipReason::SystemFile));
+        }
+        if let Some(reason) = self.match_path_patterns(path) {
+            return Ok(Err(reason));
+        }
+
         let metadata = fs::metadata(path)
             .with_context(|| format!("Failed to get metadata for {}", path.display()))?;
 
@@ -128,12 +970,12 @@ impl Scanner {
 
         // Size filters
         if size < self.config.min_size {
-            return Ok(false);
+            return Ok(Err(SkipReason::BelowMinSize));
         }
 
         if let Some(max_size) = self.config.max_size {
             if size > max_size {
-                return Ok(false);
+                return Ok(Err(SkipReason::AboveMaxSize));
             }
         }
 
@@ -142,82 +984,672 @@ impl Scanner {
             let ext_str = ext.to_string_lossy().to_lowercase();
             
             // If include list is specified, file must be in it
-            if !self.config.include_extensions.is_empty() {
-                if !self.config.include_extensions.contains(&ext_str) {
-                    return Ok(false);
-                }
+            if !self.config.include_extensions.is_empty()
+                && !self.config.include_extensions.contains(&ext_str)
+            {
+                return Ok(Err(SkipReason::NotIncluded));
             }
             
             // If exclude list is specified, file must not be in it
             if self.config.exclude_extensions.contains(&ext_str) {
-                return Ok(false);
+                return Ok(Err(SkipReason::Excluded));
             }
         } else if !self.config.include_extensions.is_empty() {
             // No extension, but include list is specified
-            return Ok(false);
+            return Ok(Err(SkipReason::NotIncluded));
+        }
+
+        Ok(Ok(size))
+    }
+
+    /// Why the include and exclude globs and regexes leave a file out, if they do
+    fn match_path_patterns(&self, path: &Path) -> Option<SkipReason> {
+        let config = &self.config;
+        if config.include_patterns.is_empty() && config.exclude_patterns.is_empty()
+            && config.include_regex.is_none() && config.exclude_regex.is_none()
+        {
+            return None;
+        }
+
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let text = canonical.to_string_lossy();
+        if !config.include_patterns.is_empty() && !config.include_patterns.is_match(&canonical) {
+            return Some(SkipReason::NotIncludedPattern);
+        }
+        if config.include_regex.as_ref().is_some_and(|regex| !regex.is_match(&text)) {
+            return Some(SkipReason::NotIncludedPattern);
         }
+        if config.exclude_patterns.is_match(&canonical)
+            || config.exclude_regex.as_ref().is_some_and(|regex| regex.is_match(&text))
+        {
+            return Some(SkipReason::ExcludedPattern);
+        }
+        None
+    }
 
-        Ok(true)
+    /// A result with no files yet, set up to group them the way this scan does
+    fn empty_result(&self) -> DedupResult {
+        let mut result = DedupResult::new();
+        result.require_same_name = self.config.require_same_name || self.config.size_and_name_only;
+        result.per_root = self.config.per_root;
+        result.group_by_size = !(self.config.normalize_text || self.config.decompress);
+        result.prefix_only = self.config.prefix_only;
+        result.size_and_name_only = self.config.size_and_name_only;
+        result.hash_algorithm = self.config.hash_algorithm;
+        result.keeps_unique = self.config.keep_unique;
+        result
     }
 
     /// Hash files in parallel and build the result
-    fn hash_files(&self, files: Vec<PathBuf>) -> Result<DedupResult> {
-        let progress = ProgressBar::new(files.len() as u64);
-        progress.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-                .unwrap()
-                .progress_chars("##-")
-        );
+    fn hash_files(&self, files: Vec<(PathBuf, u64)>, directories: &[PathBuf]) -> Result<DedupResult> {
+        let mut result = self.empty_result();
 
-        let file_infos: Result<Vec<FileInfo>, _> = files
-            .into_par_iter()
-            .map(|path| {
-                let result = self.hash_file(&path);
-                progress.inc(1);
-                result
+        // A file whose size nothing else shares can't have a duplicate
+        let started = Instant::now();
+        let mut buckets = self.bucket_by_size(files);
+        if let Some(shard) = self.config.shard {
+            // Sizes were bucketed over the whole tree; only ownership is split
+            buckets.candidates.retain(|(path, _)| shard.owns(path));
+            buckets.unique.retain(|(path, _)| shard.owns(path));
+        }
+        result.total_files += buckets.unique.len();
+        result.total_size += buckets.unique_bytes();
+
+        if !self.config.hash_only_extensions.is_empty() {
+            let (hashed, size_only): (Vec<_>, Vec<_>) = std::mem::take(&mut buckets.candidates)
+                .into_iter()
+                .partition(|(path, _)| self.hashes_extension(path));
+            buckets.candidates = hashed;
+
+            let unique_size_only: Vec<u64> = buckets.unique.iter()
+                .filter(|(path, _)| !self.hashes_extension(path))
+                .map(|(_, size)| *size)
+                .collect();
+            let same_size_bytes: u64 = size_only.iter().map(|(_, size)| size).sum();
+
+            result.size_only.files = size_only.len() + unique_size_only.len();
+            result.size_only.bytes = same_size_bytes + unique_size_only.iter().sum::<u64>();
+            result.size_only.same_size = size_only.len();
+            result.total_files += size_only.len();
+            result.total_size += same_size_bytes;
+        }
+        {
+            let mut profile = self.profile.lock().unwrap();
+            profile.record("size_grouping", started.elapsed());
+            profile.counts.files_after_size_filter += buckets.candidates.len();
+        }
+
+        // Hard links share their content, so each file is only read under its first
+        // name; one that only shared its size with its own links is settled unread
+        let mut link_names = HashMap::new();
+        if !self.config.size_and_name_only {
+            result.already_linked = split_hard_links(&mut buckets.candidates);
+            link_names = result.already_linked
+                .iter()
+                .map(|names| (names[0].clone(), names[1..].to_vec()))
+                .collect();
+            if !result.already_linked.is_empty() {
+                let split = self.bucket_by_size(std::mem::take(&mut buckets.candidates));
+                let settled = with_link_names(split.unique, &link_names);
+                result.total_files += settled.len();
+                result.total_size += settled.iter().map(|(_, size)| size).sum::<u64>();
+                buckets.candidates = split.candidates;
+                buckets.unique.extend(settled);
+            }
+        }
+
+        // Most same-size files already differ in their first bytes, so only those
+        // that still match there are read in full
+        if self.uses_partial_hash() {
+            let started = Instant::now();
+            let split = self.split_by_partial_hash(std::mem::take(&mut buckets.candidates));
+            let unique = with_link_names(split.unique, &link_names);
+            result.total_files += unique.len();
+            result.total_size += unique.iter().map(|(_, size)| size).sum::<u64>();
+            buckets.candidates = split.candidates;
+            buckets.unique.extend(unique);
+
+            let mut profile = self.profile.lock().unwrap();
+            profile.record("partial_hashing", started.elapsed());
+            profile.counts.files_after_partial_hash += buckets.candidates.len();
+        }
+
+        let started = Instant::now();
+        let candidates = buckets.candidates.into_iter().map(|(path, _)| path).collect();
+        let hashed = if self.config.size_and_name_only {
+            self.name_keys(candidates)?
+        } else {
+            match &self.config.autosave {
+                Some((path, interval)) => self.hash_with_autosave(candidates, directories, &result, path, *interval)?,
+                None => self.hash_all(candidates, directories, None)?,
+            }
+        };
+        if !self.config.size_and_name_only {
+            let mut profile = self.profile.lock().unwrap();
+            profile.record("hashing", started.elapsed());
+            profile.counts.files_hashed += hashed.files.len();
+            profile.counts.bytes_hashed += hashed.files.iter().map(|file| file.size).sum::<u64>();
+        }
+        result.timed_out = hashed.timed_out;
+        result.size_changed = hashed.size_changed;
+        let mut files = hashed.files;
+        let links: Vec<FileInfo> = files
+            .iter()
+            .flat_map(|file| {
+                let names = link_names.get(&file.path).map(Vec::as_slice).unwrap_or_default();
+                names.iter().map(|path| FileInfo { path: path.clone(), ..file.clone() })
             })
             .collect();
+        files.extend(links);
+        for mut file_info in files {
+            file_info.source_root = source_root(&file_info.path, directories);
+            result.add_file(file_info)?;
+        }
 
-        progress.finish_with_message("✅ Hashing complete");
-
-        let mut result = DedupResult::new();
-        
-        for file_info in file_infos? {
-            result.add_file(file_info);
+        // Padded copies differ in size and hash, so they are left without a group so far
+        let mut unmatched: Vec<(PathBuf, u64)> = Vec::new();
+        if self.config.nul_padding && self.config.shard.is_none() {
+            unmatched.extend(buckets.unique.iter()
+                .filter(|(path, _)| self.config.hash_only_extensions.is_empty() || self.hashes_extension(path))
+                .cloned());
+            unmatched.extend(result.duplicates.values()
+                .filter(|files| files.len() == 1)
+                .map(|files| (files[0].path.clone(), files[0].size)));
         }
 
-        // Filter out non-duplicates
-        result.filter_duplicates();
+        // Filter out non-duplicates, unless the group's other members may be in another shard or tree
+        if self.config.shard.is_none() && !self.config.keep_unique {
+            result.filter_duplicates();
+        }
+        if !unmatched.is_empty() {
+            self.add_nul_padded_groups(&mut result, unmatched, directories)?;
+        }
+        result.already_shared = find_already_shared(&result);
+        result.hardlinked = find_hardlinked(&result);
 
         Ok(result)
     }
 
-    /// Hash a single file
-    fn hash_file(&self, path: &Path) -> Result<FileInfo> {
-        let metadata = fs::metadata(path)
-            .with_context(|| format!("Failed to get metadata for {}", path.display()))?;
+    /// Stand-in file entries for size-and-name matching, keyed on a hash of the file name
+    ///
+    /// Nothing is read beyond metadata; together with size and same-name grouping
+    /// the name hash puts files with equal size and name in one group.
+    fn name_keys(&self, files: Vec<PathBuf>) -> Result<HashedFiles> {
+        let files = files
+            .into_par_iter()
+            .filter(|_| !self.should_stop())
+            .map(|path| {
+                let metadata = fs::metadata(&path)
+                    .with_context(|| format!("Failed to get metadata for {}", path.display()))?;
+                let mut hasher = ContentHasher::new(self.config.hash_algorithm);
+                hasher.update(path.file_name().unwrap_or_default().as_encoded_bytes());
+                Ok(FileInfo {
+                    hash: hasher.finish(self.config.hash_len),
+                    size: metadata.len(),
+                    modified: metadata.modified().unwrap_or(std::time::UNIX_EPOCH),
+                    detected_type: None,
+                    raw_hash: None,
+                    source_root: None,
+                    hash_algorithm: self.config.hash_algorithm,
+                    inode: inode(&metadata),
+                    path,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(HashedFiles { files, ..Default::default() })
+    }
+
+    /// Group files that are identical once trailing NUL bytes are ignored, shortest first
+    ///
+    /// Files are bucketed by their length without trailing NULs, then each
+    /// bucket's members are compared byte for byte against its shortest one.
+    /// Matches are added under their own keys, since their hashes differ. When
+    /// unique files are kept, the matches leave their single-file groups, so no
+    /// file ends up in two groups.
+    fn add_nul_padded_groups(&self, result: &mut DedupResult, unmatched: Vec<(PathBuf, u64)>, directories: &[PathBuf]) -> Result<()> {
+        let measured: Vec<(u64, PathBuf, u64)> = unmatched
+            .into_par_iter()
+            .filter_map(|(path, size)| {
+                let padding = trailing_nul_len(&path).ok()?;
+                (padding < size).then_some((size - padding, path, size))
+            })
+            .collect();
+        let mut by_content_len: HashMap<u64, Vec<(PathBuf, u64)>> = HashMap::new();
+        for (content_len, path, size) in measured {
+            by_content_len.entry(content_len).or_default().push((path, size));
+        }
+
+        let mut groups: Vec<Vec<PathBuf>> = Vec::new();
+        for mut bucket in by_content_len.into_values() {
+            if bucket.iter().all(|(_, size)| *size == bucket[0].1) {
+                continue;
+            }
+            bucket.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+            while bucket.len() > 1 {
+                let (shortest, _) = bucket.remove(0);
+                let (matched, rest): (Vec<_>, Vec<_>) = bucket
+                    .into_iter()
+                    .partition(|(path, _)| identical_ignoring_nul_padding(&shortest, path).unwrap_or(false));
+                bucket = rest;
+                if !matched.is_empty() {
+                    groups.push(std::iter::once(shortest).chain(matched.into_iter().map(|(path, _)| path)).collect());
+                }
+            }
+        }
+        if groups.is_empty() {
+            return Ok(());
+        }
+
+        let hashed = self.hash_all(groups.iter().flatten().cloned().collect(), directories, None)?;
+        let mut by_path: HashMap<PathBuf, FileInfo> = hashed.files.into_iter().map(|file| (file.path.clone(), file)).collect();
+        for group in groups {
+            let mut files: Vec<FileInfo> = group.iter().filter_map(|path| by_path.remove(path)).collect();
+            if files.len() < 2 {
+                continue;
+            }
+            for file in &mut files {
+                file.source_root = source_root(&file.path, directories);
+            }
+            let padded: HashSet<&Path> = files.iter().map(|file| file.path.as_path()).collect();
+            result.duplicates.retain(|_, group| !(group.len() == 1 && padded.contains(group[0].path.as_path())));
+            result.duplicates.insert(format!("{}:nul-padded", files[0].hash), files);
+        }
+        Ok(())
+    }
+
+    /// Progress bar with the given length (a spinner if none), hidden when quiet
+    fn progress_bar(&self, len: Option<u64>) -> ProgressBar {
+        match (self.config.quiet, len) {
+            (true, _) => ProgressBar::hidden(),
+            (false, Some(len)) => ProgressBar::new(len),
+            (false, None) => ProgressBar::new_spinner(),
+        }
+    }
+
+    /// Whether same-size files go through the partial hash pass
+    ///
+    /// Hashes of normalized or decompressed content don't follow from the raw
+    /// leading bytes, prefix-only and name-only scans never read whole files, and
+    /// a shard can't tell whether another shard holds a file's twin. An incremental
+    /// scan hashes same-size files in full so that every one of them is cached, and
+    /// a scan keeping unique files needs every file's full hash.
+    fn uses_partial_hash(&self) -> bool {
+        self.config.enable_size_prefilter
+            && self.cache.lock().unwrap().is_none()
+            && !self.config.keep_unique
+            && self.config.partial_hash_bytes > 0
+            && self.config.prefix_only.is_none()
+            && !self.config.size_and_name_only
+            && !self.config.normalize_text
+            && !self.config.decompress
+            && self.config.shard.is_none()
+    }
+
+    /// Split same-size files into those whose leading bytes match another file's and those that can't have a duplicate
+    ///
+    /// Files no longer than the partial length stay candidates, since their full
+    /// hash costs no more to compute. So do files that can't be read here, so that
+    /// the full pass reports them.
+    fn split_by_partial_hash(&self, files: Vec<(PathBuf, u64)>) -> SizeBuckets {
+        let bytes = self.config.partial_hash_bytes;
+        let (mut candidates, large): (Vec<_>, Vec<_>) = files.into_iter().partition(|(_, size)| *size <= bytes);
+
+        let progress = self.progress_bar(Some(large.len() as u64));
+        progress.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} partial hashes")
+                .unwrap()
+                .progress_chars("##-")
+        );
+        let keyed: Vec<(Option<String>, (PathBuf, u64))> = large
+            .into_par_iter()
+            .map(|file| {
+                let key = if self.should_stop() {
+                    None
+                } else {
+                    fs::File::open(&file.0)
+                        .ok()
+                        .and_then(|handle| self.hash_reader(handle.take(bytes), &file.0, false).ok())
+                        .map(|digest| digest.hash)
+                };
+                progress.inc(1);
+                (key, file)
+            })
+            .collect();
+        progress.finish_and_clear();
+
+        let mut by_prefix: HashMap<(u64, String), Vec<(PathBuf, u64)>> = HashMap::new();
+        for (key, file) in keyed {
+            match key {
+                Some(hash) => by_prefix.entry((file.1, hash)).or_default().push(file),
+                None => candidates.push(file),
+            }
+        }
+
+        let mut split = SizeBuckets { candidates, unique: Vec::new() };
+        for (_, group) in by_prefix {
+            if group.len() > 1 {
+                split.candidates.extend(group);
+            } else {
+                split.unique.extend(group);
+            }
+        }
+        split
+    }
 
-        let hash = self.calculate_hash(path)?;
+    /// Split files into those sharing a size with another file and those that don't
+    ///
+    /// Text files are always candidates under text normalization, since line
+    /// endings change their size, and every file is one without the size prefilter
+    /// or when unique files are kept.
+    fn bucket_by_size(&self, files: Vec<(PathBuf, u64)>) -> SizeBuckets {
+        let mut by_size: HashMap<u64, Vec<(PathBuf, u64)>> = HashMap::new();
+        let mut buckets = SizeBuckets::default();
 
-        Ok(FileInfo {
+        for (path, size) in files {
+            if !self.config.enable_size_prefilter || self.config.keep_unique || self.config.normalize_text && is_text_file(&path) {
+                buckets.candidates.push((path, size));
+            } else {
+                // Compressed files share a bucket with files of their uncompressed size
+                let key = self.decompressed_size(&path).unwrap_or(size);
+                by_size.entry(key).or_default().push((path, size));
+            }
+        }
+
+        for (_, group) in by_size {
+            if group.len() > 1 {
+                buckets.candidates.extend(group);
+            } else {
+                buckets.unique.extend(group);
+            }
+        }
+
+        buckets
+    }
+
+    /// Hash files while a background thread rewrites a report of the progress at every interval
+    ///
+    /// Hashing threads only hand each result over a channel, so a slow report write
+    /// doesn't hold them up.
+    fn hash_with_autosave(
+        &self,
+        files: Vec<PathBuf>,
+        directories: &[PathBuf],
+        base: &DedupResult,
+        path: &Path,
+        interval: Duration,
+    ) -> Result<HashedFiles> {
+        let mut partial = DedupResult {
+            duplicates: HashMap::new(),
+            total_files: base.total_files,
+            total_size: base.total_size,
+            group_by_size: base.group_by_size,
+            require_same_name: base.require_same_name,
+            per_root: base.per_root,
+            timed_out: Vec::new(),
+            already_shared: HashSet::new(),
+            hardlinked: HashSet::new(),
+            already_linked: Vec::new(),
+            skipped: BTreeMap::new(),
+            prefix_only: base.prefix_only,
+            broken_links: Vec::new(),
+            size_only: base.size_only.clone(),
+            runtime_exceeded: false,
+            size_and_name_only: base.size_and_name_only,
+            size_changed: Vec::new(),
+            hash_algorithm: base.hash_algorithm,
+            keeps_unique: base.keeps_unique,
+        };
+        let (sender, receiver) = mpsc::channel::<FileInfo>();
+
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                let mut next = Instant::now() + interval;
+                loop {
+                    if Instant::now() >= next {
+                        let mut report = Report::from_result(&partial);
+                        report.summary.in_progress = true;
+                        if let Err(e) = report.save_atomic(path) {
+                            eprintln!("{}", style(format!("Warning: Autosave failed: {:#}", e)).yellow());
+                        }
+                        next = Instant::now() + interval;
+                    }
+                    match receiver.recv_timeout(next.saturating_duration_since(Instant::now())) {
+                        Ok(mut file) => {
+                            file.source_root = source_root(&file.path, directories);
+                            // Every file was hashed by this scan, so its algorithm matches
+                            let _ = partial.add_file(file);
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            });
+            // Dropping the sender when hashing ends stops the autosave thread
+            self.hash_all(files, directories, Some(sender))
+        })
+    }
+
+    /// Hash files in parallel with a progress bar, passing a copy of each result to `autosave`
+    ///
+    /// In verbose mode a second line shows the hashing throughput of each
+    /// storage device, so one slow drive stands out in a multi-disk scan.
+    fn hash_all(&self, files: Vec<PathBuf>, directories: &[PathBuf], autosave: Option<Sender<FileInfo>>) -> Result<HashedFiles> {
+        let multi = match self.config.quiet {
+            true => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+            false => MultiProgress::new(),
+        };
+        let progress = multi.add(self.progress_bar(Some(files.len() as u64)));
+        progress.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
+                .unwrap()
+                .progress_chars("##-")
+        );
+        let throughput_line = if self.config.verbose {
+            let line = multi.add(self.progress_bar(None));
+            line.set_style(ProgressStyle::default_spinner().template("  {msg}").unwrap());
+            line
+        } else {
+            ProgressBar::hidden()
+        };
+        let throughput = DeviceThroughput::default();
+        let size_changed = Mutex::new(Vec::new());
+
+        let outcomes: Result<Vec<Option<Result<FileInfo, PathBuf>>>> = files
+            .into_par_iter()
+            .map(|path| {
+                // Let in-flight files finish but don't start new ones
+                if self.should_stop() {
+                    return Ok(None);
+                }
+                let started = Instant::now();
+                let result = match self.hash_file(&path) {
+                    Ok((file_info, stat_size)) => {
+                        if let Some(stat_size) = stat_size {
+                            multi.suspend(|| {
+                                eprintln!("{}", style(format!(
+                                    "Warning: {} changed size while being hashed ({} bytes, then {} read)",
+                                    path.display(), stat_size, file_info.size
+                                )).yellow());
+                            });
+                            size_changed.lock().unwrap().push(path.clone());
+                        }
+                        if self.config.verbose {
+                            if let Some(device) = device_id(&path) {
+                                throughput.record(device, &path, directories, file_info.size, started);
+                                throughput_line.set_message(throughput.summary());
+                            }
+                        }
+                        if let Some(sender) = &autosave {
+                            let _ = sender.send(file_info.clone());
+                        }
+                        Ok(Some(Ok(file_info)))
+                    }
+                    Err(e) if e.is::<HashTimeout>() => {
+                        multi.suspend(|| {
+                            eprintln!("{}", style(format!("Warning: Skipped {}: {}", path.display(), e)).yellow());
+                        });
+                        Ok(Some(Err(path)))
+                    }
+                    Err(e) => Err(e),
+                };
+                progress.inc(1);
+                result
+            })
+            .collect();
+
+        if self.stopped_early() {
+            progress.abandon_with_message(self.stopped_message("Hashing"));
+        } else {
+            progress.finish_with_message("✅ Hashing complete");
+        }
+        throughput_line.finish();
+
+        let mut hashed = HashedFiles {
+            size_changed: size_changed.into_inner().unwrap(),
+            ..Default::default()
+        };
+        for outcome in outcomes?.into_iter().flatten() {
+            match outcome {
+                Ok(file_info) => hashed.files.push(file_info),
+                Err(path) => hashed.timed_out.push(path),
+            }
+        }
+        Ok(hashed)
+    }
+
+    /// Hash a single file
+    ///
+    /// The size recorded is the number of bytes hashed, so it always matches the
+    /// hash even if the file grew or shrank after being stat'ed. The stat size is
+    /// returned alongside when the two disagree.
+    fn hash_file(&self, path: &Path) -> Result<(FileInfo, Option<u64>)> {
+        let metadata = fs::metadata(path)
+            .with_context(|| format!("Failed to get metadata for {}", path.display()))?;
+
+        let normalize = self.config.normalize_text && is_text_file(path);
+        let digest = match self.cached_hash(path, &metadata) {
+            Some(hash) => Digest { hash, raw_hash: None, len: Some(metadata.len()) },
+            None => {
+                let digest = self.calculate_hash(path, normalize)?;
+                self.cache_hash(path, &metadata, &digest);
+                digest
+            }
+        };
+        // A prefix-only hash reads just the prefix, so it says nothing about the size
+        let size = digest.len.unwrap_or(metadata.len());
+
+        let detected_type = if self.config.detect_types || self.config.categories.is_some() {
+            detect_mime_type(path)
+        } else {
+            None
+        };
+
+        let file_info = FileInfo {
             path: path.to_path_buf(),
-            size: metadata.len(),
-            hash,
+            size,
+            hash: digest.hash,
             modified: metadata.modified().unwrap_or(std::time::UNIX_EPOCH),
-        })
+            detected_type,
+            raw_hash: digest.raw_hash,
+            source_root: None,
+            hash_algorithm: self.config.hash_algorithm,
+            inode: inode(&metadata),
+        };
+        Ok((file_info, (size != metadata.len()).then_some(metadata.len())))
+    }
+
+    /// The hash an incremental scan has cached for a file with this size and modification time
+    fn cached_hash(&self, path: &Path, metadata: &fs::Metadata) -> Option<String> {
+        if self.cache.lock().unwrap().is_none() {
+            return None;
+        }
+        let modified = metadata.modified().ok()?;
+        let key = cache_key(path);
+        self.cache.lock().unwrap().as_ref()?.lookup(&key, metadata.len(), modified).map(str::to_string)
     }
 
-    /// Calculate BLAKE3 hash of a file
-    fn calculate_hash(&self, path: &Path) -> Result<String> {
-        let mut file = fs::File::open(path)
+    /// Add a fresh hash to the incremental scan's cache
+    ///
+    /// A file that changed size while being read isn't cached, since its hash
+    /// doesn't match the metadata it would be stored under.
+    fn cache_hash(&self, path: &Path, metadata: &fs::Metadata, digest: &Digest) {
+        if self.cache.lock().unwrap().is_none() || digest.len != Some(metadata.len()) {
+            return;
+        }
+        let Ok(modified) = metadata.modified() else { return };
+        let key = cache_key(path);
+        if let Some(cache) = self.cache.lock().unwrap().as_mut() {
+            cache.insert(key, CachedFileInfo { hash: digest.hash.clone(), size: metadata.len(), modified });
+        }
+    }
+
+    /// Hash a file with the configured algorithm, with the file's length as read unless only a prefix was hashed
+    fn calculate_hash(&self, path: &Path, normalize: bool) -> Result<Digest> {
+        let file = fs::File::open(path)
             .with_context(|| format!("Failed to open file {}", path.display()))?;
+
+        if let Some(prefix) = self.config.prefix_only {
+            let digest = self.hash_reader(file.take(prefix), path, false)?;
+            return Ok(Digest { len: None, ..digest });
+        }
+
+        #[cfg(feature = "decompress")]
+        if self.config.decompress && crate::utils::is_compressed_file(path) {
+            let decompressed = self.hash_reader(crate::decompress::decoder(file), path, false);
+            let file = fs::File::open(path)
+                .with_context(|| format!("Failed to open file {}", path.display()))?;
+            let raw = self.hash_reader(file, path, false)?;
+            // Unreadable archives are grouped by their raw bytes instead
+            return Ok(match decompressed {
+                Ok(digest) if digest.hash != raw.hash => Digest { hash: digest.hash, raw_hash: Some(raw.hash), len: raw.len },
+                _ => raw,
+            });
+        }
         
-        let mut hasher = Hasher::new();
+        self.hash_reader(file, path, normalize)
+    }
+
+    /// Uncompressed size of a compressed file when decompression is enabled
+    fn decompressed_size(&self, path: &Path) -> Option<u64> {
+        #[cfg(feature = "decompress")]
+        if self.config.decompress && crate::utils::is_compressed_file(path) {
+            return crate::decompress::decompressed_size(path);
+        }
+        let _ = path;
+        None
+    }
+
+    /// Hash a reader with the configured algorithm, giving up once the hash timeout elapses
+    ///
+    /// With `normalize`, the first hash is of the normalized text and the raw hash
+    /// is returned alongside it when the two differ.
+    fn hash_reader<R: Read + Send + 'static>(&self, reader: R, path: &Path, normalize: bool) -> Result<Digest> {
+        let deadline = self.config.hash_timeout.map(|timeout| (Instant::now() + timeout, timeout));
+        let mut reader: Box<dyn Read> = match deadline {
+            Some((deadline, _)) => Box::new(DeadlineReader::spawn(reader, deadline)),
+            None => Box::new(reader),
+        };
+        let mut hasher = ContentHasher::new(self.config.hash_algorithm);
+        let mut normalizer = normalize.then(|| TextNormalizer::new(self.config.hash_algorithm));
         let mut buffer = vec![0; 8192]; // 8KB buffer
+        let mut len = 0u64;
         
         loop {
-            let bytes_read = file.read(&mut buffer)
+            if let Some((deadline, timeout)) = deadline {
+                if Instant::now() >= deadline {
+                    return Err(HashTimeout(timeout).into());
+                }
+            }
+
+            let read = reader.read(&mut buffer);
+            if let (Some((_, timeout)), Err(e)) = (deadline, &read) {
+                if e.kind() == io::ErrorKind::TimedOut {
+                    return Err(HashTimeout(timeout).into());
+                }
+            }
+            let bytes_read = read
                 .with_context(|| format!("Failed to read file {}", path.display()))?;
             
             if bytes_read == 0 {
@@ -225,14 +1657,1123 @@ impl Scanner {
             }
             
             hasher.update(&buffer[..bytes_read]);
+            len += bytes_read as u64;
+            if let Some(normalizer) = &mut normalizer {
+                normalizer.update(&buffer[..bytes_read]);
+            }
         }
         
-        Ok(hasher.finalize().to_hex().to_string())
+        let raw = hasher.finish(self.config.hash_len);
+        Ok(match normalizer.map(|n| n.finish().finish(self.config.hash_len)) {
+            Some(normalized) if normalized != raw => Digest { hash: normalized, raw_hash: Some(raw), len: Some(len) },
+            _ => Digest { hash: raw, raw_hash: None, len: Some(len) },
+        })
     }
 }
 
+/// The hash of some content, the raw hash when that differs, and the bytes read
+#[derive(Debug)]
+struct Digest {
+    hash: String,
+    raw_hash: Option<String>,
+    /// Bytes read from the file; `None` when only a prefix was hashed
+    len: Option<u64>,
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Hashes text with a leading UTF-8 BOM dropped and CRLF/CR line endings turned into LF
+struct TextNormalizer {
+    hasher: ContentHasher,
+    /// Leading bytes held back until we know whether they are a BOM
+    head: Vec<u8>,
+    head_done: bool,
+    pending_cr: bool,
+}
+
+impl TextNormalizer {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        Self {
+            hasher: ContentHasher::new(algorithm),
+            head: Vec::new(),
+            head_done: false,
+            pending_cr: false,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        if !self.head_done {
+            let take = (UTF8_BOM.len() - self.head.len()).min(data.len());
+            self.head.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.head.len() < UTF8_BOM.len() {
+                return;
+            }
+            self.flush_head();
+        }
+        self.feed(data);
+    }
+
+    fn flush_head(&mut self) {
+        self.head_done = true;
+        let head = std::mem::take(&mut self.head);
+        self.feed(head.strip_prefix(UTF8_BOM).unwrap_or(&head));
+    }
+
+    fn feed(&mut self, data: &[u8]) {
+        let mut out = Vec::with_capacity(data.len());
+        for &byte in data {
+            if self.pending_cr {
+                self.pending_cr = false;
+                out.push(b'\n');
+                if byte == b'\n' {
+                    continue;
+                }
+            }
+            if byte == b'\r' {
+                self.pending_cr = true;
+            } else {
+                out.push(byte);
+            }
+        }
+        self.hasher.update(&out);
+    }
+
+    fn finish(mut self) -> ContentHasher {
+        if !self.head_done {
+            self.flush_head();
+        }
+        if self.pending_cr {
+            self.hasher.update(b"\n");
+        }
+        self.hasher
+    }
+}
+
+/// Collected files with their sizes, split by whether their size is shared
+#[derive(Default)]
+struct SizeBuckets {
+    candidates: Vec<(PathBuf, u64)>,
+    unique: Vec<(PathBuf, u64)>,
+}
+
+impl SizeBuckets {
+    fn candidate_bytes(&self) -> u64 {
+        self.candidates.iter().map(|(_, size)| size).sum()
+    }
+
+    fn unique_bytes(&self) -> u64 {
+        self.unique.iter().map(|(_, size)| size).sum()
+    }
+}
+
+/// Output of the collection walk
+struct Collected {
+    files: Vec<(PathBuf, u64)>,
+    skipped: BTreeMap<SkipReason, usize>,
+    broken_links: Vec<PathBuf>,
+}
+
+/// Files hashed in one pass, plus those skipped for exceeding the hash timeout
+/// and those whose size changed while being hashed
+#[derive(Default)]
+struct HashedFiles {
+    files: Vec<FileInfo>,
+    timed_out: Vec<PathBuf>,
+    size_changed: Vec<PathBuf>,
+}
+
+/// Error returned when hashing a file takes longer than the configured timeout
+#[derive(Debug)]
+struct HashTimeout(Duration);
+
+impl std::fmt::Display for HashTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "hashing took longer than {}", format_duration(self.0))
+    }
+}
+
+impl std::error::Error for HashTimeout {}
+
+/// A reader whose reads run on their own thread, so waiting for one can give up at a deadline
+///
+/// A read that outlives the deadline fails with `ErrorKind::TimedOut`; the thread
+/// stays blocked in it and exits once it returns.
+struct DeadlineReader {
+    chunks: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+    deadline: Instant,
+}
+
+impl DeadlineReader {
+    fn spawn<R: Read + Send + 'static>(mut reader: R, deadline: Instant) -> Self {
+        let (sender, chunks) = mpsc::sync_channel(4);
+        thread::spawn(move || loop {
+            let mut chunk = vec![0; 8192];
+            let read = reader.read(&mut chunk).map(|n| {
+                chunk.truncate(n);
+                chunk
+            });
+            let done = !matches!(&read, Ok(chunk) if !chunk.is_empty());
+            if sender.send(read).is_err() || done {
+                break;
+            }
+        });
+        DeadlineReader { chunks, chunk: Vec::new(), pos: 0, deadline }
+    }
+}
+
+impl Read for DeadlineReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            let wait = self.deadline.saturating_duration_since(Instant::now());
+            self.chunk = match self.chunks.recv_timeout(wait) {
+                Ok(chunk) => chunk?,
+                Err(RecvTimeoutError::Timeout) => return Err(io::ErrorKind::TimedOut.into()),
+                // The thread only stops after sending the end of the file or an error
+                Err(RecvTimeoutError::Disconnected) => Vec::new(),
+            };
+            self.pos = 0;
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Drop paths that resolve to a file already in the list, keeping the first spelling for display.
+/// Returns how many aliases were removed.
+fn dedup_by_canonical_path(files: &mut Vec<(PathBuf, u64)>) -> usize {
+    let before = files.len();
+    let mut seen = HashSet::new();
+    files.retain(|(path, _)| {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+        seen.insert(canonical)
+    });
+    before - files.len()
+}
+
+/// Duplicates whose physical extents match an earlier member of their group
+fn find_already_shared(result: &DedupResult) -> HashSet<PathBuf> {
+    result.duplicates
+        .par_iter()
+        .flat_map_iter(|(_, files)| {
+            let extents: Vec<_> = files.iter().map(|file| shared_extents(&file.path)).collect();
+            files
+                .iter()
+                .enumerate()
+                .skip(1)
+                .filter(move |&(i, _)| {
+                    extents[i].is_some() && extents[..i].contains(&extents[i])
+                })
+                .map(|(_, file)| file.path.clone())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Keep only the first name of each file reached through several hard links
+///
+/// Returns every name of each such file, the kept one first.
+fn split_hard_links(files: &mut Vec<(PathBuf, u64)>) -> Vec<Vec<PathBuf>> {
+    let mut by_id: HashMap<(u64, u64), usize> = HashMap::new();
+    let mut names: Vec<Vec<PathBuf>> = Vec::new();
+    files.retain(|(path, _)| {
+        let Some(id) = real_file_id(path) else { return true };
+        match by_id.get(&id) {
+            Some(&i) => {
+                names[i].push(path.clone());
+                false
+            }
+            None => {
+                by_id.insert(id, names.len());
+                names.push(vec![path.clone()]);
+                true
+            }
+        }
+    });
+    names.retain(|names| names.len() > 1);
+    names
+}
+
+/// The files followed by the other hard-linked names of each, with the same size
+fn with_link_names(files: Vec<(PathBuf, u64)>, link_names: &HashMap<PathBuf, Vec<PathBuf>>) -> Vec<(PathBuf, u64)> {
+    files
+        .into_iter()
+        .flat_map(|(path, size)| {
+            let names = link_names.get(&path).cloned().unwrap_or_default();
+            std::iter::once((path, size)).chain(names.into_iter().map(move |name| (name, size)))
+        })
+        .collect()
+}
+
+/// Group members that are hard links to an earlier member, found by device and inode
+fn find_hardlinked(result: &DedupResult) -> HashSet<PathBuf> {
+    result.duplicates
+        .par_iter()
+        .flat_map_iter(|(_, files)| {
+            let ids: Vec<_> = files.iter().map(|file| file_id(&file.path)).collect();
+            files
+                .iter()
+                .enumerate()
+                .skip(1)
+                .filter(move |&(i, _)| ids[i].is_some() && ids[..i].contains(&ids[i]))
+                .map(|(_, file)| file.path.clone())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Hashing progress of one storage device
+struct DeviceStats {
+    /// Input directory (or path) the device was first seen under
+    label: String,
+    bytes: u64,
+    first_start: Instant,
+    last_end: Instant,
+}
+
+/// Per-device hashing throughput, for the verbose progress readout
+#[derive(Default)]
+struct DeviceThroughput {
+    devices: Mutex<BTreeMap<u64, DeviceStats>>,
+}
+
+impl DeviceThroughput {
+    /// Count a file hashed on `device` that started hashing at `started`
+    fn record(&self, device: u64, path: &Path, directories: &[PathBuf], bytes: u64, started: Instant) {
+        let now = Instant::now();
+        let mut devices = self.devices.lock().unwrap();
+        let stats = devices.entry(device).or_insert_with(|| DeviceStats {
+            label: source_root(path, directories)
+                .unwrap_or_else(|| path.to_path_buf())
+                .display()
+                .to_string(),
+            bytes: 0,
+            first_start: started,
+            last_end: now,
+        });
+        stats.bytes += bytes;
+        stats.first_start = stats.first_start.min(started);
+        stats.last_end = stats.last_end.max(now);
+    }
+
+    /// One "label: rate" entry per device, e.g. "/mnt/usb: 8 MB/s, /mnt/ssd: 900 MB/s"
+    fn summary(&self) -> String {
+        use humansize::{format_size, DECIMAL};
+
+        let devices = self.devices.lock().unwrap();
+        devices
+            .values()
+            .map(|stats| {
+                let secs = stats.last_end.duration_since(stats.first_start).as_secs_f64();
+                let rate = if secs > 0.0 { (stats.bytes as f64 / secs) as u64 } else { stats.bytes };
+                format!("{}: {}/s", stats.label, format_size(rate, DECIMAL))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Canonical form of a path for the scan cache, so each file has one entry however it was reached
+fn cache_key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The most specific input directory containing `path`
+fn source_root(path: &Path, directories: &[PathBuf]) -> Option<PathBuf> {
+    directories
+        .iter()
+        .filter(|dir| path.starts_with(dir))
+        .max_by_key(|dir| dir.components().count())
+        .cloned()
+}
+
+/// Pairs of input directories where the first lies inside (or is) the second
+fn overlapping_roots(directories: &[PathBuf]) -> Vec<(PathBuf, PathBuf)> {
+    let canonical: Vec<(&PathBuf, PathBuf)> = directories
+        .iter()
+        .filter_map(|dir| fs::canonicalize(dir).ok().map(|canonical| (dir, canonical)))
+        .collect();
+
+    let mut overlaps = Vec::new();
+    for (i, (dir_a, a)) in canonical.iter().enumerate() {
+        for (dir_b, b) in &canonical[i + 1..] {
+            if a.starts_with(b) {
+                overlaps.push(((*dir_a).clone(), (*dir_b).clone()));
+            } else if b.starts_with(a) {
+                overlaps.push(((*dir_b).clone(), (*dir_a).clone()));
+            }
+        }
+    }
+    overlaps
+}
+
 impl Default for Scanner {
     fn default() -> Self {
         Self::new()
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exts(list: &[&str]) -> Vec<String> {
+        list.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_validate_default_config() {
+        assert_eq!(ScanConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_size_range() {
+        let mut scanner = Scanner::new();
+        scanner.set_min_size(100);
+        scanner.set_max_size(10);
+        assert_eq!(scanner.config().validate(), Err(ConfigError::SizeRange { min: 100, max: 10 }));
+
+        scanner.set_max_size(100);
+        assert_eq!(scanner.config().validate(), Ok(()));
+
+        scanner.set_min_depth(3);
+        scanner.set_max_depth(2);
+        assert_eq!(scanner.config().validate(), Err(ConfigError::DepthRange { min: 3, max: 2 }));
+    }
+
+    #[test]
+    fn test_depth_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        fs::write(dir.path().join("top.txt"), b"1").unwrap();
+        fs::write(dir.path().join("a/mid.txt"), b"22").unwrap();
+        fs::write(dir.path().join("a/b/deep.txt"), b"333").unwrap();
+
+        let total_size = |scanner: &Scanner| scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap().total_size;
+        let mut scanner = Scanner::new();
+        assert_eq!(total_size(&scanner), 6);
+
+        scanner.set_max_depth(1);
+        assert_eq!(total_size(&scanner), 1);
+        scanner.set_max_depth(2);
+        assert_eq!(total_size(&scanner), 3);
+
+        scanner.set_min_depth(2);
+        assert_eq!(total_size(&scanner), 2);
+        scanner.set_max_depth(3);
+        assert_eq!(total_size(&scanner), 5);
+    }
+
+    #[test]
+    fn test_validate_conflicting_extensions() {
+        let mut scanner = Scanner::new();
+        scanner.set_include_extensions(exts(&["jpg", "PNG", "gif"]));
+        scanner.set_exclude_extensions(exts(&["png", "tmp", "jpg"]));
+        assert_eq!(
+            scanner.config().validate(),
+            Err(ConfigError::ConflictingExtensions(exts(&["jpg", "png"])))
+        );
+    }
+
+    #[test]
+    fn test_validate_bad_extensions() {
+        let mut scanner = Scanner::new();
+        scanner.set_include_extensions(exts(&[""]));
+        assert_eq!(scanner.config().validate(), Err(ConfigError::EmptyExtension));
+
+        let mut scanner = Scanner::new();
+        scanner.set_exclude_extensions(exts(&[".tmp"]));
+        assert_eq!(scanner.config().validate(), Err(ConfigError::LeadingDot(".tmp".to_string())));
+    }
+
+    /// Reader that yields one byte per call after a delay
+    struct SlowReader {
+        remaining: usize,
+        delay: Duration,
+    }
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            std::thread::sleep(self.delay);
+            self.remaining -= 1;
+            buf[0] = 0;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_hash_timeout() {
+        let mut scanner = Scanner::new();
+        scanner.set_hash_timeout(Duration::from_millis(50));
+        let slow = SlowReader { remaining: 1000, delay: Duration::from_millis(10) };
+
+        let err = scanner.hash_reader(slow, Path::new("slow"), false).unwrap_err();
+        assert!(err.is::<HashTimeout>());
+
+        let quick = SlowReader { remaining: 2, delay: Duration::from_millis(1) };
+        assert!(scanner.hash_reader(quick, Path::new("quick"), false).is_ok());
+
+        // A single read that hangs, like one on a stalled network mount
+        let stalled = SlowReader { remaining: 1, delay: Duration::from_secs(30) };
+        let started = Instant::now();
+        let err = scanner.hash_reader(stalled, Path::new("stalled"), false).unwrap_err();
+        assert!(err.is::<HashTimeout>());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_normalize_text_line_endings() {
+        let scanner = Scanner::new();
+        let hash = |text: &'static [u8]| scanner.hash_reader(text, Path::new("t.txt"), true).unwrap();
+
+        let lf = hash(b"one\ntwo\n");
+        assert_eq!(lf.raw_hash, None);
+        let crlf = hash(b"\xEF\xBB\xBFone\r\ntwo\r\n");
+        assert_eq!(crlf.hash, lf.hash);
+        assert!(crlf.raw_hash.is_some());
+        assert_eq!(crlf.len, Some(13));
+        assert_eq!(hash(b"one\rtwo\r").hash, lf.hash);
+        assert_ne!(hash(b"one\n\ntwo\n").hash, lf.hash);
+    }
+
+    #[test]
+    fn test_device_throughput_summary() {
+        let throughput = DeviceThroughput::default();
+        let roots = [PathBuf::from("/mnt/usb"), PathBuf::from("/mnt/ssd")];
+        let started = Instant::now();
+        throughput.record(1, Path::new("/mnt/usb/a"), &roots, 100, started);
+        throughput.record(2, Path::new("/mnt/ssd/b"), &roots, 100, started);
+        throughput.record(1, Path::new("/mnt/usb/c"), &roots, 100, started);
+
+        let summary = throughput.summary();
+        assert!(summary.starts_with("/mnt/usb: "), "{}", summary);
+        assert!(summary.contains(", /mnt/ssd: "), "{}", summary);
+        assert!(summary.ends_with("/s"), "{}", summary);
+    }
+
+    #[test]
+    fn test_skip_reasons_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("tiny.txt"), b"1").unwrap();
+        fs::write(dir.path().join("a.log"), b"12345").unwrap();
+        fs::write(dir.path().join("b.txt"), b"12345").unwrap();
+        fs::write(dir.path().join("c.txt"), b"12345").unwrap();
+
+        let mut scanner = Scanner::new();
+        scanner.set_min_size(2);
+        scanner.set_include_extensions(vec!["txt".to_string()]);
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+
+        assert_eq!(result.skipped.get(&SkipReason::BelowMinSize), Some(&1));
+        assert_eq!(result.skipped.get(&SkipReason::NotIncluded), Some(&1));
+        assert_eq!(result.skipped.len(), 2);
+        assert_eq!(SkipReason::NotIncluded.to_string(), "extension not in include list");
+    }
+
+    #[test]
+    fn test_path_patterns_and_regexes_compose() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("web/node_modules/pkg/lib/deep");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(nested.join("index.js"), b"same").unwrap();
+        fs::write(dir.path().join("web/node_modules/top.js"), b"same").unwrap();
+        fs::write(dir.path().join("src/app.js"), b"same").unwrap();
+        fs::write(dir.path().join("src/app_test.js"), b"same").unwrap();
+        fs::write(dir.path().join("src/copy.js"), b"same").unwrap();
+        fs::write(dir.path().join("src/notes.txt"), b"same").unwrap();
+
+        let mut scanner = Scanner::new();
+        scanner.set_exclude_patterns(&["**/node_modules/**".to_string()]).unwrap();
+        scanner.set_include_patterns(&["**/*.js".to_string()]).unwrap();
+        scanner.set_exclude_regex(Regex::new(r"_test\.js$").unwrap());
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+
+        assert_eq!(result.skipped.get(&SkipReason::ExcludedPattern), Some(&3));
+        assert_eq!(result.skipped.get(&SkipReason::NotIncludedPattern), Some(&1));
+        let group = result.duplicates.values().next().unwrap();
+        let mut names: Vec<_> = group.iter().map(|f| f.path.file_name().unwrap().to_owned()).collect();
+        names.sort();
+        assert_eq!(names, ["app.js", "copy.js"]);
+
+        scanner.set_include_regex(Regex::new(r"/copy\.js$").unwrap());
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        assert_eq!(result.total_files, 1);
+
+        // `*` stays within one path component
+        assert!(scanner.set_include_patterns(&["[unclosed".to_string()]).is_err());
+        scanner.set_include_patterns(&["/*.js".to_string()]).unwrap();
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        assert_eq!(result.total_files, 0);
+    }
+
+    #[test]
+    fn test_hidden_and_system_files_excluded() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        fs::create_dir_all(dir.path().join("photos/.thumbnails")).unwrap();
+        fs::write(dir.path().join(".git/config"), b"same").unwrap();
+        fs::write(dir.path().join("photos/.thumbnails/a.png"), b"same").unwrap();
+        fs::write(dir.path().join("photos/a.png"), b"same").unwrap();
+        fs::write(dir.path().join("photos/Thumbs.db"), b"same").unwrap();
+
+        let mut scanner = Scanner::new();
+        assert_eq!(scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap().total_files, 4);
+
+        scanner.set_exclude_hidden(true);
+        scanner.set_exclude_system_files(true);
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        assert_eq!(result.total_files, 1);
+        assert_eq!(result.skipped.get(&SkipReason::Hidden), Some(&2));
+        assert_eq!(result.skipped.get(&SkipReason::SystemFile), Some(&1));
+    }
+
+    #[test]
+    fn test_find_duplicates_of() {
+        let corpus = tempfile::tempdir().unwrap();
+        let uploads = tempfile::tempdir().unwrap();
+        fs::write(corpus.path().join("stored.jpg"), b"picture").unwrap();
+        fs::write(corpus.path().join("other.jpg"), b"pictura").unwrap();
+        fs::write(corpus.path().join("larger.jpg"), b"picture, but more").unwrap();
+        let upload = uploads.path().join("upload.jpg");
+        fs::write(&upload, b"picture").unwrap();
+        let roots = [corpus.path().to_path_buf()];
+
+        let mut scanner = Scanner::new();
+        scanner.set_quiet(true);
+        let matches = scanner.find_duplicates_of(&upload, &roots).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].path, corpus.path().join("stored.jpg"));
+
+        // Already stored files don't match themselves
+        assert!(scanner.find_duplicates_of(&corpus.path().join("other.jpg"), &roots).unwrap().is_empty());
+
+        fs::write(&upload, b"new picture").unwrap();
+        assert!(scanner.find_duplicates_of(&upload, &roots).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_max_runtime_marks_partial_result() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), b"same").unwrap();
+        fs::write(dir.path().join("b"), b"same").unwrap();
+        let roots = [dir.path().to_path_buf()];
+
+        let mut scanner = Scanner::new();
+        let complete = scanner.scan_directories(&roots).unwrap();
+        assert!(!complete.runtime_exceeded);
+        assert_eq!(complete.duplicates.len(), 1);
+
+        scanner.set_max_runtime(Duration::ZERO);
+        let partial = scanner.scan_directories(&roots).unwrap();
+        assert!(partial.runtime_exceeded);
+        assert!(partial.duplicates.is_empty());
+    }
+
+    #[test]
+    fn test_nul_padded_files_grouped() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("out.bin"), b"payload").unwrap();
+        fs::write(dir.path().join("out.padded.bin"), b"payload\0\0\0\0").unwrap();
+        fs::write(dir.path().join("other.bin"), b"payloaX\0").unwrap();
+        let roots = [dir.path().to_path_buf()];
+
+        let mut scanner = Scanner::new();
+        assert!(scanner.scan_directories(&roots).unwrap().duplicates.is_empty());
+
+        scanner.set_nul_padding(true);
+        let result = scanner.scan_directories(&roots).unwrap();
+        assert_eq!(result.duplicates.len(), 1);
+        let files = result.duplicates.values().next().unwrap();
+        assert!(crate::is_nul_padded_group(files));
+        assert_eq!(files[0].path, dir.path().join("out.bin"));
+        assert_eq!(files[1].path, dir.path().join("out.padded.bin"));
+        assert_eq!(result.total_files, 3);
+
+        // Kept unique files don't also stay behind in groups of their own
+        scanner.set_keep_unique(true);
+        let result = scanner.scan_directories(&roots).unwrap();
+        let mut paths: Vec<&Path> = result.duplicates.values().flatten().map(|file| file.path.as_path()).collect();
+        paths.sort();
+        assert_eq!(paths, [dir.path().join("other.bin"), dir.path().join("out.bin"), dir.path().join("out.padded.bin")]);
+        assert_eq!(result.duplicates.values().filter(|files| crate::is_nul_padded_group(files)).count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_root_is_walked() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real");
+        fs::create_dir_all(real.join("nested")).unwrap();
+        fs::write(real.join("a.jpg"), b"photo").unwrap();
+        fs::write(real.join("nested/b.jpg"), b"photo").unwrap();
+        std::os::unix::fs::symlink(&real, dir.path().join("photos")).unwrap();
+        // A link inside the tree is not followed
+        std::os::unix::fs::symlink(&real, real.join("loop")).unwrap();
+
+        let root = dir.path().join("photos");
+        let result = Scanner::new().scan_directories(std::slice::from_ref(&root)).unwrap();
+        assert_eq!(result.total_files, 2);
+        let files = result.duplicates.values().next().unwrap();
+        assert!(files.iter().all(|file| file.path.starts_with(&root) && file.source_root.as_ref() == Some(&root)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_symlinks_survives_cycles() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("a")).unwrap();
+        fs::write(dir.path().join("a/f.txt"), b"same").unwrap();
+        fs::write(dir.path().join("g.txt"), b"same").unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("a/loop")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("a"), dir.path().join("also_a")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("g.txt"), dir.path().join("link.txt")).unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("h.txt"), b"same").unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("photos")).unwrap();
+
+        let mut scanner = Scanner::new();
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        assert_eq!(result.get_duplicate_count(), 1);
+
+        scanner.set_follow_symlinks(true);
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        let group = result.duplicates.values().next().unwrap();
+        let mut names: Vec<_> = group.iter()
+            .map(|file| fs::canonicalize(&file.path).unwrap().file_name().unwrap().to_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["f.txt", "g.txt", "h.txt"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_broken_links_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), b"data").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("a"), dir.path().join("good")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("missing"), dir.path().join("dead")).unwrap();
+        let roots = [dir.path().to_path_buf()];
+
+        let result = Scanner::new().scan_directories(&roots).unwrap();
+        assert!(result.broken_links.is_empty());
+
+        let mut scanner = Scanner::new();
+        scanner.set_report_broken_links(true);
+        let result = scanner.scan_directories(&roots).unwrap();
+        assert_eq!(result.broken_links, [dir.path().join("dead")]);
+        assert!(dir.path().join("dead").symlink_metadata().is_ok());
+    }
+
+    #[test]
+    fn test_prefix_only_groups_on_leading_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), b"HEADER-one").unwrap();
+        fs::write(dir.path().join("b"), b"HEADER-two").unwrap();
+        fs::write(dir.path().join("c"), b"HEADER-three").unwrap();
+
+        let mut scanner = Scanner::new();
+        scanner.set_prefix_only(6);
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        assert_eq!(result.prefix_only, Some(6));
+        // Same prefix, but the size still splits c off
+        assert_eq!(result.duplicates.len(), 1);
+        assert_eq!(result.duplicates.values().next().unwrap().len(), 2);
+
+        scanner.set_normalize_text(true);
+        assert_eq!(scanner.config().validate(), Err(ConfigError::PrefixWithTransform));
+        scanner.set_normalize_text(false);
+        scanner.set_prefix_only(0);
+        assert_eq!(scanner.config().validate(), Err(ConfigError::ZeroPrefix));
+    }
+
+    #[test]
+    fn test_size_and_name_only_groups_without_reading() {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["x", "y", "z"] {
+            fs::create_dir(dir.path().join(sub)).unwrap();
+        }
+        fs::write(dir.path().join("x/data.bin"), b"one").unwrap();
+        fs::write(dir.path().join("y/data.bin"), b"two").unwrap();
+        fs::write(dir.path().join("z/other.bin"), b"one").unwrap();
+
+        let mut scanner = Scanner::new();
+        scanner.set_size_and_name_only(true);
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        assert!(result.size_and_name_only);
+        // Different content, same size and name: grouped anyway
+        assert_eq!(result.duplicates.len(), 1);
+        let mut paths: Vec<_> = result.duplicates.values().next().unwrap().iter().map(|f| f.path.clone()).collect();
+        paths.sort();
+        assert_eq!(paths, [dir.path().join("x/data.bin"), dir.path().join("y/data.bin")]);
+        assert_eq!(scanner.profile().counts.files_hashed, 0);
+
+        scanner.set_prefix_only(4);
+        assert_eq!(scanner.config().validate(), Err(ConfigError::NameOnlyWithContent));
+    }
+
+    #[test]
+    fn test_profile_stage_counts() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), b"same").unwrap();
+        fs::write(dir.path().join("b"), b"same").unwrap();
+        fs::write(dir.path().join("c"), b"unique size").unwrap();
+
+        let scanner = Scanner::new();
+        scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+
+        let profile = scanner.profile();
+        let phases: Vec<&str> = profile.phases.iter().map(|timing| timing.phase.as_str()).collect();
+        assert_eq!(phases, ["collection", "size_grouping", "partial_hashing", "hashing"]);
+        assert_eq!(profile.counts.files_collected, 3);
+        assert_eq!(profile.counts.files_after_size_filter, 2);
+        assert_eq!(profile.counts.files_after_partial_hash, 2);
+        assert_eq!(profile.counts.files_hashed, 2);
+        assert_eq!(profile.counts.bytes_hashed, 8);
+    }
+
+    #[test]
+    fn test_tiered_hashing_matches_full_hashing() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, content) in [
+            ("a", &b"prefix-AAAA"[..]),
+            ("b", b"prefix-AAAA"),
+            ("c", b"prefix-BBBB"),
+            ("d", b"other-XXXXX"),
+            ("e", b"tiny"),
+            ("f", b"tiny"),
+            ("g", b"a size of its own"),
+        ] {
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+        let roots = [dir.path().to_path_buf()];
+        let groups = |result: &DedupResult| {
+            let mut groups: Vec<Vec<PathBuf>> = result.duplicates.values()
+                .map(|files| {
+                    let mut paths: Vec<PathBuf> = files.iter().map(|file| file.path.clone()).collect();
+                    paths.sort();
+                    paths
+                })
+                .collect();
+            groups.sort();
+            groups
+        };
+
+        let mut tiered = Scanner::new();
+        tiered.set_partial_hash_bytes(4);
+        let tiered_result = tiered.scan_directories(&roots).unwrap();
+        let mut naive = Scanner::new();
+        naive.set_size_prefilter(false);
+        let naive_result = naive.scan_directories(&roots).unwrap();
+
+        assert_eq!(groups(&tiered_result), groups(&naive_result));
+        assert_eq!(groups(&tiered_result).len(), 2);
+        assert_eq!(tiered_result.total_files, naive_result.total_files);
+        assert_eq!(tiered_result.total_size, naive_result.total_size);
+
+        // "d" differs in its first bytes and "g" in size, so neither is read in full
+        assert_eq!(tiered.profile().counts.files_after_partial_hash, 5);
+        assert_eq!(tiered.profile().counts.files_hashed, 5);
+        assert_eq!(naive.profile().counts.files_hashed, 7);
+    }
+
+    #[test]
+    fn test_incremental_scan_rehashes_resized_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, content) in [("a", &b"same"[..]), ("b", b"same"), ("c", b"diff"), ("d", b"diff!")] {
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+        let roots = [dir.path().to_path_buf()];
+        let group_of = |result: &DedupResult, name: &str| -> Vec<PathBuf> {
+            let mut paths: Vec<PathBuf> = result.duplicates.values()
+                .find(|files| files.iter().any(|file| file.path.ends_with(name)))
+                .map(|files| files.iter().map(|file| file.path.clone()).collect())
+                .unwrap_or_default();
+            paths.sort();
+            paths
+        };
+        let freeze = |name: &str, content: &[u8]| {
+            let path = dir.path().join(name);
+            let mtime = filetime::FileTime::from_last_modification_time(&fs::metadata(&path).unwrap());
+            fs::write(&path, content).unwrap();
+            filetime::set_file_mtime(&path, mtime).unwrap();
+        };
+
+        let scanner = Scanner::new();
+        let mut cache = ScanCache::new();
+        scanner.scan_directories_incremental(&roots, &mut cache).unwrap();
+        assert_eq!(cache.len(), 3);
+
+        // Same size and time: the stale cached hash is trusted
+        freeze("c", b"same");
+        let result = scanner.scan_directories_incremental(&roots, &mut cache).unwrap();
+        assert_eq!(group_of(&result, "c"), Vec::<PathBuf>::new());
+
+        // A new size means new content, whatever the time says
+        freeze("c", b"diff!");
+        let result = scanner.scan_directories_incremental(&roots, &mut cache).unwrap();
+        assert_eq!(group_of(&result, "c"), [dir.path().join("c"), dir.path().join("d")]);
+        assert_eq!(cache.len(), 4);
+
+        fs::remove_file(dir.path().join("a")).unwrap();
+        assert_eq!(cache.evict_missing(), 1);
+
+        let mut normalizing = Scanner::new();
+        normalizing.set_normalize_text(true);
+        assert!(normalizing.scan_directories_incremental(&roots, &mut cache).is_err());
+    }
+
+    #[test]
+    fn test_keep_unique_finds_duplicates_across_trees() {
+        let (nas, usb) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        fs::write(nas.path().join("photo.jpg"), b"shared photo").unwrap();
+        fs::write(nas.path().join("notes.txt"), b"nas only").unwrap();
+        fs::write(usb.path().join("backup.jpg"), b"shared photo").unwrap();
+
+        let mut scanner = Scanner::new();
+        scanner.set_keep_unique(true);
+        let mut result = scanner.scan_directories(&[nas.path().to_path_buf()]).unwrap();
+        assert!(result.keeps_unique);
+        assert_eq!(result.duplicates.len(), 2);
+
+        result.merge(scanner.scan_directories(&[usb.path().to_path_buf()]).unwrap()).unwrap();
+        assert_eq!(result.total_files, 3);
+        assert_eq!(result.duplicates.len(), 1);
+        let roots: Vec<_> = result.duplicates.values().next().unwrap().iter().map(|file| file.source_root.clone().unwrap()).collect();
+        assert_eq!(roots, [nas.path(), usb.path()]);
+
+        // Without it, each tree's unique file is never hashed or kept
+        let plain = Scanner::new().scan_directories(&[usb.path().to_path_buf()]).unwrap();
+        assert!(plain.duplicates.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hard_links_are_not_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), b"same").unwrap();
+        fs::hard_link(dir.path().join("a"), dir.path().join("b")).unwrap();
+        fs::write(dir.path().join("c"), b"same").unwrap();
+
+        let scanner = Scanner::new();
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        assert_eq!(result.hardlinked.len(), 1);
+        assert_eq!(result.get_duplicate_count(), 1);
+        assert_eq!(result.get_wasted_space(), 4);
+
+        // Only one name of the linked file is read
+        assert_eq!(scanner.profile().counts.files_hashed, 2);
+        let mut linked = result.already_linked.clone();
+        linked.iter_mut().for_each(|names| names.sort());
+        assert_eq!(linked, [vec![dir.path().join("a"), dir.path().join("b")]]);
+        let inodes: HashSet<_> = result.duplicates.values().flatten().map(|file| file.inode.unwrap()).collect();
+        assert_eq!(inodes.len(), 2);
+
+        // Once every copy is linked, a rerun reads nothing and has nothing left to do
+        fs::remove_file(dir.path().join("c")).unwrap();
+        fs::hard_link(dir.path().join("a"), dir.path().join("c")).unwrap();
+        let scanner = Scanner::new();
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        assert_eq!(scanner.profile().counts.files_hashed, 0);
+        assert!(result.duplicates.is_empty());
+        assert_eq!((result.total_files, result.total_size), (3, 12));
+        assert_eq!(result.already_linked.len(), 1);
+        assert_eq!(result.already_linked[0].len(), 3);
+    }
+
+    #[test]
+    fn test_autosave_writes_partial_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let report = out.path().join("report.json");
+        fs::write(dir.path().join("a"), b"same").unwrap();
+        fs::write(dir.path().join("b"), b"same").unwrap();
+
+        let mut scanner = Scanner::new();
+        scanner.set_quiet(true);
+        scanner.set_autosave(report.clone(), Duration::ZERO);
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        assert_eq!(result.duplicates.len(), 1);
+
+        let saved: Report = crate::envelope::load_json(&report, "report").unwrap();
+        assert!(saved.summary.in_progress);
+        assert!(!out.path().join("report.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_hash_only_extensions_count_the_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.jpg", "b.JPG", "c.txt", "d.txt"] {
+            fs::write(dir.path().join(name), b"same").unwrap();
+        }
+        fs::write(dir.path().join("e.txt"), b"unique size").unwrap();
+
+        let mut scanner = Scanner::new();
+        scanner.set_hash_only_extensions(vec!["jpg".to_string()]);
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+
+        assert_eq!(result.duplicates.len(), 1);
+        assert_eq!(result.get_duplicate_count(), 1);
+        assert_eq!(result.total_files, 5);
+        assert_eq!(result.size_only.files, 3);
+        assert_eq!(result.size_only.bytes, 4 + 4 + 11);
+        assert_eq!(result.size_only.same_size, 2);
+    }
+
+    #[cfg(feature = "decompress")]
+    #[test]
+    fn test_decompress_groups_gzip_with_plain_copy() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let content = b"the same log lines, over and over, over and over";
+        fs::write(dir.path().join("app.log"), content).unwrap();
+        let mut encoder = GzEncoder::new(fs::File::create(dir.path().join("app.log.gz")).unwrap(), Compression::best());
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap();
+
+        let mut scanner = Scanner::new();
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        assert!(result.duplicates.is_empty());
+
+        scanner.set_decompress(true);
+        let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        assert_eq!(result.duplicates.len(), 1);
+        let group = result.duplicates.values().next().unwrap();
+        assert!(crate::dedup::is_decompressed_group(group));
+    }
+
+    #[test]
+    fn test_hash_len() {
+        let mut scanner = Scanner::new();
+        let full = scanner.hash_reader(&b"data"[..], Path::new("d"), false).unwrap().hash;
+        assert_eq!(full, blake3::hash(b"data").to_hex().as_str());
+
+        scanner.set_hash_len(8);
+        let short = scanner.hash_reader(&b"data"[..], Path::new("d"), false).unwrap().hash;
+        assert_eq!(short, full[..16]);
+
+        scanner.set_hash_len(0);
+        assert_eq!(scanner.config().validate(), Err(ConfigError::HashLen(0)));
+    }
+
+    #[test]
+    fn test_same_content_collides_under_every_algorithm() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), b"same content").unwrap();
+        fs::write(dir.path().join("b"), b"same content").unwrap();
+        fs::write(dir.path().join("c"), b"diff content").unwrap();
+
+        let mut hashes = HashSet::new();
+        for algorithm in [
+            HashAlgorithm::Blake3,
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha1,
+            HashAlgorithm::Md5,
+            HashAlgorithm::XxHash3,
+        ] {
+            let mut scanner = Scanner::new();
+            scanner.set_hash_algorithm(algorithm);
+            let result = scanner.scan_directories(&[dir.path().to_path_buf()]).unwrap();
+            assert_eq!(result.hash_algorithm, algorithm);
+            assert_eq!(result.duplicates.len(), 1, "{}", algorithm);
+
+            let group = result.duplicates.values().next().unwrap();
+            assert_eq!(group.len(), 2, "{}", algorithm);
+            assert_eq!(group[0].hash.len(), 2 * algorithm.digest_len().unwrap_or(DEFAULT_HASH_LEN).min(DEFAULT_HASH_LEN));
+            hashes.insert(group[0].hash.clone());
+        }
+        assert_eq!(hashes.len(), 5);
+    }
+
+    #[test]
+    fn test_hash_file_size_is_bytes_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        fs::write(&path, b"0123456789").unwrap();
+
+        let mut scanner = Scanner::new();
+        let (file_info, stat_size) = scanner.hash_file(&path).unwrap();
+        assert_eq!(file_info.size, 10);
+        assert_eq!(stat_size, None);
+
+        scanner.set_prefix_only(4);
+        let (file_info, stat_size) = scanner.hash_file(&path).unwrap();
+        assert_eq!(file_info.size, 10);
+        assert_eq!(stat_size, None);
+    }
+
+    #[test]
+    fn test_estimate_skips_unique_sizes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), b"1234").unwrap();
+        fs::write(dir.path().join("b"), b"abcd").unwrap();
+        fs::write(dir.path().join("c"), b"123456").unwrap();
+
+        let estimate = Scanner::new().estimate(&[dir.path().to_path_buf()]).unwrap();
+        assert_eq!(estimate, ScanEstimate {
+            total_files: 3,
+            total_bytes: 14,
+            files_to_hash: 2,
+            bytes_to_hash: 8,
+        });
+
+        let result = Scanner::new().scan_directories(&[dir.path().to_path_buf()]).unwrap();
+        assert_eq!(result.total_files, 3);
+        assert_eq!(result.total_size, 14);
+    }
+
+    #[test]
+    fn test_overlapping_roots_not_self_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let photos = dir.path().join("photos");
+        fs::create_dir(&photos).unwrap();
+        fs::write(photos.join("a.jpg"), b"img").unwrap();
+        fs::write(dir.path().join("other"), b"xyz").unwrap();
+
+        let roots = [dir.path().to_path_buf(), photos.clone()];
+        assert_eq!(overlapping_roots(&roots), vec![(photos.clone(), dir.path().to_path_buf())]);
+
+        let files = Scanner::new().collect_files(&roots).unwrap();
+        assert_eq!(files.len(), 2);
+        let result = Scanner::new().scan_directories(&roots).unwrap();
+        assert_eq!(result.get_duplicate_count(), 0);
+    }
+
+    #[test]
+    fn test_per_root_grouping() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        fs::write(first.path().join("a"), b"same").unwrap();
+        fs::write(second.path().join("a"), b"same").unwrap();
+        fs::write(second.path().join("b"), b"same").unwrap();
+        let roots = [first.path().to_path_buf(), second.path().to_path_buf()];
+
+        let result = Scanner::new().scan_directories(&roots).unwrap();
+        assert_eq!(result.duplicates.len(), 1);
+        assert_eq!(result.get_duplicate_count(), 2);
+
+        let mut scanner = Scanner::new();
+        scanner.set_per_root(true);
+        let result = scanner.scan_directories(&roots).unwrap();
+        assert_eq!(result.duplicates.len(), 1);
+        let group = result.duplicates.values().next().unwrap();
+        assert!(group.iter().all(|f| f.source_root.as_deref() == Some(second.path())));
+    }
+
+    #[test]
+    fn test_scan_rejects_invalid_config() {
+        let mut scanner = Scanner::new();
+        scanner.set_min_size(2);
+        scanner.set_max_size(1);
+        assert!(scanner.scan_directories(&[PathBuf::from(".")]).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use anyhow::{bail, Result};
+use serde::{Serialize, Deserialize};
+
+use crate::DedupResult;
+use crate::envelope::{load_json, save_json};
+
+/// One slice of a scan split across several workers, written `k/n`
+///
+/// Every worker collects the full tree, but only hashes the files whose path
+/// hash falls into its slice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl Shard {
+    /// Whether this shard is responsible for hashing `path`
+    pub fn owns(&self, path: &Path) -> bool {
+        let digest = blake3::hash(path.as_os_str().as_encoded_bytes());
+        let mut prefix = [0; 8];
+        prefix.copy_from_slice(&digest.as_bytes()[..8]);
+        u64::from_le_bytes(prefix) % self.count == self.index
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, count) = s.split_once('/')
+            .ok_or_else(|| format!("expected k/n, got '{}'", s))?;
+        let index: u64 = index.trim().parse()
+            .map_err(|_| format!("invalid shard index '{}'", index))?;
+        let count: u64 = count.trim().parse()
+            .map_err(|_| format!("invalid shard count '{}'", count))?;
+        if count == 0 {
+            return Err("shard count must be at least 1".to_string());
+        }
+        if index >= count {
+            return Err(format!("shard index {} must be less than the shard count {}", index, count));
+        }
+        Ok(Self { index, count })
+    }
+}
+
+/// Partial, unfiltered scan result written by one shard worker
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShardReport {
+    pub shard: Shard,
+    pub roots: Vec<PathBuf>,
+    pub result: DedupResult,
+}
+
+impl ShardReport {
+    /// Write the report to a JSON file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_json(path, self, "shard report")
+    }
+
+    /// Read a report previously written with `save`
+    pub fn load(path: &Path) -> Result<Self> {
+        load_json(path, "shard report")
+    }
+}
+
+/// Missing shards named in the error before the rest are only counted
+const MAX_LISTED_MISSING: usize = 10;
+
+/// Combine the reports of every shard of one scan into its final result
+///
+/// Fails unless each shard `0..n` is present exactly once, since a missing
+/// shard would silently hide duplicates.
+pub fn merge_shards(reports: Vec<ShardReport>) -> Result<(Vec<PathBuf>, DedupResult)> {
+    let Some(count) = reports.first().map(|report| report.shard.count) else {
+        bail!("no shard reports to merge");
+    };
+
+    let mut seen = HashSet::new();
+    for report in &reports {
+        // Reports are read from JSON, which bypasses the checks of `FromStr`
+        if report.shard.count == 0 || report.shard.index >= report.shard.count {
+            bail!("invalid shard {} in report", report.shard);
+        }
+        if report.shard.count != count {
+            bail!("shard {} does not belong to a scan split {} ways", report.shard, count);
+        }
+        if !seen.insert(report.shard.index) {
+            bail!("shard {} was given more than once", report.shard);
+        }
+    }
+    let missing_count = count - seen.len() as u64;
+    if missing_count > 0 {
+        let missing: Vec<String> = (0..count)
+            .filter(|index| !seen.contains(index))
+            .take(MAX_LISTED_MISSING)
+            .map(|index| format!("{}/{}", index, count))
+            .collect();
+        let more = missing_count - missing.len() as u64;
+        let more = if more > 0 { format!(" and {} more", more) } else { String::new() };
+        bail!("missing shard reports: {}{}", missing.join(", "), more);
+    }
+
+    let mut reports = reports.into_iter();
+    let first = reports.next().unwrap();
+    let roots = first.roots;
+    let mut result = first.result;
+    let others: Vec<DedupResult> = reports
+        .map(|mut report| {
+            // Every shard applies the filters to the whole tree
+            report.result.skipped.clear();
+            report.result.broken_links.clear();
+            report.result
+        })
+        .collect();
+    result.merge_all(others)?;
+
+    Ok((roots, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileInfo, HashAlgorithm};
+    use std::time::UNIX_EPOCH;
+
+    fn report(index: u64, count: u64, files: &[(&str, &str)]) -> ShardReport {
+        let mut result = DedupResult::new();
+        for (path, hash) in files {
+            result.add_file(FileInfo {
+                path: PathBuf::from(path),
+                size: 1,
+                hash: hash.to_string(),
+                modified: UNIX_EPOCH,
+                detected_type: None,
+                raw_hash: None,
+                source_root: None,
+                hash_algorithm: HashAlgorithm::Blake3,
+                inode: None,
+            }).unwrap();
+        }
+        ShardReport { shard: Shard { index, count }, roots: vec![PathBuf::from("/data")], result }
+    }
+
+    #[test]
+    fn test_parse_shard() {
+        assert_eq!("1/4".parse(), Ok(Shard { index: 1, count: 4 }));
+        assert!("4/4".parse::<Shard>().is_err());
+        assert!("1/0".parse::<Shard>().is_err());
+        assert!("1".parse::<Shard>().is_err());
+    }
+
+    #[test]
+    fn test_each_path_owned_by_one_shard() {
+        for name in ["a", "b/c", "photo.jpg", "notes.txt"] {
+            let path = Path::new(name);
+            let owners = (0..3).filter(|&index| Shard { index, count: 3 }.owns(path)).count();
+            assert_eq!(owners, 1);
+        }
+    }
+
+    #[test]
+    fn test_merge_shards() {
+        let reports = vec![
+            report(0, 2, &[("/data/a", "aa"), ("/data/c", "cc")]),
+            report(1, 2, &[("/data/b", "aa")]),
+        ];
+        let (roots, result) = merge_shards(reports).unwrap();
+        assert_eq!(roots, [PathBuf::from("/data")]);
+        assert_eq!(result.total_files, 3);
+        assert_eq!(result.duplicates.len(), 1);
+        assert_eq!(result.duplicates["aa:1"].len(), 2);
+
+        let err = merge_shards(vec![report(0, 3, &[]), report(2, 3, &[])]).unwrap_err();
+        assert!(err.to_string().contains("1/3"));
+    }
+
+    #[test]
+    fn test_merge_rejects_invalid_shards() {
+        let err = merge_shards(vec![report(0, 1, &[]), report(5, 1, &[])]).unwrap_err();
+        assert!(err.to_string().contains("invalid shard 5/1"), "{}", err);
+        let err = merge_shards(vec![report(0, 0, &[])]).unwrap_err();
+        assert!(err.to_string().contains("invalid shard 0/0"), "{}", err);
+
+        // A huge count is reported without allocating for every shard
+        let err = merge_shards(vec![report(0, u64::MAX, &[])]).unwrap_err();
+        assert!(err.to_string().contains("1/18446744073709551615"), "{}", err);
+        assert!(err.to_string().ends_with(&format!(" and {} more", u64::MAX - 11)), "{}", err);
+    }
+
+    #[test]
+    fn test_merged_shards_stay_unverified() {
+        let reports = (0..2)
+            .map(|index| {
+                let mut report = report(index, 2, &[]);
+                report.result.prefix_only = Some(4);
+                report
+            })
+            .collect();
+        let (_, result) = merge_shards(reports).unwrap();
+        assert_eq!(result.prefix_only, Some(4));
+
+        let reports = (0..2)
+            .map(|index| {
+                let mut report = report(index, 2, &[]);
+                report.result.size_and_name_only = true;
+                report
+            })
+            .collect();
+        let (_, result) = merge_shards(reports).unwrap();
+        assert!(result.size_and_name_only);
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Move a file into a trash directory laid out per the freedesktop.org Trash specification
+///
+/// The file goes to `files/` under the trash directory and a `.trashinfo` entry
+/// recording its original path and deletion date goes to `info/`, so desktop
+/// environments can list and restore it. Names already taken get a `.2`, `.3`,
+/// ... suffix before the extension. The trash directory must be on the same
+/// filesystem as the file, since trashing is a rename. Returns the new path.
+pub fn trash_file(path: &Path, trash_dir: &Path) -> io::Result<PathBuf> {
+    let original = std::path::absolute(path)?;
+    let name = original.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let files_dir = trash_dir.join("files");
+    let info_dir = trash_dir.join("info");
+    create_private_dir(&files_dir)?;
+    create_private_dir(&info_dir)?;
+
+    let info = trash_info(&original, SystemTime::now());
+    let stem = Path::new(name).file_stem().unwrap_or(name).to_string_lossy().into_owned();
+    let ext = Path::new(name).extension().map(|ext| format!(".{}", ext.to_string_lossy()));
+
+    for n in 1.. {
+        let candidate = match (n, &ext) {
+            (1, _) => name.to_string_lossy().into_owned(),
+            (_, Some(ext)) => format!("{}.{}{}", stem, n, ext),
+            (_, None) => format!("{}.{}", stem, n),
+        };
+        let info_path = info_dir.join(format!("{}.trashinfo", candidate));
+        // Creating the info file exclusively reserves the name, as the spec requires
+        let mut info_file = match fs::OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        let target = files_dir.join(&candidate);
+        if target.symlink_metadata().is_ok() {
+            let _ = fs::remove_file(&info_path);
+            continue;
+        }
+
+        let moved = info_file.write_all(info.as_bytes())
+            .and_then(|_| info_file.sync_all())
+            .and_then(|_| fs::rename(&original, &target));
+        return match moved {
+            Ok(()) => Ok(target),
+            Err(e) => {
+                let _ = fs::remove_file(&info_path);
+                Err(e)
+            }
+        };
+    }
+    unreachable!("the name search only ends by returning")
+}
+
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+/// Contents of a `.trashinfo` file
+fn trash_info(original: &Path, deleted: SystemTime) -> String {
+    format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(original.as_os_str().as_encoded_bytes()),
+        local_datetime(deleted)
+    )
+}
+
+/// Percent-encode a path as the spec's `Path` key expects, keeping `/` and unreserved characters
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// `YYYY-MM-DDThh:mm:ss` in local time (UTC where the platform offers no time zone lookup)
+fn local_datetime(time: SystemTime) -> String {
+    let secs = time.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+
+    #[cfg(unix)]
+    {
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        let t = secs as libc::time_t;
+        if !unsafe { libc::localtime_r(&t, &mut tm) }.is_null() {
+            return format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec);
+        }
+    }
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trash_layout_and_collisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let trash = dir.path().join(".Trash-1000");
+        let file = dir.path().join("my report.txt");
+
+        fs::write(&file, b"first").unwrap();
+        let first = trash_file(&file, &trash).unwrap();
+        fs::write(&file, b"second").unwrap();
+        let second = trash_file(&file, &trash).unwrap();
+
+        assert_eq!(first, trash.join("files/my report.txt"));
+        assert_eq!(second, trash.join("files/my report.2.txt"));
+        assert!(!file.exists());
+        assert_eq!(fs::read(&second).unwrap(), b"second");
+
+        let info = fs::read_to_string(trash.join("info/my report.2.txt.trashinfo")).unwrap();
+        let lines: Vec<&str> = info.lines().collect();
+        assert_eq!(lines[0], "[Trash Info]");
+        assert!(lines[1].starts_with("Path=/") && lines[1].ends_with("/my%20report.txt"), "{}", lines[1]);
+        assert!(lines[2].starts_with("DeletionDate=") && lines[2].len() == "DeletionDate=".len() + 19);
+    }
+}
@@ -0,0 +1,315 @@
+use std::io;
+use std::time::Duration;
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use humansize::{format_size, DECIMAL};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, TableState};
+use ratatui::{Frame, Terminal};
+
+use crate::{is_empty_group, DedupAction, DedupResult, FileInfo, KeepStrategy};
+use crate::actions::{action_name, select_keeper};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Groups,
+    Files,
+}
+
+/// Review state: the groups, which members to keep, and the cursor
+struct App {
+    groups: Vec<(String, Vec<FileInfo>)>,
+    keep: Vec<Vec<bool>>,
+    group_state: TableState,
+    file_state: TableState,
+    focus: Focus,
+    action: &'static str,
+    status: String,
+}
+
+impl App {
+    fn new(result: &DedupResult, strategies: &[KeepStrategy], action: &DedupAction) -> Self {
+        let mut groups: Vec<(String, Vec<FileInfo>)> = result.duplicates
+            .iter()
+            .filter(|(_, files)| files.len() > 1 && !is_empty_group(files))
+            .map(|(key, files)| (key.clone(), files.clone()))
+            .collect();
+        groups.sort_by(|a, b| {
+            let waste = |files: &[FileInfo]| files[0].size * (files.len() as u64 - 1);
+            waste(&b.1).cmp(&waste(&a.1)).then_with(|| a.0.cmp(&b.0))
+        });
+
+        let keep = groups.iter()
+            .map(|(_, files)| {
+                let keeper = select_keeper(files, strategies);
+                (0..files.len()).map(|i| i == keeper).collect()
+            })
+            .collect();
+
+        let mut group_state = TableState::default();
+        group_state.select((!groups.is_empty()).then_some(0));
+
+        Self {
+            groups,
+            keep,
+            group_state,
+            file_state: TableState::default().with_selected(Some(0)),
+            focus: Focus::Groups,
+            action: action_name(action),
+            status: String::new(),
+        }
+    }
+
+    fn group(&self) -> usize {
+        self.group_state.selected().unwrap_or(0)
+    }
+
+    fn move_cursor(&mut self, delta: isize) {
+        let (state, len) = match self.focus {
+            Focus::Groups => (&mut self.group_state, self.groups.len()),
+            Focus::Files => {
+                let len = self.groups.get(self.group_state.selected().unwrap_or(0)).map_or(0, |(_, files)| files.len());
+                (&mut self.file_state, len)
+            }
+        };
+        if len == 0 {
+            return;
+        }
+        let current = state.selected().unwrap_or(0) as isize;
+        state.select(Some((current + delta).clamp(0, len as isize - 1) as usize));
+        if self.focus == Focus::Groups {
+            self.file_state.select(Some(0));
+        }
+    }
+
+    /// Flip the selected file between keep and act; every group keeps at least one file
+    fn toggle(&mut self) {
+        let group = self.group();
+        let Some(file) = self.file_state.selected() else { return };
+        let Some(marks) = self.keep.get_mut(group) else { return };
+        if marks[file] && marks.iter().filter(|keep| **keep).count() == 1 {
+            self.status = "Every group must keep at least one file".to_string();
+            return;
+        }
+        marks[file] = !marks[file];
+        self.status.clear();
+    }
+
+    /// Files marked to act on, and the bytes that would free
+    fn marked(&self) -> (usize, u64) {
+        self.groups.iter().zip(&self.keep).fold((0, 0), |(count, bytes), ((_, files), marks)| {
+            let acted = marks.iter().filter(|keep| !**keep).count();
+            (count + acted, bytes + acted as u64 * files[0].size)
+        })
+    }
+
+    /// Groups to act on, each with its first kept file leading and the files kept
+    /// beside it left out
+    fn plan(&self, result: &DedupResult) -> DedupResult {
+        let mut planned = DedupResult {
+            duplicates: Default::default(),
+            total_files: result.total_files,
+            total_size: result.total_size,
+            group_by_size: result.group_by_size,
+            require_same_name: result.require_same_name,
+            per_root: result.per_root,
+            timed_out: result.timed_out.clone(),
+            already_shared: result.already_shared.clone(),
+            hardlinked: result.hardlinked.clone(),
+            already_linked: result.already_linked.clone(),
+            skipped: result.skipped.clone(),
+            prefix_only: result.prefix_only,
+            broken_links: result.broken_links.clone(),
+            size_only: result.size_only.clone(),
+            runtime_exceeded: result.runtime_exceeded,
+            size_and_name_only: result.size_and_name_only,
+            size_changed: result.size_changed.clone(),
+            hash_algorithm: result.hash_algorithm,
+            keeps_unique: result.keeps_unique,
+        };
+        for ((key, files), marks) in self.groups.iter().zip(&self.keep) {
+            let Some(keeper) = marks.iter().position(|keep| *keep) else { continue };
+            let mut group = vec![files[keeper].clone()];
+            group.extend(files.iter().zip(marks).filter(|(_, keep)| !**keep).map(|(file, _)| file.clone()));
+            if group.len() > 1 {
+                planned.duplicates.insert(key.clone(), group);
+            }
+        }
+        planned
+    }
+}
+
+/// Let the user review duplicate groups in a terminal table and choose which files to keep
+///
+/// Returns the groups to act on, with each group's keeper first, or `None` if the
+/// user quit without applying.
+pub fn review(result: &DedupResult, strategies: &[KeepStrategy], action: &DedupAction) -> Result<Option<DedupResult>> {
+    let mut app = App::new(result, strategies, action);
+
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let outcome = event_loop(&mut terminal, &mut app);
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+
+    Ok(outcome?.then(|| app.plan(result)))
+}
+
+/// Run until the user applies (`true`) or quits (`false`)
+fn event_loop(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App) -> Result<bool> {
+    loop {
+        terminal.draw(|frame| draw(frame, app))?;
+
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else { continue };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            // Raw mode swallows the interrupt signal
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(false),
+            KeyCode::Up | KeyCode::Char('k') => app.move_cursor(-1),
+            KeyCode::Down | KeyCode::Char('j') => app.move_cursor(1),
+            KeyCode::PageUp => app.move_cursor(-10),
+            KeyCode::PageDown => app.move_cursor(10),
+            KeyCode::Tab | KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') if app.focus == Focus::Groups => {
+                app.focus = Focus::Files;
+            }
+            KeyCode::Tab | KeyCode::Left | KeyCode::Char('h') if app.focus == Focus::Files => {
+                app.focus = Focus::Groups;
+            }
+            KeyCode::Char(' ') if app.focus == Focus::Files => app.toggle(),
+            KeyCode::Char('a') => {
+                if app.action == "list" {
+                    app.status = "No action selected; rerun with --action to apply changes".to_string();
+                } else {
+                    return Ok(true);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Percentage(45),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .split(frame.size());
+
+    let (marked, marked_bytes) = app.marked();
+    let wasted: u64 = app.groups.iter().map(|(_, files)| files[0].size * (files.len() as u64 - 1)).sum();
+    let summary = format!(
+        "{} groups, {} wasted | {} files marked to {}, freeing {}",
+        app.groups.len(), format_size(wasted, DECIMAL), marked, app.action, format_size(marked_bytes, DECIMAL)
+    );
+    frame.render_widget(
+        Paragraph::new(summary).block(Block::default().borders(Borders::ALL).title(" Duplicate review ")),
+        areas[0],
+    );
+
+    let focused = |focus: Focus| {
+        let style = Style::default().add_modifier(Modifier::REVERSED);
+        if app.focus == focus { style.fg(Color::Cyan) } else { style }
+    };
+
+    let group_rows: Vec<Row> = app.groups.iter().zip(&app.keep).enumerate()
+        .map(|(i, ((_, files), marks))| {
+            let acted = marks.iter().filter(|keep| !**keep).count();
+            Row::new(vec![
+                format!("{}", i + 1),
+                format!("{}", files.len()),
+                format_size(files[0].size, DECIMAL),
+                format!("{}", acted),
+                files[0].path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+            ])
+        })
+        .collect();
+    let groups = Table::new(group_rows, [
+        Constraint::Length(6),
+        Constraint::Length(6),
+        Constraint::Length(10),
+        Constraint::Length(7),
+        Constraint::Min(10),
+    ])
+        .header(Row::new(vec!["#", "Files", "Size", "Marked", "Name"]).style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(Block::default().borders(Borders::ALL).title(" Groups "))
+        .highlight_style(focused(Focus::Groups));
+    frame.render_stateful_widget(groups, areas[1], &mut app.group_state);
+
+    let group = app.group();
+    let file_rows: Vec<Row> = match app.groups.get(group) {
+        Some((_, files)) => files.iter().zip(&app.keep[group])
+            .map(|(file, keep)| {
+                let mark = if *keep { "keep".to_string() } else { app.action.to_string() };
+                let style = if *keep { Style::default().fg(Color::Green) } else { Style::default().fg(Color::Red) };
+                Row::new(vec![mark, file.path.display().to_string()]).style(style)
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    let files = Table::new(file_rows, [Constraint::Length(15), Constraint::Min(10)])
+        .block(Block::default().borders(Borders::ALL).title(" Files "))
+        .highlight_style(focused(Focus::Files));
+    frame.render_stateful_widget(files, areas[2], &mut app.file_state);
+
+    let help = if app.status.is_empty() {
+        "↑/↓ move  Tab switch pane  Space keep/act  a apply  q quit".to_string()
+    } else {
+        app.status.clone()
+    };
+    frame.render_widget(Paragraph::new(help).style(Style::default().fg(Color::Yellow)), areas[3]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn file(path: &str) -> FileInfo {
+        FileInfo::fixture(path, 10, "h")
+    }
+
+    #[test]
+    fn test_toggles_shape_the_plan() {
+        let mut result = DedupResult::new();
+        result.duplicates.insert("h".to_string(), vec![file("/a"), file("/b"), file("/c")]);
+
+        let mut app = App::new(&result, &[], &DedupAction::Delete);
+        assert_eq!(app.marked(), (2, 20));
+
+        // The last kept file can't be released
+        app.focus = Focus::Files;
+        app.toggle();
+        assert_eq!(app.keep[0], vec![true, false, false]);
+
+        // Keep /c as well, then hand the keeper role to /c
+        app.move_cursor(2);
+        app.toggle();
+        app.move_cursor(-2);
+        app.toggle();
+        assert_eq!(app.keep[0], vec![false, false, true]);
+
+        let plan = app.plan(&result);
+        let paths: Vec<_> = plan.duplicates["h"].iter().map(|f| f.path.clone()).collect();
+        assert_eq!(paths, vec![PathBuf::from("/c"), PathBuf::from("/a"), PathBuf::from("/b")]);
+    }
+}
@@ -1,14 +1,104 @@
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::fs;
 use std::time::SystemTime;
 use anyhow::{Result, Context};
 use humansize::{format_size, DECIMAL};
+use sha2::Digest;
+
+use crate::HashAlgorithm;
 
 /// Format file size in human-readable format
 pub fn format_file_size(size: u64) -> String {
     format_size(size, DECIMAL)
 }
 
+/// Default number of output bytes in a content hash
+pub const DEFAULT_HASH_LEN: usize = 32;
+
+/// Largest supported hash length in bytes
+pub const MAX_HASH_LEN: usize = 64;
+
+/// Hash lengths below this many bytes risk collisions on large trees
+pub const MIN_SAFE_HASH_LEN: usize = 16;
+
+/// Calculate the hash of a file's contents, `len` bytes long
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm, len: usize) -> Result<String> {
+    let mut file = fs::File::open(path)
+        .with_context(|| format!("Failed to open file {}", path.display()))?;
+    let mut hasher = ContentHasher::new(algorithm);
+    std::io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Failed to read file {}", path.display()))?;
+    Ok(hasher.finish(len))
+}
+
+/// Leading `chars` characters of a hex hash, or all of it when shorter
+pub fn short_hash(hash: &str, chars: usize) -> &str {
+    &hash[..hash.len().min(chars)]
+}
+
+/// Incremental hasher for any supported hash algorithm
+#[derive(Clone)]
+pub enum ContentHasher {
+    Blake3(Box<blake3::Hasher>),
+    Sha256(sha2::Sha256),
+    Sha1(sha1::Sha1),
+    Md5(md5::Md5),
+    XxHash3(Box<xxhash_rust::xxh3::Xxh3>),
+}
+
+impl ContentHasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Blake3 => ContentHasher::Blake3(Box::default()),
+            HashAlgorithm::Sha256 => ContentHasher::Sha256(sha2::Sha256::new()),
+            HashAlgorithm::Sha1 => ContentHasher::Sha1(sha1::Sha1::new()),
+            HashAlgorithm::Md5 => ContentHasher::Md5(md5::Md5::new()),
+            HashAlgorithm::XxHash3 => ContentHasher::XxHash3(Box::default()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            ContentHasher::Blake3(hasher) => { hasher.update(data); }
+            ContentHasher::Sha256(hasher) => hasher.update(data),
+            ContentHasher::Sha1(hasher) => hasher.update(data),
+            ContentHasher::Md5(hasher) => hasher.update(data),
+            ContentHasher::XxHash3(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Hex-encode `len` bytes of the hash
+    ///
+    /// BLAKE3 has extendable output: shorter outputs are prefixes of longer ones,
+    /// and 32 bytes matches `finalize`. Fixed-size digests are cut to `len` bytes
+    /// and can't be made longer than they are.
+    pub fn finish(self, len: usize) -> String {
+        let output: Vec<u8> = match self {
+            ContentHasher::Blake3(hasher) => {
+                let mut output = vec![0; len];
+                hasher.finalize_xof().fill(&mut output);
+                output
+            }
+            ContentHasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            ContentHasher::Sha1(hasher) => hasher.finalize().to_vec(),
+            ContentHasher::Md5(hasher) => hasher.finalize().to_vec(),
+            ContentHasher::XxHash3(hasher) => hasher.digest128().to_be_bytes().to_vec(),
+        };
+        output.iter().take(len).map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+impl std::io::Write for ContentHasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 /// Check if a path is safe to operate on (basic safety checks)
 pub fn is_safe_path(path: &Path) -> bool {
     // Don't operate on system directories
@@ -67,14 +157,135 @@ pub fn generate_unique_filename(dir: &Path, original_name: &str) -> PathBuf {
     path
 }
 
+/// Token that can appear in a file name template
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplateToken {
+    Stem,
+    Ext,
+    Hash(Option<usize>),
+    Counter,
+    Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    Token(TemplateToken),
+}
+
+/// File name template such as `{stem}_{hash8}{ext}`
+///
+/// Supported tokens: `{stem}`, `{ext}` (including the dot, empty when there
+/// is none), `{hash}`, `{hashN}` (first N hex characters), `{counter}` and
+/// `{timestamp}` (seconds since the Unix epoch).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl NameTemplate {
+    /// Parse a template, rejecting unknown tokens and path separators
+    pub fn parse(template: &str) -> Result<Self> {
+        if template.contains('/') || template.contains('\\') {
+            anyhow::bail!("template '{}' must not contain path separators", template);
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(TemplatePart::Literal(rest[..open].to_string()));
+            }
+            let close = rest[open..].find('}')
+                .with_context(|| format!("unclosed '{{' in template '{}'", template))?;
+            let name = &rest[open + 1..open + close];
+            let token = match name {
+                "stem" => TemplateToken::Stem,
+                "ext" => TemplateToken::Ext,
+                "hash" => TemplateToken::Hash(None),
+                "counter" => TemplateToken::Counter,
+                "timestamp" => TemplateToken::Timestamp,
+                _ => match name.strip_prefix("hash").and_then(|n| n.parse().ok()) {
+                    Some(len) if len > 0 => TemplateToken::Hash(Some(len)),
+                    _ => anyhow::bail!("unknown token '{{{}}}' in template '{}'", name, template),
+                },
+            };
+            parts.push(TemplatePart::Token(token));
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Literal(rest.to_string()));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// Whether the template uses `{counter}` to avoid collisions itself
+    pub fn has_counter(&self) -> bool {
+        self.parts.contains(&TemplatePart::Token(TemplateToken::Counter))
+    }
+
+    /// Expand the template for a file name
+    pub fn expand(&self, original_name: &str, hash: &str, counter: usize) -> String {
+        let (stem, ext) = split_filename(original_name);
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(text) => name.push_str(text),
+                TemplatePart::Token(TemplateToken::Stem) => name.push_str(&stem),
+                TemplatePart::Token(TemplateToken::Ext) => {
+                    if !ext.is_empty() {
+                        name.push('.');
+                        name.push_str(&ext);
+                    }
+                }
+                TemplatePart::Token(TemplateToken::Hash(len)) => {
+                    let len = len.unwrap_or(hash.len()).min(hash.len());
+                    name.push_str(&hash[..len]);
+                }
+                TemplatePart::Token(TemplateToken::Counter) => name.push_str(&counter.to_string()),
+                TemplatePart::Token(TemplateToken::Timestamp) => {
+                    let secs = SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0);
+                    name.push_str(&secs.to_string());
+                }
+            }
+        }
+        name
+    }
+
+    /// Build a path in `dir` that doesn't exist yet, using `{counter}` when
+    /// present and falling back to `_N` suffixes otherwise
+    pub fn unique_path(&self, dir: &Path, original_name: &str, hash: &str) -> PathBuf {
+        if self.has_counter() {
+            let mut counter = 1;
+            loop {
+                let path = dir.join(self.expand(original_name, hash, counter));
+                if !path.exists() {
+                    return path;
+                }
+                counter += 1;
+            }
+        }
+
+        generate_unique_filename(dir, &self.expand(original_name, hash, 0))
+    }
+}
+
 /// Split filename into stem and extension
-fn split_filename(filename: &str) -> (String, String) {
-    if let Some(dot_pos) = filename.rfind('.') {
-        let stem = filename[..dot_pos].to_string();
-        let ext = filename[dot_pos + 1..].to_string();
-        (stem, ext)
-    } else {
-        (filename.to_string(), String::new())
+///
+/// A leading dot marks a hidden file rather than an extension, so `.bashrc`
+/// is all stem.
+pub(crate) fn split_filename(filename: &str) -> (String, String) {
+    match filename.rfind('.') {
+        Some(dot_pos) if dot_pos > 0 => {
+            let stem = filename[..dot_pos].to_string();
+            let ext = filename[dot_pos + 1..].to_string();
+            (stem, ext)
+        }
+        _ => (filename.to_string(), String::new()),
     }
 }
 
@@ -99,6 +310,326 @@ pub fn are_same_file(path1: &Path, path2: &Path) -> Result<bool> {
     }
 }
 
+/// Canonical form of a path that may not exist yet
+///
+/// The deepest existing ancestor is canonicalized and the missing components
+/// are appended unchanged.
+pub fn canonicalize_missing(path: &Path) -> PathBuf {
+    let mut missing = Vec::new();
+    let mut current = path;
+    loop {
+        if let Ok(canonical) = fs::canonicalize(current) {
+            return missing.iter().rev().fold(canonical, |acc, part| acc.join(part));
+        }
+        match (current.parent(), current.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_os_string());
+                current = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+/// The first scan root that contains `target`, comparing canonical paths
+pub fn root_containing(target: &Path, roots: &[PathBuf]) -> Option<PathBuf> {
+    let target = canonicalize_missing(target);
+    roots
+        .iter()
+        .find(|root| fs::canonicalize(root).is_ok_and(|root| target.starts_with(root)))
+        .cloned()
+}
+
+/// Identifier of the storage device holding a file, where the platform exposes one
+pub fn device_id(path: &Path) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fs::metadata(path).ok().map(|metadata| metadata.dev())
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Device and inode of a path itself (not following symlinks), where the platform exposes them
+pub fn file_id(path: &Path) -> Option<(u64, u64)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fs::symlink_metadata(path).ok().map(|metadata| (metadata.dev(), metadata.ino()))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Inode number from a file's metadata, where the platform exposes one
+pub fn inode(metadata: &fs::Metadata) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some(metadata.ino())
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+/// Identity of the file or directory a path resolves to, following symlinks and junctions
+///
+/// Device and inode on Unix; volume serial number and file index on Windows.
+pub fn real_file_id(path: &Path) -> Option<(u64, u64)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fs::metadata(path).ok().map(|metadata| (metadata.dev(), metadata.ino()))
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::Storage::FileSystem::{
+            GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION, FILE_FLAG_BACKUP_SEMANTICS,
+        };
+
+        // Directories only open with backup semantics; reading the IDs needs no access rights
+        let file = fs::OpenOptions::new()
+            .access_mode(0)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(path)
+            .ok()?;
+        let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+        if unsafe { GetFileInformationByHandle(file.as_raw_handle(), &mut info) } == 0 {
+            return None;
+        }
+        let index = (u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow);
+        Some((u64::from(info.dwVolumeSerialNumber), index))
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Copy timestamps, permissions and (on Unix) extended attributes from one file to another
+pub fn copy_metadata(source: &Path, target: &Path) -> std::io::Result<()> {
+    let metadata = fs::metadata(source)?;
+    fs::set_permissions(target, metadata.permissions())?;
+
+    #[cfg(unix)]
+    for name in xattr::list(source)? {
+        if let Some(value) = xattr::get(source, &name)? {
+            xattr::set(target, &name, &value)?;
+        }
+    }
+
+    // Times last, since setting permissions or attributes may touch them
+    let mut times = fs::FileTimes::new().set_modified(metadata.modified()?);
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    fs::File::options().write(true).open(target)?.set_times(times)
+}
+
+/// Compare two files byte for byte
+///
+/// Sizes are compared first, then the first and last blocks, since most files
+/// that differ at all differ there; only then is the middle streamed.
+pub fn files_identical(path1: &Path, path2: &Path) -> std::io::Result<bool> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    const BLOCK: u64 = 64 * 1024;
+
+    let mut file1 = fs::File::open(path1)?;
+    let mut file2 = fs::File::open(path2)?;
+    let len = file1.metadata()?.len();
+    if len != file2.metadata()?.len() {
+        return Ok(false);
+    }
+
+    let mut buffer1 = vec![0; BLOCK as usize];
+    let mut buffer2 = vec![0; BLOCK as usize];
+    let mut same_range = |start: u64, end: u64| -> std::io::Result<bool> {
+        file1.seek(SeekFrom::Start(start))?;
+        file2.seek(SeekFrom::Start(start))?;
+        let mut offset = start;
+        while offset < end {
+            let n = (end - offset).min(BLOCK) as usize;
+            file1.read_exact(&mut buffer1[..n])?;
+            file2.read_exact(&mut buffer2[..n])?;
+            if buffer1[..n] != buffer2[..n] {
+                return Ok(false);
+            }
+            offset += n as u64;
+        }
+        Ok(true)
+    };
+
+    let head_end = len.min(BLOCK);
+    let tail_start = len.saturating_sub(BLOCK).max(head_end);
+    Ok(same_range(0, head_end)? && same_range(tail_start, len)? && same_range(head_end, tail_start)?)
+}
+
+/// Number of NUL bytes at the end of a file, read backwards from the end
+pub fn trailing_nul_len(path: &Path) -> std::io::Result<u64> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    const BLOCK: u64 = 64 * 1024;
+
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut buffer = vec![0; BLOCK as usize];
+    let mut end = len;
+    while end > 0 {
+        let start = end.saturating_sub(BLOCK);
+        let n = (end - start) as usize;
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut buffer[..n])?;
+        if let Some(last) = buffer[..n].iter().rposition(|&byte| byte != 0) {
+            return Ok(len - start - last as u64 - 1);
+        }
+        end = start;
+    }
+    Ok(len)
+}
+
+/// Compare two files byte for byte, treating NUL bytes padding the end of the longer one as absent
+///
+/// Files of equal size must be identical; otherwise the shorter file must be a
+/// prefix of the longer one and everything after it must be NUL.
+pub fn identical_ignoring_nul_padding(path1: &Path, path2: &Path) -> std::io::Result<bool> {
+    use std::io::Read;
+
+    let (len1, len2) = (fs::metadata(path1)?.len(), fs::metadata(path2)?.len());
+    if len1 == len2 {
+        return files_identical(path1, path2);
+    }
+    let (short, long, short_len) = if len1 < len2 { (path1, path2, len1) } else { (path2, path1, len2) };
+    if trailing_nul_len(long)? < fs::metadata(long)?.len() - short_len {
+        return Ok(false);
+    }
+
+    let mut file1 = fs::File::open(short)?;
+    let mut file2 = fs::File::open(long)?.take(short_len);
+    let mut buffer1 = vec![0; 64 * 1024];
+    let mut buffer2 = vec![0; 64 * 1024];
+    let mut remaining = short_len;
+    while remaining > 0 {
+        let n = remaining.min(buffer1.len() as u64) as usize;
+        file1.read_exact(&mut buffer1[..n])?;
+        file2.read_exact(&mut buffer2[..n])?;
+        if buffer1[..n] != buffer2[..n] {
+            return Ok(false);
+        }
+        remaining -= n as u64;
+    }
+    Ok(true)
+}
+
+/// Check whether a path lives on persistent storage rather than a RAM-backed filesystem
+pub fn is_persistent_storage(path: &Path) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+            return true;
+        };
+        let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+            return true;
+        }
+        const RAMFS_MAGIC: u32 = 0x8584_58f6;
+        let fs_type = stats.f_type as u32;
+        fs_type != libc::TMPFS_MAGIC as u32 && fs_type != RAMFS_MAGIC
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = path;
+        true
+    }
+}
+
+/// Whether the filesystem holding `path` can make copy-on-write clones (reflinks)
+///
+/// On Linux this follows from the filesystem type: btrfs, XFS, bcachefs and
+/// OCFS2 can clone, though XFS only does when formatted with reflink support
+/// (the default since xfsprogs 5.1). On Windows the volume must support block
+/// cloning, as ReFS does. Other platforms can't clone.
+pub fn is_reflink_supported(path: &Path) -> Result<bool> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        const BTRFS_SUPER_MAGIC: u32 = 0x9123_683e;
+        const XFS_SUPER_MAGIC: u32 = 0x5846_5342;
+        const BCACHEFS_SUPER_MAGIC: u32 = 0xca45_1a4e;
+        const OCFS2_SUPER_MAGIC: u32 = 0x7461_636f;
+
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+            .with_context(|| format!("Invalid path {}", path.display()))?;
+        let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to get the filesystem type of {}", path.display()));
+        }
+        Ok(matches!(
+            stats.f_type as u32,
+            BTRFS_SUPER_MAGIC | XFS_SUPER_MAGIC | BCACHEFS_SUPER_MAGIC | OCFS2_SUPER_MAGIC
+        ))
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        use windows_sys::Win32::Storage::FileSystem::{GetVolumeInformationW, GetVolumePathNameW};
+
+        const FILE_SUPPORTS_BLOCK_REFCOUNTING: u32 = 0x0800_0000;
+
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut root = vec![0u16; wide.len().max(261)];
+        let mut flags = 0u32;
+        let found = unsafe {
+            GetVolumePathNameW(wide.as_ptr(), root.as_mut_ptr(), root.len() as u32) != 0
+                && GetVolumeInformationW(
+                    root.as_ptr(),
+                    std::ptr::null_mut(),
+                    0,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    &mut flags,
+                    std::ptr::null_mut(),
+                    0,
+                ) != 0
+        };
+        if !found {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to get the volume of {}", path.display()));
+        }
+        Ok(flags & FILE_SUPPORTS_BLOCK_REFCOUNTING != 0)
+    }
+
+    #[cfg(not(any(target_os = "linux", windows)))]
+    {
+        let _ = path;
+        Ok(false)
+    }
+}
+
 /// Format duration in human-readable format
 pub fn format_duration(duration: std::time::Duration) -> String {
     let secs = duration.as_secs();
@@ -111,6 +642,37 @@ pub fn format_duration(duration: std::time::Duration) -> String {
     }
 }
 
+/// Parse a duration such as `90`, `90s`, `15m`, `2h` or `1h30m`; bare numbers are seconds
+pub fn parse_duration(text: &str) -> std::result::Result<std::time::Duration, String> {
+    let text = text.trim();
+    if let Ok(secs) = text.parse::<u64>() {
+        return Ok(std::time::Duration::from_secs(secs));
+    }
+
+    let mut secs = 0u64;
+    let mut digits = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86_400,
+            _ => return Err(format!("unknown unit '{}' in duration '{}' (use s, m, h or d)", c, text)),
+        };
+        let value: u64 = digits.parse().map_err(|_| format!("missing number before '{}' in duration '{}'", c, text))?;
+        secs += value * unit;
+        digits.clear();
+    }
+    if !digits.is_empty() || text.is_empty() {
+        return Err(format!("invalid duration '{}' (e.g. 90s, 15m, 1h30m)", text));
+    }
+    Ok(std::time::Duration::from_secs(secs))
+}
+
 /// Check if a file is likely to be a system file
 pub fn is_system_file(path: &Path) -> bool {
     let filename = path.file_name().unwrap_or_default().to_string_lossy();
@@ -119,12 +681,62 @@ pub fn is_system_file(path: &Path) -> bool {
     let system_files = [
         "desktop.ini", "thumbs.db", ".ds_store", "pagefile.sys",
         "hiberfil.sys", "swapfile.sys", "bootmgr", "ntldr",
+        ".apdisk", ".volumeicon.icns", "icon\r",
     ];
     
     let filename_lower = filename.to_lowercase();
     system_files.iter().any(|&sys_file| filename_lower == sys_file)
 }
 
+/// Check if a file is hidden
+///
+/// A file is hidden when its name, or that of a directory between it and its
+/// scan root, starts with `.`; on Windows also when it or such a directory has
+/// the hidden attribute. `relative` is the path below the root, which itself is
+/// never considered.
+pub fn is_hidden(path: &Path, relative: &Path) -> bool {
+    let dotted = relative.components().any(|component| {
+        matches!(component, Component::Normal(name) if name.to_string_lossy().starts_with('.'))
+    });
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+
+        dotted || path
+            .ancestors()
+            .take(relative.components().count())
+            .any(|p| fs::metadata(p).is_ok_and(|metadata| metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0))
+    }
+
+    #[cfg(not(windows))]
+    {
+        let _ = path;
+        dotted
+    }
+}
+
+/// Check if a file is macOS metadata rather than user content
+///
+/// Covers AppleDouble companions (`._name`, which hold another file's resource
+/// fork and attributes on non-Mac filesystems), Finder files such as `.DS_Store`,
+/// and anything inside the volume bookkeeping or `__MACOSX` directories.
+pub fn is_apple_metadata(path: &Path) -> bool {
+    const METADATA_DIRS: [&str; 6] = [
+        "__MACOSX", ".fseventsd", ".Spotlight-V100", ".Trashes", ".TemporaryItems", ".DocumentRevisions-V100",
+    ];
+    const METADATA_FILES: [&str; 4] = [".DS_Store", ".apdisk", ".VolumeIcon.icns", "Icon\r"];
+
+    let filename = path.file_name().unwrap_or_default().to_string_lossy();
+    if filename.starts_with("._") || METADATA_FILES.contains(&filename.as_ref()) {
+        return true;
+    }
+    path.components().any(|component| {
+        METADATA_DIRS.iter().any(|dir| component.as_os_str() == *dir)
+    })
+}
+
 /// Calculate the percentage of one number relative to another
 pub fn calculate_percentage(part: u64, total: u64) -> f64 {
     if total == 0 {
@@ -134,6 +746,30 @@ pub fn calculate_percentage(part: u64, total: u64) -> f64 {
     }
 }
 
+/// Extensions treated as plain text when normalizing line endings
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "csv", "tsv", "log", "ini", "cfg", "conf", "toml", "yaml", "yml", "json", "xml",
+    "html", "htm", "css", "js", "ts", "py", "rs", "c", "h", "cpp", "hpp", "cs", "java", "go",
+    "rb", "php", "sh", "bat", "ps1", "sql",
+];
+
+/// Check if a file has a recognized plain-text extension
+pub fn is_text_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| TEXT_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Extensions of compressed formats whose content can be hashed decompressed
+const COMPRESSED_EXTENSIONS: &[&str] = &["gz"];
+
+/// Check if a file has a recognized compressed-file extension
+pub fn is_compressed_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| COMPRESSED_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
 /// Validate that a file extension is in the allowed list
 pub fn is_extension_allowed(path: &Path, allowed: &[String]) -> bool {
     if allowed.is_empty() {
@@ -148,11 +784,212 @@ pub fn is_extension_allowed(path: &Path, allowed: &[String]) -> bool {
     }
 }
 
+/// Block size assumed when the filesystem can't be queried
+pub const DEFAULT_BLOCK_SIZE: u64 = 4096;
+
+/// Allocation block size of the filesystem holding `path`
+pub fn filesystem_block_size(path: &Path) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+            return DEFAULT_BLOCK_SIZE;
+        };
+        let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } == 0 && stats.f_frsize > 0 {
+            return stats.f_frsize as u64;
+        }
+        DEFAULT_BLOCK_SIZE
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        DEFAULT_BLOCK_SIZE
+    }
+}
+
+/// Bytes free to unprivileged users on the filesystem that holds, or would hold, `path`
+///
+/// A path that doesn't exist yet is looked up through its nearest existing ancestor.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|ancestor| ancestor.exists())?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let c_path = std::ffi::CString::new(existing.as_os_str().as_bytes()).ok()?;
+        let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } != 0 {
+            return None;
+        }
+        Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+        let wide: Vec<u16> = existing.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut available = 0u64;
+        let found = unsafe {
+            GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) != 0
+        };
+        found.then_some(available)
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = existing;
+        None
+    }
+}
+
+/// Bytes a file occupies on disk and how many names its data has
+///
+/// On Unix this is the allocated block count, so sparse and inline files count
+/// what they really use; elsewhere the size is rounded up to whole blocks.
+pub fn allocation(path: &Path, block_size: u64) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let _ = block_size;
+        Some((metadata.blocks() * 512, metadata.nlink()))
+    }
+
+    #[cfg(not(unix))]
+    {
+        Some((metadata.len().div_ceil(block_size.max(1)) * block_size.max(1), 1))
+    }
+}
+
+/// A contiguous range of a file mapped to a physical location on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extent {
+    pub logical: u64,
+    pub physical: u64,
+    pub length: u64,
+}
+
+/// Physical extents of a file that shares storage with another file
+///
+/// Uses the `FIEMAP` ioctl on Linux. Returns `None` on other platforms, when
+/// the filesystem can't report extents reliably (inline or not-yet-allocated
+/// data), and when no extent is shared, since then the file can't be a clone.
+#[cfg(target_os = "linux")]
+pub fn shared_extents(path: &Path) -> Option<Vec<Extent>> {
+    use std::os::unix::io::AsRawFd;
+
+    const FS_IOC_FIEMAP: libc::c_ulong = 0xC020_660B;
+    const FIEMAP_FLAG_SYNC: u32 = 0x1;
+    const FIEMAP_EXTENT_LAST: u32 = 0x1;
+    const FIEMAP_EXTENT_UNRELIABLE: u32 = 0x2 | 0x4 | 0x100 | 0x200; // unknown, delalloc, not aligned, inline
+    const FIEMAP_EXTENT_SHARED: u32 = 0x2000;
+    const BATCH: usize = 64;
+
+    #[repr(C)]
+    struct FiemapExtent {
+        fe_logical: u64,
+        fe_physical: u64,
+        fe_length: u64,
+        fe_reserved64: [u64; 2],
+        fe_flags: u32,
+        fe_reserved: [u32; 3],
+    }
+
+    #[repr(C)]
+    struct Fiemap {
+        fm_start: u64,
+        fm_length: u64,
+        fm_flags: u32,
+        fm_mapped_extents: u32,
+        fm_extent_count: u32,
+        fm_reserved: u32,
+        fm_extents: [FiemapExtent; BATCH],
+    }
+
+    let file = fs::File::open(path).ok()?;
+    let mut extents = Vec::new();
+    let mut any_shared = false;
+    let mut start = 0;
+
+    loop {
+        let mut map: Fiemap = unsafe { std::mem::zeroed() };
+        map.fm_start = start;
+        map.fm_length = u64::MAX - start;
+        map.fm_flags = FIEMAP_FLAG_SYNC;
+        map.fm_extent_count = BATCH as u32;
+
+        if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut map) } != 0 {
+            return None;
+        }
+        if map.fm_mapped_extents == 0 {
+            break;
+        }
+
+        let mapped = &map.fm_extents[..map.fm_mapped_extents as usize];
+        for extent in mapped {
+            if extent.fe_flags & FIEMAP_EXTENT_UNRELIABLE != 0 {
+                return None;
+            }
+            any_shared |= extent.fe_flags & FIEMAP_EXTENT_SHARED != 0;
+            extents.push(Extent {
+                logical: extent.fe_logical,
+                physical: extent.fe_physical,
+                length: extent.fe_length,
+            });
+        }
+
+        let last = &mapped[mapped.len() - 1];
+        if last.fe_flags & FIEMAP_EXTENT_LAST != 0 {
+            break;
+        }
+        start = last.fe_logical + last.fe_length;
+    }
+
+    (any_shared && !extents.is_empty()).then_some(extents)
+}
+
+/// Extent inspection is only implemented on Linux
+#[cfg(not(target_os = "linux"))]
+pub fn shared_extents(_path: &Path) -> Option<Vec<Extent>> {
+    None
+}
+
 /// Check if a file is readable
 pub fn is_readable(path: &Path) -> bool {
-    match fs::File::open(path) {
-        Ok(_) => true,
-        Err(_) => false,
+    fs::File::open(path).is_ok()
+}
+
+/// Best-effort check whether another process holds a file open in a way that blocks changes
+///
+/// On Windows this detects sharing violations; on Unix it detects advisory
+/// `flock` locks held by other processes.
+pub fn is_file_in_use(path: &Path) -> bool {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const DELETE: u32 = 0x0001_0000;
+        const ERROR_SHARING_VIOLATION: i32 = 32;
+        const ERROR_LOCK_VIOLATION: i32 = 33;
+
+        match fs::OpenOptions::new().access_mode(DELETE).share_mode(0).open(path) {
+            Ok(_) => false,
+            Err(e) => matches!(e.raw_os_error(), Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)),
+        }
+    }
+
+    #[cfg(not(windows))]
+    {
+        match fs::File::open(path) {
+            Ok(file) => matches!(file.try_lock(), Err(fs::TryLockError::WouldBlock)),
+            Err(_) => false,
+        }
     }
 }
 
@@ -163,7 +1000,7 @@ pub fn get_relative_path(from: &Path, to: &Path) -> Result<PathBuf> {
     let to_absolute = to.canonicalize()
         .with_context(|| format!("Failed to canonicalize {}", to.display()))?;
     
-    Ok(pathdiff::diff_paths(&to_absolute, &from_absolute).unwrap_or_else(|| to_absolute))
+    Ok(pathdiff::diff_paths(&to_absolute, &from_absolute).unwrap_or(to_absolute))
 }
 
 #[cfg(test)]
@@ -177,6 +1014,8 @@ mod tests {
         assert_eq!(split_filename("test.txt"), ("test".to_string(), "txt".to_string()));
         assert_eq!(split_filename("test"), ("test".to_string(), String::new()));
         assert_eq!(split_filename("test.tar.gz"), ("test.tar".to_string(), "gz".to_string()));
+        assert_eq!(split_filename(".gitignore"), (".gitignore".to_string(), String::new()));
+        assert_eq!(split_filename(".config.bak"), (".config".to_string(), "bak".to_string()));
     }
     
     #[test]
@@ -199,17 +1038,226 @@ mod tests {
         assert_eq!(unique_path2.file_name().unwrap(), "test_2.txt");
     }
     
+    #[test]
+    fn test_name_template() {
+        let template = NameTemplate::parse("{stem}_{hash8}{ext}").unwrap();
+        assert_eq!(template.expand("photo.jpg", "0123456789abcdef", 0), "photo_01234567.jpg");
+        assert_eq!(template.expand("README", "0123456789abcdef", 0), "README_01234567");
+
+        let template = NameTemplate::parse("{counter}-{stem}.dup{ext}").unwrap();
+        assert!(template.has_counter());
+        assert_eq!(template.expand(".bashrc", "ab", 3), "3-.bashrc.dup");
+
+        assert!(NameTemplate::parse("{stem}_{bogus}").is_err());
+        assert!(NameTemplate::parse("{stem").is_err());
+        assert!(NameTemplate::parse("sub/{stem}").is_err());
+        assert!(NameTemplate::parse("{hash0}").is_err());
+    }
+
+    #[test]
+    fn test_name_template_unique_path() {
+        let dir = tempdir().unwrap();
+        let template = NameTemplate::parse("{stem}-{counter}{ext}").unwrap();
+        File::create(dir.path().join("a-1.txt")).unwrap();
+        assert_eq!(template.unique_path(dir.path(), "a.txt", "ff"), dir.path().join("a-2.txt"));
+
+        let template = NameTemplate::parse("{stem}_{hash2}{ext}").unwrap();
+        File::create(dir.path().join("a_ff.txt")).unwrap();
+        assert_eq!(template.unique_path(dir.path(), "a.txt", "ff"), dir.path().join("a_ff_1.txt"));
+    }
+
     #[test]
     fn test_is_system_file() {
         assert!(is_system_file(&PathBuf::from("desktop.ini")));
         assert!(is_system_file(&PathBuf::from("THUMBS.DB")));
         assert!(!is_system_file(&PathBuf::from("my_file.txt")));
+        assert!(is_system_file(&PathBuf::from(".apdisk")));
+    }
+
+    #[test]
+    fn test_real_file_id() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("a"), b"same").unwrap();
+        fs::write(dir.path().join("c"), b"same").unwrap();
+        fs::hard_link(dir.path().join("a"), dir.path().join("b")).unwrap();
+
+        let id = |name: &str| real_file_id(&dir.path().join(name)).unwrap();
+        assert_eq!(id("a"), id("b"));
+        assert_ne!(id("a"), id("c"));
+        assert_eq!(real_file_id(dir.path()), Some(id("sub/..")));
+        assert_ne!(id("sub"), id("."));
+        assert!(real_file_id(&dir.path().join("missing")).is_none());
+    }
+
+    #[test]
+    fn test_is_hidden() {
+        let root = Path::new("/data/.backup");
+        let hidden = |relative: &str| is_hidden(&root.join(relative), Path::new(relative));
+        assert!(hidden(".git/config"));
+        assert!(hidden("photos/.thumbnails/a.jpg"));
+        assert!(hidden("photos/.DS_Store"));
+        assert!(!hidden("photos/a.jpg"));
+        assert!(!hidden("photos/a.b.jpg"));
+    }
+
+    #[test]
+    fn test_is_apple_metadata() {
+        assert!(is_apple_metadata(Path::new("photos/._IMG_0001.jpg")));
+        assert!(is_apple_metadata(Path::new("photos/.DS_Store")));
+        assert!(is_apple_metadata(Path::new("__MACOSX/photos/IMG_0001.jpg")));
+        assert!(is_apple_metadata(Path::new(".Spotlight-V100/Store-V2/store.db")));
+        assert!(!is_apple_metadata(Path::new("photos/IMG_0001.jpg")));
+        assert!(!is_apple_metadata(Path::new("photos/_draft.txt")));
     }
     
+    #[test]
+    fn test_is_file_in_use() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("locked.txt");
+        File::create(&path).unwrap();
+        assert!(!is_file_in_use(&path));
+
+        let holder = File::open(&path).unwrap();
+        holder.lock().unwrap();
+        assert!(is_file_in_use(&path));
+
+        holder.unlock().unwrap();
+        assert!(!is_file_in_use(&path));
+    }
+
+    #[test]
+    fn test_filesystem_block_size() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(filesystem_block_size(dir.path()).is_power_of_two());
+        assert_eq!(filesystem_block_size(Path::new("/no/such/dir")), DEFAULT_BLOCK_SIZE);
+    }
+
+    #[test]
+    fn test_shared_extents_plain_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain");
+        fs::write(&path, vec![7u8; 64 * 1024]).unwrap();
+        // A freshly written file shares nothing with anyone
+        assert_eq!(shared_extents(&path), None);
+    }
+
+    #[test]
+    fn test_files_identical() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        let c = dir.path().join("c");
+        let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        fs::write(&a, &data).unwrap();
+        fs::write(&b, &data).unwrap();
+        let mut changed = data.clone();
+        changed[150_000] ^= 1;
+        fs::write(&c, &changed).unwrap();
+
+        assert!(files_identical(&a, &b).unwrap());
+        assert!(!files_identical(&a, &c).unwrap());
+    }
+
+    #[test]
+    fn test_nul_padding() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("plain");
+        let padded = dir.path().join("padded");
+        let different = dir.path().join("different");
+        fs::write(&plain, b"data\0x").unwrap();
+        fs::write(&padded, [&b"data\0x"[..], &[0; 100_000]].concat()).unwrap();
+        fs::write(&different, [&b"data\0y"[..], &[0; 10]].concat()).unwrap();
+
+        assert_eq!(trailing_nul_len(&plain).unwrap(), 0);
+        assert_eq!(trailing_nul_len(&padded).unwrap(), 100_000);
+        assert!(identical_ignoring_nul_padding(&plain, &padded).unwrap());
+        assert!(identical_ignoring_nul_padding(&padded, &plain).unwrap());
+        assert!(!identical_ignoring_nul_padding(&plain, &different).unwrap());
+    }
+
+    #[test]
+    fn test_files_identical_staged_on_large_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let original = dir.path().join("original");
+        let data: Vec<u8> = (0..8 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&original, &data).unwrap();
+        let copy = dir.path().join("copy");
+        fs::write(&copy, &data).unwrap();
+        assert!(files_identical(&original, &copy).unwrap());
+
+        // Differences in the first block, last block, middle and final byte
+        for (name, offset) in [("head", 10), ("tail", data.len() - 1000), ("middle", data.len() / 2), ("last", data.len() - 1)] {
+            let mut changed = data.clone();
+            changed[offset] ^= 0xFF;
+            let path = dir.path().join(name);
+            fs::write(&path, &changed).unwrap();
+            assert!(!files_identical(&original, &path).unwrap(), "{} difference missed", name);
+        }
+
+        let empty1 = dir.path().join("empty1");
+        let empty2 = dir.path().join("empty2");
+        fs::write(&empty1, b"").unwrap();
+        fs::write(&empty2, b"").unwrap();
+        assert!(files_identical(&empty1, &empty2).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_metadata_round_trips_xattr() {
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        let target = dir.path().join("target");
+        fs::write(&source, b"data").unwrap();
+        fs::write(&target, b"data").unwrap();
+
+        if let Err(e) = xattr::set(&source, "user.dedup.test", b"kept") {
+            // Some filesystems (e.g. tmpfs without user xattrs) can't store them
+            eprintln!("skipping xattr check: {}", e);
+            return;
+        }
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options().write(true).open(&source).unwrap()
+            .set_times(fs::FileTimes::new().set_modified(mtime)).unwrap();
+
+        copy_metadata(&source, &target).unwrap();
+
+        assert_eq!(xattr::get(&target, "user.dedup.test").unwrap().as_deref(), Some(&b"kept"[..]));
+        assert_eq!(fs::metadata(&target).unwrap().modified().unwrap(), mtime);
+    }
+
     #[test]
     fn test_calculate_percentage() {
         assert_eq!(calculate_percentage(50, 100), 50.0);
         assert_eq!(calculate_percentage(0, 100), 0.0);
         assert_eq!(calculate_percentage(100, 0), 0.0);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_parse_duration() {
+        use std::time::Duration;
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("1h30").is_err());
+        assert!(parse_duration("").is_err());
+    }
+
+    #[test]
+    fn test_root_containing() {
+        let dir = tempfile::tempdir().unwrap();
+        let scanned = dir.path().join("scanned");
+        let other = dir.path().join("other");
+        fs::create_dir_all(&scanned).unwrap();
+        fs::create_dir_all(&other).unwrap();
+        let roots = [scanned.clone()];
+
+        assert_eq!(root_containing(&scanned.join("not/yet/created"), &roots), Some(scanned.clone()));
+        assert_eq!(root_containing(&other.join("../scanned/dupes"), &roots), Some(scanned.clone()));
+        assert_eq!(root_containing(&other.join("dupes"), &roots), None);
+        assert_eq!(root_containing(dir.path(), &roots), None);
+    }
+}
@@ -0,0 +1,236 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use anyhow::Result;
+use console::style;
+use dialoguer::{Confirm, Select};
+use humansize::{format_size, DECIMAL};
+
+use crate::{is_empty_group, DedupAction, DedupResult, FileInfo, KeepStrategy};
+use crate::actions::{action_name, select_keeper};
+use crate::utils::{format_duration, is_text_file, short_hash};
+
+/// Bytes of a text file shown as a preview
+const PREVIEW_BYTES: u64 = 240;
+
+/// What the user chose for one group
+enum Choice {
+    Keep(usize),
+    Skip,
+    AlwaysKeep,
+    Finish,
+    Abort,
+}
+
+/// Walk through the duplicate groups one at a time, letting the user pick each keeper
+///
+/// Each group shows its files with size and age, plus a preview for text files,
+/// then asks which file to keep and confirms the action. "Always keep files from
+/// this directory" adds a rule that settles later groups without asking. Returns
+/// the groups to act on, keeper first, or `None` if the user aborted.
+pub fn run_wizard(result: &DedupResult, strategies: &[KeepStrategy], action: &DedupAction) -> Result<Option<DedupResult>> {
+    let mut groups: Vec<(&String, &Vec<FileInfo>)> = result.duplicates
+        .iter()
+        .filter(|(_, files)| files.len() > 1 && !is_empty_group(files) && result.group_duplicate_count(files) > 0)
+        .collect();
+    groups.sort_by(|a, b| result.group_wasted_space(b.1).cmp(&result.group_wasted_space(a.1)).then_with(|| a.0.cmp(b.0)));
+
+    let verb = action_name(action);
+    let mut always_keep: Vec<PathBuf> = Vec::new();
+    let mut decisions: Vec<(String, usize)> = Vec::new();
+
+    for (number, (key, files)) in groups.iter().enumerate() {
+        println!();
+        println!("{} {} files, {} each ({})",
+            style(format!("Group {}/{}:", number + 1, groups.len())).bold(),
+            files.len(),
+            format_size(files[0].size, DECIMAL),
+            short_hash(&files[0].hash, 12)
+        );
+
+        if let Some(keeper) = ruled_keeper(files, &always_keep) {
+            println!("  📄 Keeping {} (always keep rule)", files[keeper].path.display());
+            decisions.push(((*key).clone(), keeper));
+            continue;
+        }
+
+        print_group(files);
+        let suggested = select_keeper(files, strategies);
+
+        let keeper = loop {
+            match ask(files, suggested)? {
+                Choice::Keep(keeper) => break Some(keeper),
+                Choice::Skip => break None,
+                Choice::AlwaysKeep => {
+                    let dirs = parent_dirs(files);
+                    let picked = Select::new()
+                        .with_prompt("Always keep files from")
+                        .items(&dirs.iter().map(|dir| dir.display().to_string()).collect::<Vec<_>>())
+                        .default(0)
+                        .interact()?;
+                    always_keep.push(dirs[picked].clone());
+                    if let Some(keeper) = ruled_keeper(files, &always_keep) {
+                        break Some(keeper);
+                    }
+                }
+                Choice::Finish => return Ok(Some(plan(result, &decisions))),
+                Choice::Abort => return Ok(None),
+            }
+        };
+
+        let Some(keeper) = keeper else { continue };
+        let confirmed = Confirm::new()
+            .with_prompt(format!("Keep {} and {} the other {}?", files[keeper].path.display(), verb, files.len() - 1))
+            .default(true)
+            .interact()?;
+        if confirmed {
+            decisions.push(((*key).clone(), keeper));
+        }
+    }
+
+    Ok(Some(plan(result, &decisions)))
+}
+
+fn ask(files: &[FileInfo], suggested: usize) -> Result<Choice> {
+    let mut items: Vec<String> = files.iter().map(|file| format!("Keep {}", file.path.display())).collect();
+    items.extend([
+        "Skip this group".to_string(),
+        "Always keep files from a directory...".to_string(),
+        "Stop here and apply the choices so far".to_string(),
+        "Abort without changing anything".to_string(),
+    ]);
+
+    let picked = Select::new()
+        .with_prompt("Which file should be kept?")
+        .items(&items)
+        .default(suggested)
+        .interact()?;
+
+    Ok(match picked.checked_sub(files.len()) {
+        None => Choice::Keep(picked),
+        Some(0) => Choice::Skip,
+        Some(1) => Choice::AlwaysKeep,
+        Some(2) => Choice::Finish,
+        _ => Choice::Abort,
+    })
+}
+
+fn print_group(files: &[FileInfo]) {
+    for file in files {
+        println!("  📄 {} ({}, modified {})", file.path.display(), format_size(file.size, DECIMAL), age(file.modified));
+    }
+    if let Some(preview) = files.iter().find(|file| is_text_file(&file.path)).and_then(|file| preview(&file.path)) {
+        println!("  {}", style("Preview:").dim());
+        for line in preview.lines().take(5) {
+            println!("    {}", style(line).dim());
+        }
+    }
+}
+
+/// The first member under an always-keep directory, earliest rule first
+fn ruled_keeper(files: &[FileInfo], always_keep: &[PathBuf]) -> Option<usize> {
+    always_keep.iter().find_map(|dir| files.iter().position(|file| file.path.starts_with(dir)))
+}
+
+/// Distinct parent directories of a group's files, in group order
+fn parent_dirs(files: &[FileInfo]) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for dir in files.iter().filter_map(|file| file.path.parent()) {
+        if !dirs.iter().any(|known| known == dir) {
+            dirs.push(dir.to_path_buf());
+        }
+    }
+    dirs
+}
+
+/// The start of a text file, or `None` if it can't be read as UTF-8
+///
+/// A character cut off at `PREVIEW_BYTES` is dropped rather than treated as invalid.
+fn preview(path: &Path) -> Option<String> {
+    let mut bytes = Vec::new();
+    fs::File::open(path).ok()?.take(PREVIEW_BYTES).read_to_end(&mut bytes).ok()?;
+    match std::str::from_utf8(&bytes) {
+        Ok(text) => Some(text.to_string()),
+        Err(e) if e.error_len().is_none() => Some(String::from_utf8_lossy(&bytes[..e.valid_up_to()]).into_owned()),
+        Err(_) => None,
+    }
+}
+
+fn age(modified: SystemTime) -> String {
+    match SystemTime::now().duration_since(modified) {
+        Ok(elapsed) if elapsed.as_secs() >= 86_400 => format!("{} days ago", elapsed.as_secs() / 86_400),
+        Ok(elapsed) => format!("{} ago", format_duration(elapsed)),
+        Err(_) => "in the future".to_string(),
+    }
+}
+
+/// The decided groups, each with its keeper moved to the front
+fn plan(result: &DedupResult, decisions: &[(String, usize)]) -> DedupResult {
+    let mut planned = DedupResult {
+        duplicates: Default::default(),
+        total_files: result.total_files,
+        total_size: result.total_size,
+        group_by_size: result.group_by_size,
+        require_same_name: result.require_same_name,
+        per_root: result.per_root,
+        timed_out: result.timed_out.clone(),
+        already_shared: result.already_shared.clone(),
+        hardlinked: result.hardlinked.clone(),
+        already_linked: result.already_linked.clone(),
+        skipped: result.skipped.clone(),
+        prefix_only: result.prefix_only,
+        broken_links: result.broken_links.clone(),
+        size_only: result.size_only.clone(),
+        runtime_exceeded: result.runtime_exceeded,
+        size_and_name_only: result.size_and_name_only,
+        size_changed: result.size_changed.clone(),
+        hash_algorithm: result.hash_algorithm,
+        keeps_unique: result.keeps_unique,
+    };
+    for (key, keeper) in decisions {
+        let mut files = result.duplicates[key].clone();
+        let kept = files.remove(*keeper);
+        files.insert(0, kept);
+        planned.duplicates.insert(key.clone(), files);
+    }
+    planned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str) -> FileInfo {
+        FileInfo::fixture(path, 10, "h")
+    }
+
+    #[test]
+    fn test_rules_and_plan() {
+        let files = vec![file("/inbox/a"), file("/photos/a"), file("/backup/a")];
+        assert_eq!(ruled_keeper(&files, &[]), None);
+        assert_eq!(ruled_keeper(&files, &[PathBuf::from("/elsewhere"), PathBuf::from("/backup")]), Some(2));
+        assert_eq!(parent_dirs(&files).len(), 3);
+
+        let mut result = DedupResult::new();
+        result.duplicates.insert("h".to_string(), files.clone());
+        result.duplicates.insert("g".to_string(), vec![file("/x"), file("/y")]);
+        let planned = plan(&result, &[("h".to_string(), 1)]);
+
+        assert_eq!(planned.duplicates.len(), 1);
+        let paths: Vec<_> = planned.duplicates["h"].iter().map(|f| f.path.clone()).collect();
+        assert_eq!(paths, [PathBuf::from("/photos/a"), PathBuf::from("/inbox/a"), PathBuf::from("/backup/a")]);
+    }
+
+    #[test]
+    fn test_preview_needs_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        // The last "é" is cut in half by the preview length
+        fs::write(&path, format!("a{}", "é".repeat(PREVIEW_BYTES as usize))).unwrap();
+        assert_eq!(preview(&path).unwrap().len(), PREVIEW_BYTES as usize - 1);
+
+        fs::write(&path, b"caf\xe9 latin-1").unwrap();
+        assert_eq!(preview(&path), None);
+    }
+}